@@ -1,83 +1,180 @@
-use env_logger;
-
 #[macro_use]
 extern crate log;
-use termion;
 
 use std::env;
 use std::error::Error as StdError;
 use std::fs;
 use std::io::Read;
-use std::io::{stdin, stdout, Write};
+use std::io::{self, stdin, stdout, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
 
 use termion::event::{Event, Key};
 use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 
-use hi::command_prompt::{CommandMachineEvent, CommandPrompt};
-use hi::context::Context;
+use hi::buffer::Buffer;
+use hi::command_prompt::{Command, CommandMachineEvent, CommandPrompt};
+use hi::layout::Direction;
+use hi::macros::Macros;
 use hi::screen::Screen;
+use hi::script;
+use hi::session;
 use hi::{Frame, State};
 
+/// Everything the main loop reacts to, gathered from several threads
+enum Input {
+    Event(io::Result<Event>),
+    Resize(Frame),
+}
+
 enum HandlerStatus {
     Continue,
     Quit,
 }
 
-struct EventHandler<'a, 'b: 'a, T: 'a>
+/// Keys which need a second keypress to complete
+#[derive(Copy, Clone)]
+enum Pending {
+    SetMark,
+    JumpToMark,
+    Window,
+    Record,
+    /// Replay a macro, this many times
+    Replay(usize),
+}
+
+struct EventHandler<'a, T: 'a>
 where
     T: Write,
 {
     prompt: CommandPrompt,
-    screen: &'a mut Screen<'b, T>,
+    screen: &'a mut Screen<T>,
+    pending: Option<Pending>,
+    /// Where files opened with commands have their sessions restored from, `None` with `--fresh`
+    sessions: Option<PathBuf>,
 }
 
-impl<'a, 'b: 'a, T: 'a> EventHandler<'a, 'b, T>
+impl<'a, T: 'a> EventHandler<'a, T>
 where
     T: Write,
 {
-    fn new(screen: &'a mut Screen<'b, T>) -> Self {
+    fn new(screen: &'a mut Screen<T>, sessions: Option<PathBuf>) -> Self {
         let prompt = CommandPrompt::new();
-        Self { prompt, screen }
+        Self {
+            prompt,
+            screen,
+            pending: None,
+            sessions,
+        }
     }
 
-    fn call(
-        &mut self,
-        context: &Context,
-        event: termion::event::Event,
-    ) -> Result<HandlerStatus, Box<dyn StdError>> {
+    fn call(&mut self, event: termion::event::Event) -> Result<HandlerStatus, Box<dyn StdError>> {
+        self.screen.macros.record(&event);
         let screen = &mut self.screen;
 
+        if let Some(pending) = self.pending.take() {
+            if let Event::Key(Key::Char(c)) = event {
+                match pending {
+                    Pending::Replay(count) => return self.replay(c, count),
+                    Pending::Record if Macros::is_register(c) => screen.start_recording(c),
+                    Pending::Record => {}
+                    Pending::SetMark => screen.set_mark(c),
+                    Pending::JumpToMark => screen.jump_to_mark(c),
+                    Pending::Window => match c {
+                        'w' => screen.next_window(),
+                        'W' | 'p' => screen.previous_window(),
+                        's' => screen.split(Direction::Horizontal, None),
+                        'v' => screen.split(Direction::Vertical, None),
+                        'c' | 'q' => screen.close_window(),
+                        _ => {}
+                    },
+                }
+            }
+            self.render()?;
+            return Ok(HandlerStatus::Continue);
+        }
+
         match screen.state {
             State::Wait => match event {
-                Event::Key(Key::Char('q')) => return Ok(HandlerStatus::Quit),
-                Event::Key(Key::Char('h')) => screen.scroll_left(),
-                Event::Key(Key::Char('l')) => screen.scroll_right(),
-                Event::Key(Key::Char('j')) => screen.down(),
-                Event::Key(Key::Char('k')) => screen.up(),
-                Event::Key(Key::Char(':')) => screen.prompt(),
-                Event::Key(Key::Char('f')) => screen.toggle_text_display_mode(),
-                Event::Key(Key::Ctrl('d')) | Event::Key(Key::PageDown) => screen.page_down(),
-                Event::Key(Key::Ctrl('u')) | Event::Key(Key::PageUp) => screen.page_up(),
-                Event::Key(Key::Home) => screen.start(),
-                Event::Key(Key::End) => screen.end(),
+                Event::Key(Key::Char(c))
+                    if c.is_ascii_digit() && (c != '0' || screen.count().is_some()) =>
+                {
+                    screen.push_count(c.to_digit(10).unwrap_or(0) as usize)
+                }
+                event => {
+                    let count = screen.take_count();
+                    match event {
+                        Event::Key(Key::Char('q')) if screen.macros.recording().is_some() => {
+                            screen.stop_recording()
+                        }
+                        Event::Key(Key::Char('q')) => {
+                            // q used to quit, so say how to now
+                            screen.message("q then a register records a macro, type :q to quit");
+                            self.pending = Some(Pending::Record);
+                        }
+                        Event::Key(Key::Char('@')) => self.pending = Some(Pending::Replay(count)),
+                        Event::Key(Key::Char('h')) => screen.scroll_left(count),
+                        Event::Key(Key::Char('l')) => screen.scroll_right(count),
+                        Event::Key(Key::Char('j')) => screen.down(count),
+                        Event::Key(Key::Char('k')) => screen.up(count),
+                        Event::Key(Key::Char(':')) => screen.prompt(),
+                        Event::Key(Key::Char('f')) => screen.toggle_text_display_mode(),
+                        Event::Key(Key::Ctrl('d')) | Event::Key(Key::PageDown) => {
+                            screen.page_down(count)
+                        }
+                        Event::Key(Key::Ctrl('u')) | Event::Key(Key::PageUp) => {
+                            screen.page_up(count)
+                        }
+                        Event::Key(Key::Home) => screen.start(),
+                        Event::Key(Key::End) => screen.end(),
+                        Event::Key(Key::Char(']')) => screen.next_region(),
+                        Event::Key(Key::Char('[')) => screen.previous_region(),
+                        Event::Key(Key::Char('m')) => self.pending = Some(Pending::SetMark),
+                        Event::Key(Key::Char('\'')) => self.pending = Some(Pending::JumpToMark),
+                        Event::Key(Key::Ctrl('w')) => self.pending = Some(Pending::Window),
+                        _ => {}
+                    }
+                }
+            },
+            State::Overlay if screen.overlay_filterable() => match event {
+                Event::Key(Key::Down) | Event::Key(Key::Ctrl('n')) => screen.overlay_down(),
+                Event::Key(Key::Up) | Event::Key(Key::Ctrl('p')) => screen.overlay_up(),
+                Event::Key(Key::Char('\n')) => screen.select_overlay_item(),
+                Event::Key(Key::Esc) => {
+                    screen.close_overlay();
+                }
+                Event::Key(Key::Backspace) => screen.pop_overlay_filter(),
+                Event::Key(Key::Char(c)) => screen.push_overlay_filter(c),
+                _ => {}
+            },
+            State::Overlay => match event {
+                Event::Key(Key::Char('j')) | Event::Key(Key::Down) => screen.overlay_down(),
+                Event::Key(Key::Char('k')) | Event::Key(Key::Up) => screen.overlay_up(),
+                Event::Key(Key::Char('\n')) => screen.select_overlay_item(),
+                Event::Key(Key::Char('q')) | Event::Key(Key::Esc) => {
+                    screen.close_overlay();
+                }
                 _ => {}
             },
             State::Prompt => match event {
-                Event::Key(x) => {
-                    use hi::command_prompt::Command::{ScrollX, ScrollY, SetOffset, SetWidth};
-
-                    match self.prompt.step(x) {
-                        CommandMachineEvent::Reset | CommandMachineEvent::UnknownCommand(..) => {
-                            screen.reset_prompt()
+                Event::Key(x) => match self.prompt.step(x) {
+                    CommandMachineEvent::Reset => screen.reset_prompt(),
+                    CommandMachineEvent::UnknownCommand(text) => {
+                        screen.reset_prompt();
+                        screen.error(&format!("unknown command: {}", text));
+                    }
+                    CommandMachineEvent::Update(text) => screen.update_prompt(text),
+                    CommandMachineEvent::Execute(command) => {
+                        screen.reset_prompt();
+                        if let HandlerStatus::Quit =
+                            execute(screen, command, self.sessions.as_deref(), 0)
+                        {
+                            return Ok(HandlerStatus::Quit);
                         }
-                        CommandMachineEvent::Update(text) => screen.update_prompt(text),
-                        CommandMachineEvent::Execute(SetWidth(n)) => screen.set_width(n),
-                        CommandMachineEvent::Execute(SetOffset(n)) => screen.set_offset(n),
-                        CommandMachineEvent::Execute(ScrollX(n)) => screen.set_scroll_x(n),
-                        CommandMachineEvent::Execute(ScrollY(n)) => screen.set_scroll_y(n),
                     }
-                }
+                },
                 e => {
                     let message = format!("{:?}", e);
                     info!("{}", message);
@@ -85,36 +182,428 @@ where
             },
         }
 
-        screen.render(context)?;
+        self.render()?;
         Ok(HandlerStatus::Continue)
     }
+
+    /// Feed the events of the macro in `register` through again `count` times, `@` being the
+    /// register last replayed
+    fn replay(&mut self, register: char, count: usize) -> Result<HandlerStatus, Box<dyn StdError>> {
+        let events = match self.screen.macros.replay(register, count) {
+            Ok(events) => events,
+            Err(error) => {
+                self.screen.error(&error);
+                self.render()?;
+                return Ok(HandlerStatus::Continue);
+            }
+        };
+        let mut status = HandlerStatus::Continue;
+        for event in events {
+            status = self.call(event)?;
+            if let HandlerStatus::Quit = status {
+                break;
+            }
+        }
+        self.screen.macros.finish_replay();
+        if let HandlerStatus::Quit = status {
+            return Ok(status);
+        }
+        self.render()?;
+        Ok(HandlerStatus::Continue)
+    }
+
+    /// Draw the screen, unless replaying a macro, when it's drawn once the macro finishes
+    fn render(&mut self) -> Result<(), Box<dyn StdError>> {
+        if !self.screen.macros.replaying() {
+            self.screen.render()?;
+        }
+        Ok(())
+    }
 }
 
-fn run() -> Result<(), Box<dyn StdError>> {
-    env_logger::init()?;
-    let path = env::args().nth(1).ok_or("Usage: hi FILE")?;
-    let mut file = fs::File::open(&path)?;
+/// Nesting of scripts sourcing scripts beyond which `:source` refuses, to stop loops
+const MAX_SOURCE_DEPTH: usize = 16;
+
+/// Carry out `command`, opening files where their sessions in `sessions` left them
+///
+/// `depth` is the number of scripts being sourced.
+fn execute<T: Write>(
+    screen: &mut Screen<T>,
+    command: Command,
+    sessions: Option<&Path>,
+    depth: usize,
+) -> HandlerStatus {
+    use hi::command_prompt::Command::{
+        Bookmark, CStruct, ClearHighlight, ClearTemplate, CloseWindow, Commit, DeleteBookmark,
+        Edit, ExportBookmarks, Fill, Goto, GotoRva, Hash, ListBookmarks, ListBuffers, Macro,
+        NextBuffer, PreviousBuffer, Quit, Resize, Revert, Save, ScrollX, ScrollY, Section,
+        SetColour, SetEncoding, SetGroupSize, SetLittleEndian, SetMinimap, SetMode, SetOffset,
+        SetWidth, Source, Split, Strings, Structures, Template, ToggleColours, ToggleMinimap,
+        TogglePictures, ToggleWrap, Transform, VerticalResize, VerticalSplit,
+    };
+
+    match command {
+        Quit(force) if force || screen.confirm_quit() => return HandlerStatus::Quit,
+        Quit(_) => {}
+        Edit(path) => {
+            if let Some(buffer) = open_buffer(screen, &path, sessions) {
+                screen.open_buffer(buffer);
+            }
+        }
+        ListBuffers => screen.show_buffer_list(),
+        Strings(min_length, encoding) => screen.show_strings(min_length, encoding),
+        Structures => screen.show_structures(),
+        Bookmark(range, label) => screen.add_bookmark(range, label),
+        ListBookmarks => screen.show_bookmarks(),
+        DeleteBookmark(label) => screen.remove_bookmark(&label),
+        ExportBookmarks(path) => screen.export_bookmarks(&path),
+        Template(path, offset) => screen.apply_template(&path, offset),
+        CStruct(path, name, offset) => screen.apply_c_struct(&path, &name, offset),
+        ClearTemplate => screen.clear_template(),
+        Fill(start, length, pattern) => screen.fill(start, length, &pattern),
+        Save(path) => screen.save(path.as_deref()),
+        Transform(transform, range) => screen.transform(transform, range),
+        Commit => screen.commit(),
+        Revert => screen.revert(),
+        Hash(algorithm, range, copy) => screen.hash(algorithm, range, copy),
+        Goto(offset) => screen.goto_offset(offset),
+        GotoRva(rva) => screen.goto_rva(rva),
+        Section(name) => screen.goto_section(&name),
+        ClearHighlight => screen.clear_highlight(),
+        NextBuffer => screen.next_buffer(),
+        PreviousBuffer => screen.previous_buffer(),
+        Split(path) => split(screen, Direction::Horizontal, path, sessions),
+        VerticalSplit(path) => split(screen, Direction::Vertical, path, sessions),
+        CloseWindow => screen.close_window(),
+        Resize(n) => screen.resize_window(Direction::Horizontal, n),
+        VerticalResize(n) => screen.resize_window(Direction::Vertical, n),
+        SetWidth(n) => screen.set_width(n),
+        SetOffset(n) => screen.set_offset(n),
+        ScrollX(n) => screen.set_scroll_x(n),
+        ScrollY(n) => screen.set_scroll_y(n),
+        SetGroupSize(n) => screen.set_group_size(n),
+        SetMode(mode) => screen.set_mode(mode),
+        SetEncoding(encoding) => screen.set_encoding(encoding),
+        SetLittleEndian(little) => screen.set_little_endian(little),
+        ToggleWrap => screen.toggle_wrap(),
+        ToggleColours => screen.toggle_colours(),
+        TogglePictures => screen.toggle_pictures(),
+        ToggleMinimap => screen.toggle_minimap(),
+        SetMinimap(measure) => screen.set_minimap(measure),
+        SetColour(class, colour) => screen.set_colour(class, colour),
+        Macro(register, keys) => screen.define_macro(register, keys),
+        Source(path) => {
+            if depth >= MAX_SOURCE_DEPTH {
+                screen.error(&format!("{}: scripts nested too deeply", path));
+                return HandlerStatus::Continue;
+            }
+            let commands = fs::read_to_string(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|source| script::parse(&source).map_err(|error| error.to_string()));
+            match commands {
+                Ok(commands) => {
+                    // only failures of the script's own commands are to be blamed on their lines
+                    screen.take_error();
+                    for (line, command) in commands {
+                        let status = execute(screen, command, sessions, depth + 1);
+                        if let Some(error) = screen.take_error() {
+                            screen.error(&format!("{}: line {}: {}", path, line, error));
+                        }
+                        if let HandlerStatus::Quit = status {
+                            return HandlerStatus::Quit;
+                        }
+                    }
+                }
+                Err(error) => screen.error(&format!("{}: {}", path, error)),
+            }
+        }
+    }
+    HandlerStatus::Continue
+}
+
+/// Open the file at `path` where its session in `sessions` left it, reporting failure in the
+/// prompt bar
+fn open_buffer<T: Write>(
+    screen: &mut Screen<T>,
+    path: &str,
+    sessions: Option<&Path>,
+) -> Option<Buffer> {
+    match Buffer::open(path) {
+        Ok(mut buffer) => {
+            if let Some(dir) = sessions {
+                session::restore(dir, &mut buffer);
+            }
+            Some(buffer)
+        }
+        Err(e) => {
+            screen.reset_prompt();
+            screen.error(&format!("{}: {}", path, e));
+            None
+        }
+    }
+}
+
+fn split<T: Write>(
+    screen: &mut Screen<T>,
+    direction: Direction,
+    path: Option<String>,
+    sessions: Option<&Path>,
+) {
+    match path {
+        Some(path) => {
+            if let Some(buffer) = open_buffer(screen, &path, sessions) {
+                screen.split(direction, Some(buffer));
+            }
+        }
+        None => screen.split(direction, None),
+    }
+}
+
+const USAGE: &str = "Usage: hi [--fresh] [-c COMMAND]... FILE
+       hi --script SCRIPT [-c COMMAND]... FILE
+       hi --diff-report FILE_A FILE_B";
 
+/// What to open and run, from the command line
+struct Options {
+    path: String,
+    /// Ignore the session the file was last left in
+    fresh: bool,
+    /// Prompt commands given with `-c`, run in order once the file is open
+    commands: Vec<String>,
+    /// Script run without the terminal interface, printing what each command reports
+    script: Option<String>,
+}
+
+fn parse_options(args: &[String]) -> Option<Options> {
+    let mut path = None;
+    let mut fresh = false;
+    let mut commands = Vec::new();
+    let mut script = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fresh" => fresh = true,
+            "-c" => commands.push(args.next()?.to_owned()),
+            "--script" => script = Some(args.next()?.to_owned()),
+            _ if path.is_none() => path = Some(arg.to_owned()),
+            _ => return None,
+        }
+    }
+    Some(Options {
+        path: path?,
+        fresh,
+        commands,
+        script,
+    })
+}
+
+/// Parse the `-c` commands, and the script when there is one, reporting where any error is
+///
+/// Each command comes with where it was given, such as `-c 2` or `check.hi: line 4`, to report
+/// it failing. The config file's commands come first, unless running a script, which shouldn't
+/// depend on who runs it.
+fn startup_commands(options: &Options) -> Result<Vec<(String, Command)>, String> {
+    let mut commands = Vec::new();
+    if let (None, Some(path)) = (&options.script, script::config_path()) {
+        match fs::read_to_string(&path) {
+            Ok(source) => commands.extend(script_commands(&path.to_string_lossy(), &source)?),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(format!("{}: {}", path.display(), error)),
+        }
+    }
+    for (i, text) in options.commands.iter().enumerate() {
+        let parsed = script::parse(text)
+            .map_err(|error| format!("-c {}: unknown command: {}", i + 1, error.text))?;
+        let label = format!("-c {}", i + 1);
+        commands.extend(
+            parsed
+                .into_iter()
+                .map(|(_, command)| (label.clone(), command)),
+        );
+    }
+    if let Some(path) = &options.script {
+        let source = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        commands.extend(script_commands(path, &source)?);
+    }
+    Ok(commands)
+}
+
+/// The commands of the script at `path`, each labelled with its line
+fn script_commands(path: &str, source: &str) -> Result<Vec<(String, Command)>, String> {
+    let parsed = script::parse(source).map_err(|error| format!("{}: {}", path, error))?;
+    Ok(parsed
+        .into_iter()
+        .map(|(line, command)| (format!("{}: line {}", path, line), command))
+        .collect())
+}
+
+/// Run `commands` against `buffer` without the terminal interface
+///
+/// Whatever each command reports, and the items of anything it lists, is printed in turn.
+/// Commands which fail are reported on stderr with where they were given, and make the run fail
+/// once every command has had its turn. Sessions are neither restored nor saved so results don't
+/// depend on earlier use.
+fn run_headless(buffer: Buffer, commands: Vec<(String, Command)>) -> Result<(), Box<dyn StdError>> {
+    let frame = Frame {
+        width: 80,
+        height: 24,
+    };
+    let mut screen = Screen::new(buffer, frame, io::sink()).headless();
+    let stdout = stdout();
+    let mut out = stdout.lock();
+    let mut failures = 0;
+
+    for (label, command) in commands {
+        let status = execute(&mut screen, command, None, 0);
+        if let Some(overlay) = screen.close_overlay() {
+            writeln!(out, "{}", overlay.title)?;
+            for item in &overlay.items {
+                writeln!(out, "{}", item)?;
+            }
+        }
+        let message = screen.take_message();
+        match screen.take_error() {
+            Some(error) => {
+                failures += 1;
+                eprintln!("{}: {}", label, error);
+            }
+            None => {
+                if let Some(message) = message {
+                    writeln!(out, "{}", message)?;
+                }
+            }
+        }
+        if let HandlerStatus::Quit = status {
+            break;
+        }
+    }
+    match failures {
+        0 => Ok(()),
+        1 => Err("1 command failed".into()),
+        failures => Err(format!("{} commands failed", failures).into()),
+    }
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, Box<dyn StdError>> {
+    let mut file = fs::File::open(path)?;
     let mut bytes = Vec::new();
     file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
 
+/// Print a summary of the differences between two files
+fn diff_report(path_a: &str, path_b: &str) -> Result<(), Box<dyn StdError>> {
+    let a = read_file(path_a)?;
+    let b = read_file(path_b)?;
+
+    let hunks = hi::diff::diff(&a, &b);
+
+    let stdout = stdout();
+    let mut out = stdout.lock();
+    writeln!(out, "--- {} ({} bytes)", path_a, a.len())?;
+    writeln!(out, "+++ {} ({} bytes)", path_b, b.len())?;
+    hi::diff::write_report(&mut out, &a, &b, &hunks)?;
+    Ok(())
+}
+
+/// Handle keys and resizes until told to quit
+fn event_loop<T: Write>(
+    screen: &mut Screen<T>,
+    sessions: Option<PathBuf>,
+) -> Result<(), Box<dyn StdError>> {
     let stdin = stdin();
-    let stdout = stdout().into_raw_mode()?;
-    let (width, height) = termion::terminal_size()?;
-    let mut screen = Screen::new(&bytes, Frame { width, height }, stdout);
-    let context = Context { file_path: &path };
+    screen.render()?;
 
-    screen.render(&context)?;
+    let (tx, rx) = mpsc::channel();
+
+    let resize_tx = tx.clone();
+    hi::resize::watch(move |frame| {
+        let _ = resize_tx.send(Input::Resize(frame));
+    })?;
+
+    thread::spawn(move || {
+        for event in stdin.events() {
+            if tx.send(Input::Event(event)).is_err() {
+                break;
+            }
+        }
+    });
 
-    let mut handler = EventHandler::new(&mut screen);
-    for event in stdin.events() {
-        let event = event?;
-        match handler.call(&context, event)? {
-            HandlerStatus::Continue => {}
-            HandlerStatus::Quit => break,
+    let mut handler = EventHandler::new(screen, sessions);
+    loop {
+        // the minimap summary is built between events so large files don't block input
+        let input = if handler.screen.summarise() {
+            match rx.try_recv() {
+                Ok(input) => input,
+                Err(mpsc::TryRecvError::Empty) => {
+                    handler.screen.render()?;
+                    continue;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match rx.recv() {
+                Ok(input) => input,
+                Err(..) => break,
+            }
         };
+
+        match input {
+            Input::Event(event) => match handler.call(event?)? {
+                HandlerStatus::Continue => {}
+                HandlerStatus::Quit => break,
+            },
+            Input::Resize(frame) => {
+                handler.screen.resize(frame);
+                handler.screen.render()?;
+            }
+        }
     }
+    Ok(())
+}
 
+fn run() -> Result<(), Box<dyn StdError>> {
+    env_logger::init()?;
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let options = match args.as_slice() {
+        [flag, a, b] if flag == "--diff-report" => return diff_report(a, b),
+        args => parse_options(args).ok_or(USAGE)?,
+    };
+    let path = &options.path;
+    let commands = startup_commands(&options)?;
+    let mut buffer = Buffer::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    if options.script.is_some() {
+        return run_headless(buffer, commands);
+    }
+    // --fresh leaves saved sessions alone, neither restoring nor overwriting them
+    let sessions = session::state_dir().filter(|_| !options.fresh);
+    if let Some(dir) = &sessions {
+        session::restore(dir, &mut buffer);
+    }
+
+    let stdout = stdout().into_raw_mode()?;
+    let (width, height) = termion::terminal_size()?;
+    let mut screen = Screen::new(buffer, Frame { width, height }, stdout);
+    let mut quit = false;
+    for (_, command) in commands {
+        if let HandlerStatus::Quit = execute(&mut screen, command, sessions.as_deref(), 0) {
+            quit = true;
+            break;
+        }
+    }
+    if !quit {
+        event_loop(&mut screen, sessions.clone())?;
+    }
+
+    if let Some(dir) = sessions {
+        for buffer in screen.buffers() {
+            if let Err(error) = session::save(&dir, buffer) {
+                warn!("couldn't save the session of {}: {}", buffer.path(), error);
+            }
+        }
+    }
     screen.reset()?;
 
     Ok(())
@@ -0,0 +1,197 @@
+//! Labelled ranges of a file kept in a sidecar file beside it, so they last between sessions
+//!
+//! The sidecar holds one bookmark per line, its offset and length in hex followed by the label:
+//!
+//! ```text
+//! # bookmarks for firmware.bin
+//! 0x10 0x4 header CRC
+//! 0x200 0x0 start of the encrypted blob
+//! ```
+//!
+//! Blank lines, comments and lines which can't be read are skipped.
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+/// Suffix added to a file's path to name its sidecar
+const SIDECAR_SUFFIX: &str = ".hi-bookmarks";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bookmark {
+    pub offset: usize,
+    /// Number of bytes annotated, 0 for a position rather than a range
+    pub length: usize,
+    pub label: String,
+}
+
+impl Bookmark {
+    /// Bytes drawn in the bookmark's colour, the byte at the offset for a position
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.length.max(1)
+    }
+}
+
+/// Path of the sidecar holding the bookmarks of the file at `path`
+pub fn sidecar(path: &str) -> String {
+    format!("{}{}", path, SIDECAR_SUFFIX)
+}
+
+/// Read the bookmarks of the file at `path`, none if it has no sidecar
+pub fn load(path: &str) -> io::Result<Vec<Bookmark>> {
+    match fs::read_to_string(sidecar(path)) {
+        Ok(text) => Ok(parse(&text)),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Write `bookmarks` to the sidecar of the file at `path`, removing it when there are none
+pub fn save(path: &str, bookmarks: &[Bookmark]) -> io::Result<()> {
+    let sidecar = sidecar(path);
+    if bookmarks.is_empty() {
+        return match fs::remove_file(&sidecar) {
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        };
+    }
+
+    let name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let mut text = format!("# bookmarks for {}\n", name);
+    for bookmark in bookmarks {
+        writeln!(
+            text,
+            "{:#x} {:#x} {}",
+            bookmark.offset, bookmark.length, bookmark.label
+        )
+        .unwrap();
+    }
+    fs::write(sidecar, text)
+}
+
+fn parse(text: &str) -> Vec<Bookmark> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.starts_with('#') {
+                return None;
+            }
+            let mut parts = line.splitn(3, char::is_whitespace);
+            let offset = number(parts.next()?)?;
+            let length = number(parts.next()?)?;
+            let label = parts.next().unwrap_or("").trim().to_owned();
+            Some(Bookmark {
+                offset,
+                length,
+                label,
+            })
+        })
+        .collect()
+}
+
+fn number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// `bookmarks` of the file at `path` as a JSON document
+pub fn to_json(path: &str, bookmarks: &[Bookmark]) -> String {
+    let mut json = format!("{{\n  \"file\": {},\n  \"bookmarks\": [", json_string(path));
+    for (i, bookmark) in bookmarks.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        write!(
+            json,
+            "{}\n    {{\"offset\": {}, \"length\": {}, \"label\": {}}}",
+            separator,
+            bookmark.offset,
+            bookmark.length,
+            json_string(&bookmark.label)
+        )
+        .unwrap();
+    }
+    if !bookmarks.is_empty() {
+        json.push_str("\n  ");
+    }
+    json.push_str("]\n}\n");
+    json
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookmark(offset: usize, length: usize, label: &str) -> Bookmark {
+        Bookmark {
+            offset,
+            length,
+            label: label.to_owned(),
+        }
+    }
+
+    #[test]
+    fn bookmarks_are_kept_in_a_sidecar() {
+        let path = std::env::temp_dir().join(format!("hi-bookmark-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert_eq!(load(path).unwrap(), []);
+
+        let bookmarks = vec![bookmark(0x10, 4, "header CRC"), bookmark(0x200, 0, "blob")];
+        save(path, &bookmarks).unwrap();
+        assert!(fs::read_to_string(sidecar(path))
+            .unwrap()
+            .ends_with("\n0x10 0x4 header CRC\n0x200 0x0 blob\n"));
+        assert_eq!(load(path).unwrap(), bookmarks);
+
+        save(path, &[]).unwrap();
+        assert!(fs::metadata(sidecar(path)).is_err());
+    }
+
+    #[test]
+    fn unreadable_lines_are_skipped() {
+        assert_eq!(
+            parse("# comment\n\n0x8 2 a  b \nnonsense\n16 0x0\n0xzz 1 bad\n"),
+            [bookmark(8, 2, "a  b"), bookmark(16, 0, "")]
+        );
+        assert_eq!(bookmark(16, 0, "").range(), 16..17);
+    }
+
+    #[test]
+    fn bookmarks_are_exported_as_json() {
+        assert_eq!(
+            to_json("a.bin", &[]),
+            "{\n  \"file\": \"a.bin\",\n  \"bookmarks\": []\n}\n"
+        );
+        assert_eq!(
+            to_json(
+                "a.bin",
+                &[bookmark(1, 2, "say \"hi\"\\\u{1}"), bookmark(3, 0, "x")]
+            ),
+            "{\n  \"file\": \"a.bin\",\n  \"bookmarks\": [\n    \
+             {\"offset\": 1, \"length\": 2, \"label\": \"say \\\"hi\\\"\\\\\\u0001\"},\n    \
+             {\"offset\": 3, \"length\": 0, \"label\": \"x\"}\n  ]\n}\n"
+        );
+    }
+}
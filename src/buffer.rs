@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::ops::Range;
+
+use crate::bookmark::{self, Bookmark};
+use crate::context::Context;
+use crate::line::Format;
+use crate::magic::{self, FileType};
+use crate::minimap::Summary;
+use crate::template::Applied;
+
+/// Position and display settings of a viewport into a buffer
+#[derive(Clone, Debug, PartialEq)]
+pub struct View {
+    pub offset: usize,
+    pub scroll_y: usize,
+    pub scroll_x: usize,
+    pub bytes_per_row: usize,
+    /// Layout of bytes along each line
+    pub format: Format,
+    /// Wrap rows wider than the window onto continuation lines instead of scrolling
+    pub wrap: bool,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        View {
+            offset: 0,
+            scroll_y: 0,
+            scroll_x: 0,
+            bytes_per_row: 32,
+            format: Format::default(),
+            wrap: false,
+        }
+    }
+}
+
+/// A change made to a buffer's data since it was last saved
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    /// The command that made the change, like `fill`
+    pub name: String,
+    pub range: Range<usize>,
+    /// The bytes the change replaced, restored when it's reverted
+    original: Vec<u8>,
+}
+
+/// An open file along with the view state to restore when switching back to it
+pub struct Buffer {
+    pub context: Context,
+    pub data: Vec<u8>,
+    pub view: View,
+    /// Byte indexes saved with `m{char}`, jumped back to with `'{char}`
+    pub marks: HashMap<char, usize>,
+    /// Statistics shown in the minimap, built up gradually
+    pub summary: Summary,
+    /// Bytes of the structure last selected, drawn highlighted
+    pub highlight: Option<Range<usize>>,
+    /// Fields of the template applied with `:template`, coloured and listed in a side panel
+    pub template: Option<Applied>,
+    /// Unsaved changes, oldest first, each drawn highlighted
+    pub edits: Vec<Edit>,
+    /// A change shown in the data but not yet committed as an edit
+    pub preview: Option<Edit>,
+    /// Annotated ranges ordered by offset, kept in a sidecar beside the file
+    pub bookmarks: Vec<Bookmark>,
+}
+
+impl Buffer {
+    /// Create a buffer holding `data`, viewed with defaults suiting its detected file type
+    pub fn new(file_path: String, data: Vec<u8>) -> Self {
+        let file_type = magic::detect(&data);
+        let view = file_type.map(FileType::default_view).unwrap_or_default();
+        Buffer {
+            context: Context {
+                file_path,
+                file_type,
+            },
+            data,
+            view,
+            marks: HashMap::new(),
+            summary: Summary::default(),
+            highlight: None,
+            template: None,
+            edits: Vec::new(),
+            preview: None,
+            bookmarks: Vec::new(),
+        }
+    }
+
+    /// Read the file at `path` into a new buffer, along with its bookmarks
+    ///
+    /// A sidecar which can't be read is treated as holding no bookmarks.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let mut buffer = Buffer::new(path.to_owned(), data);
+        buffer.bookmarks = bookmark::load(path).unwrap_or_default();
+        Ok(buffer)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.context.file_path
+    }
+
+    /// Overwrite the data from `start` with `bytes`, recorded as a single edit called `name`
+    ///
+    /// The bytes must fit within the data, nothing is written to disk until the buffer is saved.
+    pub fn write(&mut self, start: usize, bytes: &[u8], name: &str) {
+        let edit = self.replace(start, bytes, name);
+        self.edits.push(edit);
+    }
+
+    /// Overwrite the data from `start` with `bytes` without committing to the change
+    ///
+    /// Any earlier preview is reverted first. The change is kept with [`Buffer::commit`].
+    pub fn set_preview(&mut self, start: usize, bytes: &[u8], name: &str) {
+        if let Some(preview) = self.preview.take() {
+            self.restore(&preview);
+        }
+        self.preview = Some(self.replace(start, bytes, name));
+    }
+
+    /// Copy of the data in `range` as it is without the preview
+    pub fn unpreviewed(&self, range: Range<usize>) -> Vec<u8> {
+        let mut bytes = self.data[range.clone()].to_vec();
+        if let Some(preview) = &self.preview {
+            for (offset, &byte) in preview.range.clone().zip(&preview.original) {
+                if range.contains(&offset) {
+                    bytes[offset - range.start] = byte;
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Keep the previewed change as an edit, returning false if there's no preview
+    pub fn commit(&mut self) -> bool {
+        match self.preview.take() {
+            Some(preview) => {
+                self.edits.push(preview);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Undo the preview, or the latest edit if there's no preview, returning what was undone
+    pub fn revert(&mut self) -> Option<Edit> {
+        let edit = self.preview.take().or_else(|| self.edits.pop())?;
+        self.restore(&edit);
+        Some(edit)
+    }
+
+    fn replace(&mut self, start: usize, bytes: &[u8], name: &str) -> Edit {
+        let range = start..start + bytes.len();
+        let original = self.data[range.clone()].to_vec();
+        self.data[range.clone()].copy_from_slice(bytes);
+        // the minimap is rebuilt from the changed data
+        self.summary = Summary::default();
+        Edit {
+            name: name.to_owned(),
+            range,
+            original,
+        }
+    }
+
+    fn restore(&mut self, edit: &Edit) {
+        self.data[edit.range.clone()].copy_from_slice(&edit.original);
+        self.summary = Summary::default();
+    }
+
+    /// Add `bookmark` in order of offset and write the bookmarks to the sidecar
+    pub fn add_bookmark(&mut self, bookmark: Bookmark) -> io::Result<()> {
+        let index = self
+            .bookmarks
+            .partition_point(|other| other.offset <= bookmark.offset);
+        self.bookmarks.insert(index, bookmark);
+        bookmark::save(self.path(), &self.bookmarks)
+    }
+
+    /// Remove the bookmarks labelled `label` and write what's left to the sidecar, returning
+    /// how many were removed
+    pub fn remove_bookmarks(&mut self, label: &str) -> io::Result<usize> {
+        let count = self.bookmarks.len();
+        self.bookmarks.retain(|bookmark| bookmark.label != label);
+        let removed = count - self.bookmarks.len();
+        if removed > 0 {
+            bookmark::save(self.path(), &self.bookmarks)?;
+        }
+        Ok(removed)
+    }
+
+    /// Whether there are changes that haven't been saved
+    pub fn modified(&self) -> bool {
+        !self.edits.is_empty() || self.preview.is_some()
+    }
+
+    /// Write the data to `path`, or back to the buffer's own file
+    ///
+    /// Edits are only forgotten when the buffer's own file is written.
+    pub fn save(&mut self, path: Option<&str>) -> io::Result<()> {
+        let own = path.is_none_or(|path| path == self.path());
+        fs::write(path.unwrap_or(&self.context.file_path), &self.data)?;
+        if own {
+            self.edits.clear();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_are_recorded_until_saved() {
+        let path = std::env::temp_dir().join(format!("hi-buffer-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut buffer = Buffer::new(path.to_owned(), vec![0; 8]);
+        buffer.write(2, b"ab", "fill");
+
+        assert_eq!(buffer.data, b"\0\0ab\0\0\0\0");
+        assert_eq!(
+            buffer.edits,
+            vec![Edit {
+                name: "fill".to_owned(),
+                range: 2..4,
+                original: vec![0, 0],
+            }]
+        );
+        assert!(buffer.modified());
+        assert!(fs::metadata(path).is_err());
+
+        buffer.save(None).unwrap();
+        assert!(!buffer.modified());
+        assert_eq!(fs::read(path).unwrap(), buffer.data);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn previews_are_committed_or_reverted() {
+        let mut buffer = Buffer::new("a".to_owned(), b"abcd".to_vec());
+        buffer.write(0, b"x", "fill");
+        buffer.set_preview(1, b"yy", "xor");
+        buffer.set_preview(2, b"zz", "add");
+        assert_eq!(buffer.data, b"xbzz");
+
+        assert_eq!(buffer.revert().unwrap().name, "add");
+        assert_eq!(buffer.data, b"xbcd");
+        assert!(!buffer.commit());
+
+        buffer.set_preview(3, b"w", "not");
+        assert!(buffer.commit());
+        assert_eq!(buffer.edits.len(), 2);
+        assert_eq!(buffer.revert().unwrap().name, "not");
+        assert_eq!(buffer.revert().unwrap().name, "fill");
+        assert_eq!(buffer.data, b"abcd");
+        assert_eq!(buffer.revert(), None);
+    }
+}
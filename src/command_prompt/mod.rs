@@ -11,6 +11,7 @@ pub enum CommandMachineEvent<'a> {
     UnknownCommand(String),
 }
 
+#[derive(Default)]
 pub struct CommandPrompt {
     pub text: String,
     pub index: usize,
@@ -24,7 +25,7 @@ impl CommandPrompt {
         }
     }
 
-    pub fn step(&mut self, key: Key) -> CommandMachineEvent {
+    pub fn step(&mut self, key: Key) -> CommandMachineEvent<'_> {
         match key {
             Key::Char('\n') => {
                 let result = match parser::parse_command(&self.text) {
@@ -1,13 +1,66 @@
 use nom;
-use nom::digit;
+use nom::{digit, rest};
+use std::convert::TryFrom;
 use std::str::FromStr;
+use termion::event::Event;
+
+use crate::encoding::Encoding;
+use crate::hash::Algorithm;
+use crate::line::{ByteClass, Mode};
+use crate::macros::{self, Macros};
+use crate::minimap::Measure;
+use crate::strings::DEFAULT_MIN_LENGTH;
+use crate::transform::Transform;
 
 #[derive(Debug, PartialEq)]
 enum CommandName {
+    Add,
+    Bookmark,
+    Bookmarks,
+    Buffers,
+    ByteSwap,
+    ClearHighlight,
+    ClearTemplate,
+    Close,
+    Colour,
+    Colours,
+    Commit,
+    CStruct,
+    DeleteBookmark,
+    Edit,
+    Encoding,
+    Endian,
+    ExportBookmarks,
+    Fill,
+    Goto,
+    Group,
+    Hash,
+    Macro,
+    Minimap,
+    Mode,
+    NextBuffer,
+    Not,
     Offset,
+    Pictures,
+    PreviousBuffer,
+    Quit,
+    Resize,
+    Revert,
+    Rotate,
+    Save,
     ScrollX,
     ScrollY,
+    Section,
+    Source,
+    Split,
+    Strings,
+    Structures,
+    Template,
+    VerticalResize,
+    VerticalSplit,
     Width,
+    Wrap,
+    Xor,
 }
 
 #[derive(Debug)]
@@ -18,21 +71,111 @@ impl FromStr for CommandName {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
+            "add" => Ok(CommandName::Add),
+            "bookmark" => Ok(CommandName::Bookmark),
+            "bookmarks" => Ok(CommandName::Bookmarks),
+            "bswap" => Ok(CommandName::ByteSwap),
+            "commit" => Ok(CommandName::Commit),
+            "ls" | "buffers" => Ok(CommandName::Buffers),
+            "delbookmark" => Ok(CommandName::DeleteBookmark),
+            "e" | "edit" => Ok(CommandName::Edit),
+            "enc" | "encoding" => Ok(CommandName::Encoding),
+            "endian" => Ok(CommandName::Endian),
+            "exportbookmarks" => Ok(CommandName::ExportBookmarks),
+            "fill" => Ok(CommandName::Fill),
+            "goto" => Ok(CommandName::Goto),
+            "group" => Ok(CommandName::Group),
+            "hash" => Ok(CommandName::Hash),
+            "macro" => Ok(CommandName::Macro),
+            "minimap" => Ok(CommandName::Minimap),
+            "mode" => Ok(CommandName::Mode),
+            "nohl" | "nohighlight" => Ok(CommandName::ClearHighlight),
+            "not" => Ok(CommandName::Not),
+            "notemplate" => Ok(CommandName::ClearTemplate),
+            "bn" | "bnext" => Ok(CommandName::NextBuffer),
+            "bp" | "bprevious" => Ok(CommandName::PreviousBuffer),
+            "clo" | "close" => Ok(CommandName::Close),
+            "colour" | "color" => Ok(CommandName::Colour),
+            "colours" | "colors" => Ok(CommandName::Colours),
+            "cstruct" => Ok(CommandName::CStruct),
+            "q" | "quit" => Ok(CommandName::Quit),
+            "res" | "resize" => Ok(CommandName::Resize),
+            "revert" => Ok(CommandName::Revert),
+            "rol" => Ok(CommandName::Rotate),
+            "save" => Ok(CommandName::Save),
+            "sp" | "split" => Ok(CommandName::Split),
+            "section" => Ok(CommandName::Section),
+            "source" => Ok(CommandName::Source),
+            "strings" => Ok(CommandName::Strings),
+            "structures" => Ok(CommandName::Structures),
+            "template" => Ok(CommandName::Template),
+            "vres" | "vresize" => Ok(CommandName::VerticalResize),
+            "vs" | "vsplit" => Ok(CommandName::VerticalSplit),
             "o" | "offset" => Ok(CommandName::Offset),
+            "pictures" => Ok(CommandName::Pictures),
             "w" | "width" => Ok(CommandName::Width),
+            "wrap" => Ok(CommandName::Wrap),
             "x" | "scrollx" => Ok(CommandName::ScrollX),
+            "xor" => Ok(CommandName::Xor),
             "y" | "scrolly" => Ok(CommandName::ScrollY),
             _ => Err(CommandParseError),
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Command {
+    /// The start and length of the range if not the current position, and the label
+    Bookmark(Option<(usize, usize)>, String),
+    ClearHighlight,
+    ClearTemplate,
+    CloseWindow,
+    Commit,
+    CStruct(String, String, Option<usize>),
+    DeleteBookmark(String),
+    ExportBookmarks(String),
+    SetColour(ByteClass, Option<u8>),
+    SetEncoding(Encoding),
+    SetGroupSize(usize),
+    SetLittleEndian(bool),
+    SetMinimap(Option<Measure>),
+    SetMode(Mode),
+    ToggleColours,
+    ToggleMinimap,
+    TogglePictures,
+    Edit(String),
+    /// Start, length and the pattern repeated over them
+    Fill(usize, usize, Vec<u8>),
+    Goto(usize),
+    GotoRva(usize),
+    /// The algorithm, the start and length of the range if not everything, and whether to copy
+    Hash(Algorithm, Option<(usize, usize)>, bool),
+    ListBookmarks,
+    ListBuffers,
+    /// Define the macro in a register, or show it when no keys are given
+    Macro(char, Option<Vec<Event>>),
+    NextBuffer,
+    PreviousBuffer,
+    /// Quit, even with unsaved changes when true
+    Quit(bool),
+    Resize(usize),
+    Revert,
+    Save(Option<String>),
     ScrollX(usize),
     ScrollY(usize),
     SetOffset(usize),
     SetWidth(usize),
+    Split(Option<String>),
+    Section(String),
+    Source(String),
+    Strings(usize, Option<Encoding>),
+    Structures,
+    Template(String, Option<usize>),
+    VerticalResize(usize),
+    VerticalSplit(Option<String>),
+    ToggleWrap,
+    /// Preview a transform over the start and length of a range, if given
+    Transform(Transform, Option<(usize, usize)>),
 }
 
 named!(
@@ -40,13 +183,236 @@ named!(
     alt!(tag!(" ") | tag!("\t") | tag!("\r") | tag!("\n"))
 );
 
-named!(numeric_string<&str>, map_res!(digit, ::std::str::from_utf8));
+named!(numeric_string<&'a str>, map_res!(digit, ::std::str::from_utf8));
 
 named!(
     usize_digit<usize>,
     map_res!(numeric_string, ::std::str::FromStr::from_str)
 );
 
+fn hex_usize(s: &str) -> Result<usize, ::std::num::ParseIntError> {
+    usize::from_str_radix(s, 16)
+}
+
+named!(
+    number<usize>,
+    alt!(
+        complete!(preceded!(
+            tag!("0x"),
+            map_res!(map_res!(nom::hex_digit, ::std::str::from_utf8), hex_usize)
+        )) | usize_digit
+    )
+);
+
+/// Trim trailing whitespace from a free text argument, failing if nothing is left
+fn text_argument(s: &str) -> Result<String, CommandParseError> {
+    let s = s.trim_end();
+    if s.is_empty() {
+        Err(CommandParseError)
+    } else {
+        Ok(s.to_owned())
+    }
+}
+
+/// A fill pattern of hex digits like `DEADBEEF` or `0xff`, or a quoted string like `"ab\x00"`
+fn byte_pattern(s: &str) -> Result<Vec<u8>, CommandParseError> {
+    let s = s.trim_end();
+    let bytes = match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(quoted) => unescape(quoted),
+        None => {
+            let digits: String = s
+                .strip_prefix("0x")
+                .unwrap_or(s)
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            if digits.len().is_multiple_of(2) && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                (0..digits.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+                    .collect()
+            } else {
+                None
+            }
+        }
+    };
+    bytes.filter(|bytes| !bytes.is_empty()).ok_or(CommandParseError)
+}
+
+/// The bytes of a quoted string, with `\\`, `\"`, `\0`, `\n`, `\r`, `\t` and `\xNN` escapes
+fn unescape(s: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let byte = match c {
+            '\\' => match chars.next()? {
+                '\\' => b'\\',
+                '"' => b'"',
+                '0' => 0,
+                'n' => b'\n',
+                'r' => b'\r',
+                't' => b'\t',
+                'x' => {
+                    let digits: String = chars.by_ref().take(2).collect();
+                    if digits.len() != 2 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return None;
+                    }
+                    u8::from_str_radix(&digits, 16).ok()?
+                }
+                _ => return None,
+            },
+            '"' => return None,
+            c => {
+                let mut buffer = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                continue;
+            }
+        };
+        bytes.push(byte);
+    }
+    Some(bytes)
+}
+
+named!(
+    text<String>,
+    map_res!(map_res!(rest, ::std::str::from_utf8), text_argument)
+);
+
+named!(no_arguments<&[u8], ()>,
+       do_parse!(
+           many0!(whitespace)            >>
+           eof!()                        >>
+           (())
+           )
+       );
+
+named!(optional_text<&[u8], Option<String>>,
+       alt!(
+           complete!(value!(None, no_arguments)) |
+           do_parse!(many1!(whitespace) >> t: text >> (Some(t)))
+           )
+       );
+
+named!(single_usize<&[u8], usize>,
+       do_parse!(
+           many0!(whitespace)            >>
+           arg1: usize_digit             >>
+           many0!(whitespace)            >>
+           eof!()                        >>
+           ({ arg1 })
+           )
+       );
+
+named!(colour_value<&[u8], Option<u8>>,
+       alt!(
+           value!(None, tag!("none")) |
+           map!(map_res!(numeric_string, u8::from_str), Some)
+           )
+       );
+
+named!(command_colour<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)            >>
+           class: map_opt!(
+               map_res!(nom::alpha, ::std::str::from_utf8),
+               ByteClass::from_name
+           )                             >>
+           many1!(whitespace)            >>
+           colour: colour_value          >>
+           many0!(whitespace)            >>
+           eof!()                        >>
+           ({ Command::SetColour(class, colour) })
+           )
+       );
+
+/// Groups are limited to the sizes of machine words
+fn group_size(n: usize) -> Option<usize> {
+    match n {
+        1 | 2 | 4 | 8 => Some(n),
+        _ => None,
+    }
+}
+
+named!(command_endian<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)            >>
+           little: alt!(
+               value!(true, tag!("little")) |
+               value!(false, tag!("big"))
+           )                             >>
+           many0!(whitespace)            >>
+           eof!()                        >>
+           ({ Command::SetLittleEndian(little) })
+           )
+       );
+
+named!(command_encoding<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)            >>
+           encoding: map_opt!(
+               map_res!(nom::alphanumeric, ::std::str::from_utf8),
+               Encoding::from_name
+           )                             >>
+           many0!(whitespace)            >>
+           eof!()                        >>
+           ({ Command::SetEncoding(encoding) })
+           )
+       );
+
+/// Encodings `strings` can search for
+fn string_encoding(name: &str) -> Option<Encoding> {
+    match Encoding::from_name(name) {
+        Some(Encoding::Ascii) => Some(Encoding::Ascii),
+        Some(Encoding::Utf16Le) => Some(Encoding::Utf16Le),
+        _ => None,
+    }
+}
+
+named!(command_strings<&[u8], Command>,
+       do_parse!(
+           min_length: opt!(complete!(preceded!(many1!(whitespace), usize_digit)))    >>
+           encoding: opt!(complete!(preceded!(
+               many1!(whitespace),
+               map_opt!(map_res!(nom::alphanumeric, ::std::str::from_utf8), string_encoding)
+           )))                                                                         >>
+           many0!(whitespace)                                                          >>
+           eof!()                                                                      >>
+           ({ Command::Strings(min_length.unwrap_or(DEFAULT_MIN_LENGTH), encoding) })
+           )
+       );
+
+named!(command_minimap<&[u8], Command>,
+       alt!(
+           complete!(value!(Command::ToggleMinimap, no_arguments)) |
+           do_parse!(
+               many1!(whitespace)        >>
+               measure: alt!(
+                   value!(None, tag!("off")) |
+                   map!(
+                       map_opt!(map_res!(nom::alpha, ::std::str::from_utf8), Measure::from_name),
+                       Some
+                   )
+               )                         >>
+               many0!(whitespace)        >>
+               eof!()                    >>
+               ({ Command::SetMinimap(measure) })
+               )
+           )
+       );
+
+named!(command_mode<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)            >>
+           mode: map_opt!(
+               map_res!(nom::alpha, ::std::str::from_utf8),
+               Mode::from_name
+           )                             >>
+           many0!(whitespace)            >>
+           eof!()                        >>
+           ({ Command::SetMode(mode) })
+           )
+       );
+
 named!(command_name<&[u8], CommandName>,
        do_parse!(
            many0!(whitespace)                              >>
@@ -98,12 +464,262 @@ named!(command_scroll_y<&[u8], Command>,
            )
        );
 
+named!(command_edit<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)            >>
+           path: text                    >>
+           ({ Command::Edit(path) })
+           )
+       );
+
+named!(command_bookmark<&[u8], Command>,
+       alt!(
+           complete!(do_parse!(
+               many1!(whitespace)        >>
+               start: number             >>
+               many1!(whitespace)        >>
+               length: number            >>
+               many1!(whitespace)        >>
+               label: text               >>
+               ({ Command::Bookmark(Some((start, length)), label) })
+           )) |
+           do_parse!(
+               many1!(whitespace)        >>
+               label: text               >>
+               ({ Command::Bookmark(None, label) })
+           )
+           )
+       );
+
+fn register(bytes: &[u8]) -> Option<char> {
+    Some(bytes[0] as char).filter(|&c| Macros::is_register(c))
+}
+
+named!(command_macro<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)                                  >>
+           register: map_opt!(take!(1), register)              >>
+           keys: alt!(
+               complete!(value!(None, no_arguments)) |
+               do_parse!(
+                   many1!(whitespace)                          >>
+                   keys: map_opt!(text, |keys: String| macros::parse(&keys)) >>
+                   (Some(keys))
+               )
+           )                                                   >>
+           ({ Command::Macro(register, keys) })
+           )
+       );
+
+named!(command_goto<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)                                      >>
+           rva: opt!(complete!(terminated!(tag!("rva"), many1!(whitespace))))  >>
+           address: number                                         >>
+           many0!(whitespace)                                      >>
+           eof!()                                                  >>
+           ({ if rva.is_some() { Command::GotoRva(address) } else { Command::Goto(address) } })
+           )
+       );
+
+named!(command_template<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)                                          >>
+           path: map_res!(is_not!(" \t\r\n"), ::std::str::from_utf8)    >>
+           offset: opt!(complete!(preceded!(many1!(whitespace), number))) >>
+           many0!(whitespace)                                          >>
+           eof!()                                                      >>
+           ({ Command::Template(path.to_owned(), offset) })
+           )
+       );
+
+named!(command_c_struct<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)                                          >>
+           path: map_res!(is_not!(" \t\r\n"), ::std::str::from_utf8)    >>
+           many1!(whitespace)                                          >>
+           name: map_res!(is_not!(" \t\r\n"), ::std::str::from_utf8)    >>
+           offset: opt!(complete!(preceded!(many1!(whitespace), number))) >>
+           many0!(whitespace)                                          >>
+           eof!()                                                      >>
+           ({ Command::CStruct(path.to_owned(), name.to_owned(), offset) })
+           )
+       );
+
+named!(command_hash<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)                                                  >>
+           algorithm: map_opt!(
+               map_res!(is_not!(" \t\r\n"), ::std::str::from_utf8),
+               Algorithm::from_name
+           )                                                                   >>
+           range: opt!(complete!(do_parse!(
+               many1!(whitespace)    >>
+               start: number         >>
+               many1!(whitespace)    >>
+               length: number        >>
+               ((start, length))
+           )))                                                                 >>
+           copy: opt!(complete!(preceded!(many1!(whitespace), tag!("copy"))))  >>
+           many0!(whitespace)                                                  >>
+           eof!()                                                              >>
+           ({ Command::Hash(algorithm, range, copy.is_some()) })
+           )
+       );
+
+named!(command_fill<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)                                              >>
+           start: number                                                   >>
+           many1!(whitespace)                                              >>
+           length: number                                                  >>
+           many1!(whitespace)                                              >>
+           pattern: map_res!(map_res!(rest, ::std::str::from_utf8), byte_pattern) >>
+           ({ Command::Fill(start, length, pattern) })
+           )
+       );
+
+/// Add `n` bytes, which can be negative
+fn wrapping_byte(negative: bool, n: usize) -> Option<u8> {
+    let n = u8::try_from(n).ok()?;
+    Some(if negative { n.wrapping_neg() } else { n })
+}
+
+fn bit_rotation(n: usize) -> Option<u32> {
+    if n < 8 {
+        Some(n as u32)
+    } else {
+        None
+    }
+}
+
+// the start and length of a range, or nothing for the default range
+named!(optional_range<&[u8], Option<(usize, usize)>>,
+       do_parse!(
+           range: opt!(complete!(do_parse!(
+               many1!(whitespace)    >>
+               start: number         >>
+               many1!(whitespace)    >>
+               length: number        >>
+               ((start, length))
+           )))                       >>
+           many0!(whitespace)        >>
+           eof!()                    >>
+           (range)
+           )
+       );
+
+named!(command_xor<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)                                                      >>
+           key: map_res!(map_res!(is_not!(" \t\r\n"), ::std::str::from_utf8), byte_pattern) >>
+           range: optional_range                                                   >>
+           ({ Command::Transform(Transform::Xor(key), range) })
+           )
+       );
+
+named!(command_add<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)                    >>
+           negative: opt!(tag!("-"))             >>
+           n: map_opt!(number, |n| wrapping_byte(negative.is_some(), n)) >>
+           range: optional_range                 >>
+           ({ Command::Transform(Transform::Add(n), range) })
+           )
+       );
+
+named!(command_rotate<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)                    >>
+           n: map_opt!(number, bit_rotation)     >>
+           range: optional_range                 >>
+           ({ Command::Transform(Transform::Rotate(n), range) })
+           )
+       );
+
+// the width follows the name directly, as in `bswap32`
+named!(command_byte_swap<&[u8], Command>,
+       do_parse!(
+           width: alt!(
+               value!(2, tag!("16")) |
+               value!(4, tag!("32")) |
+               value!(8, tag!("64"))
+           )                                     >>
+           range: optional_range                 >>
+           ({ Command::Transform(Transform::ByteSwap(width), range) })
+           )
+       );
+
+named!(command_section<&[u8], Command>,
+       do_parse!(
+           many1!(whitespace)            >>
+           name: text                    >>
+           ({ Command::Section(name) })
+           )
+       );
+
 named!(pub command<&[u8], Command>,
        switch!(command_name,
+               CommandName::Add => complete!(command_add) |
+               CommandName::Bookmark => complete!(command_bookmark) |
+               CommandName::Bookmarks => complete!(value!(Command::ListBookmarks, no_arguments)) |
+               CommandName::Buffers => complete!(value!(Command::ListBuffers, no_arguments)) |
+               CommandName::ByteSwap => complete!(command_byte_swap) |
+               CommandName::Commit => complete!(value!(Command::Commit, no_arguments)) |
+               CommandName::ClearHighlight =>
+                   complete!(value!(Command::ClearHighlight, no_arguments)) |
+               CommandName::DeleteBookmark =>
+                   complete!(preceded!(many1!(whitespace), map!(text, Command::DeleteBookmark))) |
+               CommandName::Edit => complete!(command_edit) |
+               CommandName::Encoding => complete!(command_encoding) |
+               CommandName::Endian => complete!(command_endian) |
+               CommandName::ExportBookmarks =>
+                   complete!(preceded!(many1!(whitespace), map!(text, Command::ExportBookmarks))) |
+               CommandName::Fill => complete!(command_fill) |
+               CommandName::Goto => complete!(command_goto) |
+               CommandName::Hash => complete!(command_hash) |
+               CommandName::Macro => complete!(command_macro) |
+               CommandName::Minimap => complete!(command_minimap) |
+               CommandName::Mode => complete!(command_mode) |
+               CommandName::Group =>
+                   complete!(map!(map_opt!(single_usize, group_size), Command::SetGroupSize)) |
+               CommandName::NextBuffer => complete!(value!(Command::NextBuffer, no_arguments)) |
+               CommandName::Not =>
+                   complete!(map!(optional_range, |range| Command::Transform(Transform::Not, range))) |
+               CommandName::PreviousBuffer =>
+                   complete!(value!(Command::PreviousBuffer, no_arguments)) |
+               CommandName::Close => complete!(value!(Command::CloseWindow, no_arguments)) |
+               CommandName::Colour => complete!(command_colour) |
+               CommandName::Colours => complete!(value!(Command::ToggleColours, no_arguments)) |
+               CommandName::CStruct => complete!(command_c_struct) |
+               CommandName::Quit => complete!(alt!(
+                   complete!(value!(Command::Quit(true), preceded!(tag!("!"), no_arguments))) |
+                   value!(Command::Quit(false), no_arguments)
+               )) |
+               CommandName::Resize => complete!(map!(single_usize, Command::Resize)) |
+               CommandName::Revert => complete!(value!(Command::Revert, no_arguments)) |
+               CommandName::Rotate => complete!(command_rotate) |
+               CommandName::Save => complete!(map!(optional_text, Command::Save)) |
+               CommandName::Split => complete!(map!(optional_text, Command::Split)) |
+               CommandName::Section => complete!(command_section) |
+               CommandName::Source =>
+                   complete!(preceded!(many1!(whitespace), map!(text, Command::Source))) |
+               CommandName::Strings => complete!(command_strings) |
+               CommandName::Structures => complete!(value!(Command::Structures, no_arguments)) |
+               CommandName::Template => complete!(command_template) |
+               CommandName::ClearTemplate =>
+                   complete!(value!(Command::ClearTemplate, no_arguments)) |
+               CommandName::VerticalResize =>
+                   complete!(map!(single_usize, Command::VerticalResize)) |
+               CommandName::VerticalSplit =>
+                   complete!(map!(optional_text, Command::VerticalSplit)) |
                CommandName::Offset => complete!(command_offset) |
+               CommandName::Pictures => complete!(value!(Command::TogglePictures, no_arguments)) |
                CommandName::ScrollX => complete!(command_scroll_x) |
                CommandName::ScrollY => complete!(command_scroll_y) |
-               CommandName::Width => complete!(command_width)
+               CommandName::Width => complete!(command_width) |
+               CommandName::Wrap => complete!(value!(Command::ToggleWrap, no_arguments)) |
+               CommandName::Xor => complete!(command_xor)
                )
        );
 
@@ -179,7 +795,9 @@ mod tests {
     use super::*;
     mod command {
         use super::{command, command_name, usize_digit};
-        use super::{Command, CommandName};
+        use super::{
+            Algorithm, ByteClass, Command, CommandName, Encoding, Measure, Mode, Transform,
+        };
 
         #[test]
         fn parsing_commands() {
@@ -189,10 +807,347 @@ mod tests {
             assert_parse_ok!(command, Command::SetWidth(0), [b"width 0", b"w  0"]);
             assert_parse_ok!(command, Command::ScrollX(0), [b"scrollx 0", b"x  0"]);
             assert_parse_ok!(command, Command::ScrollY(0), [b"scrolly 0", b"y  0"]);
+            assert_parse_ok!(command, Command::ToggleWrap, [b"wrap", b"wrap "]);
             assert_parse_any_error!(command, [b"wdith 3", b"width", b"wid"]);
             assert_parse_any_error!(command, [b"offest 3", b"offset", b"offse"]);
         }
 
+        #[test]
+        fn parsing_buffer_commands() {
+            assert_parse_ok!(
+                command,
+                Command::Edit("some/file.bin".to_owned()),
+                [
+                    b"e some/file.bin",
+                    b"edit  some/file.bin ",
+                    b" e\tsome/file.bin"
+                ]
+            );
+            assert_parse_ok!(
+                command,
+                Command::Edit("with space".to_owned()),
+                [b"e with space"]
+            );
+            assert_parse_ok!(command, Command::NextBuffer, [b"bn", b"bnext", b"bn "]);
+            assert_parse_ok!(command, Command::PreviousBuffer, [b"bp", b"bprevious"]);
+            assert_parse_ok!(command, Command::ListBuffers, [b"ls", b"buffers "]);
+            assert_parse_any_error!(command, [b"e", b"e  ", b"bn 2", b"ls x"]);
+        }
+
+        #[test]
+        fn parsing_colour_commands() {
+            assert_parse_ok!(command, Command::ToggleColours, [b"colours", b"colors "]);
+            assert_parse_ok!(
+                command,
+                Command::SetColour(ByteClass::Null, Some(240)),
+                [b"colour null 240", b"color  null 240 "]
+            );
+            assert_parse_ok!(
+                command,
+                Command::SetColour(ByteClass::Full, None),
+                [b"colour ff none"]
+            );
+            assert_parse_any_error!(
+                command,
+                [
+                    b"colour null",
+                    b"colour nul 1",
+                    b"colour ff 256",
+                    b"colour 1 ff"
+                ]
+            );
+        }
+
+        #[test]
+        fn parsing_format_commands() {
+            assert_parse_ok!(command, Command::SetGroupSize(4), [b"group 4", b"group  4 "]);
+            assert_parse_ok!(command, Command::SetLittleEndian(true), [b"endian little"]);
+            assert_parse_ok!(command, Command::SetLittleEndian(false), [b"endian  big "]);
+            assert_parse_any_error!(
+                command,
+                [b"group", b"group 3", b"group 16", b"endian", b"endian middle"]
+            );
+            assert_parse_ok!(command, Command::SetMode(Mode::Binary), [b"mode bin"]);
+            assert_parse_ok!(command, Command::SetMode(Mode::SignedDecimal), [b"mode  sdec "]);
+            assert_parse_any_error!(command, [b"mode", b"mode hexadecimal"]);
+            assert_parse_ok!(
+                command,
+                Command::SetEncoding(Encoding::Utf16Le),
+                [b"enc utf16le", b"encoding  utf16le "]
+            );
+            assert_parse_any_error!(command, [b"enc", b"enc utf32"]);
+            assert_parse_ok!(command, Command::TogglePictures, [b"pictures", b"pictures "]);
+            assert_parse_ok!(command, Command::ToggleMinimap, [b"minimap", b"minimap "]);
+            assert_parse_ok!(
+                command,
+                Command::SetMinimap(Some(Measure::ByteClass)),
+                [b"minimap class"]
+            );
+            assert_parse_ok!(command, Command::SetMinimap(None), [b"minimap  off "]);
+            assert_parse_any_error!(command, [b"minimap heat"]);
+        }
+
+        #[test]
+        fn parsing_structure_commands() {
+            assert_parse_ok!(command, Command::Structures, [b"structures", b"structures "]);
+            assert_parse_ok!(
+                command,
+                Command::Section(".text".to_owned()),
+                [b"section .text", b"section  .text "]
+            );
+            assert_parse_ok!(command, Command::ClearHighlight, [b"nohl", b"nohighlight"]);
+            assert_parse_any_error!(command, [b"section", b"section ", b"structures elf"]);
+        }
+
+        #[test]
+        fn parsing_hash_commands() {
+            let md5 = Algorithm::from_name("md5").unwrap();
+            let crc = Algorithm::from_name("crc16-ccitt").unwrap();
+            assert_parse_ok!(command, Command::Hash(md5, None, false), [b"hash md5", b"hash  md5 "]);
+            assert_parse_ok!(
+                command,
+                Command::Hash(crc, Some((0x100, 16)), false),
+                [b"hash crc16-ccitt 0x100 16", b"hash crc16-ccitt 256 0x10 "]
+            );
+            assert_parse_ok!(command, Command::Hash(md5, None, true), [b"hash md5 copy"]);
+            assert_parse_ok!(
+                command,
+                Command::Hash(md5, Some((0, 4)), true),
+                [b"hash md5 0 4 copy "]
+            );
+            assert_parse_any_error!(command, [b"hash", b"hash md4", b"hash md5 0", b"hash md5 x"]);
+        }
+
+        #[test]
+        fn parsing_edit_commands() {
+            assert_parse_ok!(
+                command,
+                Command::Fill(0x10, 4, vec![0xDE, 0xAD]),
+                [b"fill 0x10 4 DEAD", b"fill 16 4 0xdead ", b"fill 16 4 de ad"]
+            );
+            assert_parse_ok!(
+                command,
+                Command::Fill(0, 8, b"a \"b\"\0\xff".to_vec()),
+                [b"fill 0 8 \"a \\\"b\\\"\\0\\xff\""]
+            );
+            assert_parse_any_error!(
+                command,
+                [
+                    b"fill",
+                    b"fill 0 8",
+                    b"fill 0 8 abc",
+                    b"fill 0 8 xy",
+                    b"fill 0 8 \"\"",
+                    b"fill 0 8 \"\\q\""
+                ]
+            );
+            assert_parse_ok!(command, Command::Save(None), [b"save", b"save "]);
+            assert_parse_ok!(
+                command,
+                Command::Save(Some("out.bin".to_owned())),
+                [b"save out.bin"]
+            );
+        }
+
+        #[test]
+        fn parsing_bookmark_commands() {
+            assert_parse_ok!(
+                command,
+                Command::Bookmark(None, "header CRC".to_owned()),
+                [b"bookmark header CRC", b"bookmark  header CRC "]
+            );
+            assert_parse_ok!(
+                command,
+                Command::Bookmark(Some((0x10, 4)), "header CRC".to_owned()),
+                [b"bookmark 0x10 4 header CRC", b"bookmark 16 0x4 header CRC"]
+            );
+            assert_parse_ok!(
+                command,
+                Command::Bookmark(None, "16 blob".to_owned()),
+                [b"bookmark 16 blob"]
+            );
+            assert_parse_ok!(command, Command::ListBookmarks, [b"bookmarks", b"bookmarks "]);
+            assert_parse_ok!(
+                command,
+                Command::DeleteBookmark("header CRC".to_owned()),
+                [b"delbookmark header CRC"]
+            );
+            assert_parse_ok!(
+                command,
+                Command::ExportBookmarks("notes.json".to_owned()),
+                [b"exportbookmarks notes.json"]
+            );
+            assert_parse_any_error!(
+                command,
+                [b"bookmark", b"bookmark ", b"bookmarks x", b"delbookmark", b"exportbookmarks"]
+            );
+        }
+
+        #[test]
+        fn parsing_macros_and_quitting() {
+            use termion::event::{Event, Key};
+
+            assert_parse_ok!(
+                command,
+                Command::Macro(
+                    'a',
+                    Some(vec![Event::Key(Key::Ctrl('d')), Event::Key(Key::Char('j'))])
+                ),
+                [b"macro a <C-d>j", b"macro a  <C-d>j "]
+            );
+            assert_parse_ok!(command, Command::Macro('7', None), [b"macro 7", b"macro 7 "]);
+            assert_parse_ok!(command, Command::Quit(false), [b"q", b"quit "]);
+            assert_parse_ok!(command, Command::Quit(true), [b"q!", b"quit!"]);
+            assert_parse_any_error!(
+                command,
+                [b"macro", b"macro ab", b"macro ! j", b"macro a <F1>", b"q !", b"q x"]
+            );
+        }
+
+        #[test]
+        fn parsing_source() {
+            assert_parse_ok!(
+                command,
+                Command::Source("checks/header.hi".to_owned()),
+                [b"source checks/header.hi", b"source  checks/header.hi "]
+            );
+            assert_parse_any_error!(command, [b"source", b"source "]);
+        }
+
+        #[test]
+        fn parsing_transform_commands() {
+            assert_parse_ok!(
+                command,
+                Command::Transform(Transform::Xor(vec![0xDE, 0xAD]), None),
+                [b"xor dead", b"xor 0xDEAD ", b"xor \"\\xde\\xad\""]
+            );
+            assert_parse_ok!(
+                command,
+                Command::Transform(Transform::Xor(vec![0x5A]), Some((0x10, 4))),
+                [b"xor 5a 0x10 4", b"xor 5a 16 4 "]
+            );
+            assert_parse_ok!(command, Command::Transform(Transform::Add(3), None), [b"add 3"]);
+            assert_parse_ok!(
+                command,
+                Command::Transform(Transform::Add(0xFD), Some((0, 2))),
+                [b"add -3 0 2", b"add 0xfd 0 2"]
+            );
+            assert_parse_ok!(command, Command::Transform(Transform::Rotate(7), None), [b"rol 7"]);
+            assert_parse_ok!(
+                command,
+                Command::Transform(Transform::Not, Some((8, 8))),
+                [b"not 8 8"]
+            );
+            assert_parse_ok!(command, Command::Transform(Transform::Not, None), [b"not", b"not "]);
+            assert_parse_ok!(
+                command,
+                Command::Transform(Transform::ByteSwap(4), None),
+                [b"bswap32"]
+            );
+            assert_parse_ok!(
+                command,
+                Command::Transform(Transform::ByteSwap(8), Some((0, 16))),
+                [b"bswap64 0 16"]
+            );
+            assert_parse_ok!(command, Command::Commit, [b"commit"]);
+            assert_parse_ok!(command, Command::Revert, [b"revert "]);
+            assert_parse_any_error!(
+                command,
+                [
+                    b"xor",
+                    b"xor xy",
+                    b"xor 5a 0",
+                    b"add 256",
+                    b"add",
+                    b"rol 8",
+                    b"not 0",
+                    b"bswap16 0 1 2",
+                    b"commit 1"
+                ]
+            );
+        }
+
+        #[test]
+        fn parsing_template_commands() {
+            assert_parse_ok!(
+                command,
+                Command::Template("formats/header.hit".to_owned(), None),
+                [b"template formats/header.hit", b"template  formats/header.hit "]
+            );
+            assert_parse_ok!(
+                command,
+                Command::Template("t".to_owned(), Some(0x40)),
+                [b"template t 0x40", b"template t 64 "]
+            );
+            assert_parse_ok!(command, Command::ClearTemplate, [b"notemplate"]);
+            assert_parse_any_error!(command, [b"template", b"template t x", b"template t 1 2"]);
+
+            assert_parse_ok!(
+                command,
+                Command::CStruct("elf.h".to_owned(), "Elf64_Ehdr".to_owned(), None),
+                [b"cstruct elf.h Elf64_Ehdr", b"cstruct  elf.h  Elf64_Ehdr "]
+            );
+            assert_parse_ok!(
+                command,
+                Command::CStruct("h".to_owned(), "s".to_owned(), Some(0x40)),
+                [b"cstruct h s 0x40", b"cstruct h s 64 "]
+            );
+            assert_parse_any_error!(command, [b"cstruct", b"cstruct h", b"cstruct h s x"]);
+        }
+
+        #[test]
+        fn parsing_goto_commands() {
+            assert_parse_ok!(command, Command::Goto(4096), [b"goto 4096", b"goto 0x1000 "]);
+            assert_parse_ok!(command, Command::Goto(0), [b"goto 0", b"goto 0x0"]);
+            assert_parse_ok!(
+                command,
+                Command::GotoRva(0x2A10),
+                [b"goto rva 0x2a10", b"goto  rva  10768"]
+            );
+            assert_parse_any_error!(command, [b"goto", b"goto rva", b"goto 0x", b"goto va 1"]);
+        }
+
+        #[test]
+        fn parsing_strings_commands() {
+            assert_parse_ok!(command, Command::Strings(4, None), [b"strings", b"strings "]);
+            assert_parse_ok!(command, Command::Strings(8, None), [b"strings 8", b"strings  8 "]);
+            assert_parse_ok!(
+                command,
+                Command::Strings(6, Some(Encoding::Utf16Le)),
+                [b"strings 6 utf16le"]
+            );
+            assert_parse_ok!(
+                command,
+                Command::Strings(4, Some(Encoding::Ascii)),
+                [b"strings ascii"]
+            );
+            assert_parse_any_error!(command, [b"strings latin1", b"strings ascii 6"]);
+        }
+
+        #[test]
+        fn parsing_window_commands() {
+            assert_parse_ok!(command, Command::Split(None), [b"sp", b"split", b"split  "]);
+            assert_parse_ok!(
+                command,
+                Command::Split(Some("other.bin".to_owned())),
+                [b"sp other.bin", b"split  other.bin"]
+            );
+            assert_parse_ok!(command, Command::VerticalSplit(None), [b"vs", b"vsplit"]);
+            assert_parse_ok!(
+                command,
+                Command::VerticalSplit(Some("x".to_owned())),
+                [b"vs x"]
+            );
+            assert_parse_ok!(command, Command::CloseWindow, [b"clo", b"close"]);
+            assert_parse_ok!(command, Command::Resize(10), [b"res 10", b"resize 10"]);
+            assert_parse_ok!(
+                command,
+                Command::VerticalResize(4),
+                [b"vres 4", b"vresize 4"]
+            );
+            assert_parse_any_error!(command, [b"resize", b"vresize x", b"close 1", b"splitx"]);
+        }
+
         #[test]
         fn parsing_command_names() {
             assert_parse_ok!(
@@ -1,4 +1,8 @@
+use crate::magic::FileType;
+
 /// Additional context needed for rendering
-pub struct Context<'a> {
-    pub file_path: &'a str,
+pub struct Context {
+    pub file_path: String,
+    /// Type detected from the file's signature
+    pub file_type: Option<FileType>,
 }
@@ -0,0 +1,483 @@
+//! Insertion-aware alignment of two byte sequences
+//!
+//! Comparing data offset-by-offset breaks down as soon as a single byte is inserted, every
+//! following byte appears changed. Instead the inputs are aligned in two passes:
+//!
+//!  * anchors: blocks of `a` are indexed by a rolling hash and `b` is scanned for matches, which
+//!    gives long runs of identical bytes cheaply even for large files
+//!  * gaps between anchors are small and get an exact byte level Myers diff, gaps too large for
+//!    that are reported as a single changed range
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::line::{Format, Line};
+
+/// Length of blocks used to find anchors between the inputs
+const BLOCK_SIZE: usize = 32;
+
+/// Gaps whose combined length exceeds this are not diffed byte by byte
+const MAX_GAP_SIZE: usize = 1024;
+
+/// Number of bytes shown in a hunk preview
+const PREVIEW_BYTES: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HunkKind {
+    Changed,
+    Inserted,
+    Deleted,
+}
+
+/// A run of differing bytes
+///
+/// For insertions the `a` range is empty and positioned where the bytes were inserted, the
+/// reverse is true for deletions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hunk {
+    pub kind: HunkKind,
+    pub a: Range<usize>,
+    pub b: Range<usize>,
+}
+
+impl Hunk {
+    fn new(a: Range<usize>, b: Range<usize>) -> Self {
+        let kind = if a.start == a.end {
+            HunkKind::Inserted
+        } else if b.start == b.end {
+            HunkKind::Deleted
+        } else {
+            HunkKind::Changed
+        };
+        Hunk { kind, a, b }
+    }
+}
+
+/// Align `a` and `b`, returning the ranges which differ in ascending order
+pub fn diff(a: &[u8], b: &[u8]) -> Vec<Hunk> {
+    let mut hunks = Vec::new();
+    let mut a_pos = 0;
+    let mut b_pos = 0;
+
+    for (a_start, b_start, len) in anchors(a, b) {
+        diff_gap(a, b, a_pos..a_start, b_pos..b_start, &mut hunks);
+        a_pos = a_start + len;
+        b_pos = b_start + len;
+    }
+    diff_gap(a, b, a_pos..a.len(), b_pos..b.len(), &mut hunks);
+
+    hunks
+}
+
+/// Find runs of identical bytes, returned as `(a_start, b_start, length)`
+///
+/// Runs are non-overlapping and ascending in both inputs.
+fn anchors(a: &[u8], b: &[u8]) -> Vec<(usize, usize, usize)> {
+    let mut anchors = Vec::new();
+
+    let mut index: HashMap<u64, Vec<usize>> = HashMap::new();
+    for start in (0..a.len()).step_by(BLOCK_SIZE) {
+        if start + BLOCK_SIZE <= a.len() {
+            index
+                .entry(hash(&a[start..start + BLOCK_SIZE]))
+                .or_default()
+                .push(start);
+        }
+    }
+
+    let outgoing_weight = (1..BLOCK_SIZE).fold(1u64, |w, _| w.wrapping_mul(HASH_BASE));
+
+    let mut a_pos = 0;
+    let mut b_pos = 0;
+    let mut j = 0;
+    let mut rolling = None;
+
+    while j + BLOCK_SIZE <= b.len() {
+        let h = match rolling {
+            Some(h) => h,
+            None => hash(&b[j..j + BLOCK_SIZE]),
+        };
+
+        // repetitive data (padding, tables) gives many candidates, prefer the one closest to the
+        // diagonal of the previous anchor so a repeated block doesn't skip over real matches
+        let candidate = index.get(&h).and_then(|starts| {
+            starts
+                .iter()
+                .filter(|&&i| i >= a_pos && a[i..i + BLOCK_SIZE] == b[j..j + BLOCK_SIZE])
+                .min_by_key(|&&i| ((i - a_pos) as isize - (j - b_pos) as isize).abs())
+        });
+
+        if let Some(&i) = candidate {
+            let mut start_a = i;
+            let mut start_b = j;
+            while start_a > a_pos && start_b > b_pos && a[start_a - 1] == b[start_b - 1] {
+                start_a -= 1;
+                start_b -= 1;
+            }
+
+            let mut end_a = i + BLOCK_SIZE;
+            let mut end_b = j + BLOCK_SIZE;
+            while end_a < a.len() && end_b < b.len() && a[end_a] == b[end_b] {
+                end_a += 1;
+                end_b += 1;
+            }
+
+            anchors.push((start_a, start_b, end_a - start_a));
+            a_pos = end_a;
+            b_pos = end_b;
+            j = end_b;
+            rolling = None;
+        } else {
+            rolling = if j + BLOCK_SIZE < b.len() {
+                Some(roll(h, b[j], b[j + BLOCK_SIZE], outgoing_weight))
+            } else {
+                None
+            };
+            j += 1;
+        }
+    }
+
+    anchors
+}
+
+const HASH_BASE: u64 = 257;
+
+fn hash(block: &[u8]) -> u64 {
+    block.iter().fold(0u64, |h, &byte| {
+        h.wrapping_mul(HASH_BASE).wrapping_add(byte as u64)
+    })
+}
+
+/// Slide a block hash forward by one byte
+///
+/// `outgoing_weight` is `HASH_BASE ^ (BLOCK_SIZE - 1)`, the factor applied to the first byte of a
+/// block.
+fn roll(h: u64, outgoing: u8, incoming: u8, outgoing_weight: u64) -> u64 {
+    h.wrapping_sub((outgoing as u64).wrapping_mul(outgoing_weight))
+        .wrapping_mul(HASH_BASE)
+        .wrapping_add(incoming as u64)
+}
+
+/// Diff the bytes between two anchors, appending any hunks found
+fn diff_gap(a: &[u8], b: &[u8], a_gap: Range<usize>, b_gap: Range<usize>, hunks: &mut Vec<Hunk>) {
+    let mut a_gap = a_gap;
+    let mut b_gap = b_gap;
+
+    // trim common prefix and suffix, the anchor scan only matches whole blocks so short runs of
+    // identical bytes are common at the edges of gaps
+    while a_gap.start < a_gap.end && b_gap.start < b_gap.end && a[a_gap.start] == b[b_gap.start] {
+        a_gap.start += 1;
+        b_gap.start += 1;
+    }
+    while a_gap.start < a_gap.end && b_gap.start < b_gap.end && a[a_gap.end - 1] == b[b_gap.end - 1]
+    {
+        a_gap.end -= 1;
+        b_gap.end -= 1;
+    }
+
+    if a_gap.start == a_gap.end && b_gap.start == b_gap.end {
+        return;
+    }
+
+    if a_gap.len() + b_gap.len() > MAX_GAP_SIZE {
+        hunks.push(Hunk::new(a_gap, b_gap));
+        return;
+    }
+
+    let a_off = a_gap.start;
+    let b_off = b_gap.start;
+    let mut a_run = a_off;
+    let mut b_run = b_off;
+    let mut x = a_off;
+    let mut y = b_off;
+
+    for edit in myers(&a[a_gap], &b[b_gap]) {
+        match edit {
+            Edit::Equal => {
+                if a_run < x || b_run < y {
+                    hunks.push(Hunk::new(a_run..x, b_run..y));
+                }
+                x += 1;
+                y += 1;
+                a_run = x;
+                b_run = y;
+            }
+            Edit::Delete => x += 1,
+            Edit::Insert => y += 1,
+        }
+    }
+    if a_run < x || b_run < y {
+        hunks.push(Hunk::new(a_run..x, b_run..y));
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Edit {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Shortest edit script between `a` and `b` using Myers' O(ND) algorithm
+fn myers(a: &[u8], b: &[u8]) -> Vec<Edit> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        trace.push(v.clone());
+        let mut k = -d;
+        while k <= d {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    // walk the trace backwards to recover the edit script
+    let mut edits = Vec::new();
+    let mut x = n;
+    let mut y = m;
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let idx = (k + offset) as usize;
+        let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                edits.push(Edit::Insert);
+            } else {
+                edits.push(Edit::Delete);
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+
+    edits.reverse();
+    edits
+}
+
+/// Write a human readable summary of `hunks`, with hex previews of the differing bytes
+pub fn write_report<W: Write>(out: &mut W, a: &[u8], b: &[u8], hunks: &[Hunk]) -> io::Result<()> {
+    let format = Format::default();
+    let mut line = Line::with_format(format.width_of(PREVIEW_BYTES), format);
+
+    let mut changed = 0;
+    let mut inserted = 0;
+    let mut deleted = 0;
+
+    for hunk in hunks {
+        let name = match hunk.kind {
+            HunkKind::Changed => {
+                changed += 1;
+                "changed"
+            }
+            HunkKind::Inserted => {
+                inserted += 1;
+                "inserted"
+            }
+            HunkKind::Deleted => {
+                deleted += 1;
+                "deleted"
+            }
+        };
+
+        writeln!(
+            out,
+            "{:<8} a:{} b:{}",
+            name,
+            format_range(&hunk.a),
+            format_range(&hunk.b)
+        )?;
+
+        for &(sign, data, range) in &[('-', a, &hunk.a), ('+', b, &hunk.b)] {
+            if range.start == range.end {
+                continue;
+            }
+            let end = range.start + PREVIEW_BYTES.min(range.len());
+            let ellipsis = if end < range.end { " ..." } else { "" };
+            writeln!(
+                out,
+                "  {} {}{}",
+                sign,
+                line.format(&data[range.start..end]).trim_end(),
+                ellipsis
+            )?;
+        }
+    }
+
+    writeln!(
+        out,
+        "{} changed, {} inserted, {} deleted",
+        changed, inserted, deleted
+    )
+}
+
+fn format_range(range: &Range<usize>) -> String {
+    if range.start == range.end {
+        format!("{:#010x}", range.start)
+    } else {
+        format!(
+            "{:#010x}..{:#010x} ({})",
+            range.start,
+            range.end,
+            range.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_inputs_have_no_hunks() {
+        let a = sample(1000);
+        assert_eq!(diff(&a, &a), vec![]);
+        assert_eq!(diff(&[], &[]), vec![]);
+    }
+
+    #[test]
+    fn it_detects_changed_bytes() {
+        let a = sample(1000);
+        let mut b = a.clone();
+        b[500] ^= 0xFF;
+        b[501] ^= 0xFF;
+
+        assert_eq!(diff(&a, &b), vec![Hunk::new(500..502, 500..502)]);
+    }
+
+    #[test]
+    fn it_aligns_after_an_insertion() {
+        let a = sample(1000);
+        let mut b = a.clone();
+        b.insert(100, 0xAA);
+        b.insert(100, 0xBB);
+
+        let hunks = diff(&a, &b);
+        assert_eq!(hunks, vec![Hunk::new(100..100, 100..102)]);
+        assert_eq!(hunks[0].kind, HunkKind::Inserted);
+    }
+
+    #[test]
+    fn it_aligns_after_a_deletion() {
+        let a = sample(1000);
+        let mut b = a.clone();
+        b.drain(300..340);
+
+        let hunks = diff(&a, &b);
+        assert_eq!(hunks, vec![Hunk::new(300..340, 300..300)]);
+        assert_eq!(hunks[0].kind, HunkKind::Deleted);
+    }
+
+    #[test]
+    fn it_aligns_across_repetitive_data() {
+        let mut a = sample(1000);
+        a[200..700].iter_mut().for_each(|byte| *byte = 0);
+        let mut b = a.clone();
+        b.insert(100, 0xAA);
+
+        assert_eq!(diff(&a, &b), vec![Hunk::new(100..100, 100..101)]);
+    }
+
+    #[test]
+    fn it_reports_multiple_hunks_in_order() {
+        let a = sample(2000);
+        let mut b = a.clone();
+        b[1500] ^= 0xFF;
+        b.drain(800..810);
+        b.insert(10, 0);
+
+        assert_eq!(
+            diff(&a, &b),
+            vec![
+                Hunk::new(10..10, 10..11),
+                Hunk::new(800..810, 801..801),
+                Hunk::new(1500..1501, 1491..1492),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_handles_inputs_shorter_than_a_block() {
+        assert_eq!(diff(b"abc", b"abXc"), vec![Hunk::new(2..2, 2..3)]);
+        assert_eq!(diff(b"", b"ab"), vec![Hunk::new(0..0, 0..2)]);
+        assert_eq!(diff(b"ab", b""), vec![Hunk::new(0..2, 0..0)]);
+    }
+
+    #[test]
+    fn rolling_hash_matches_direct_hash() {
+        let data = sample(BLOCK_SIZE + 1);
+        let weight = (1..BLOCK_SIZE).fold(1u64, |w, _| w.wrapping_mul(HASH_BASE));
+        let rolled = roll(hash(&data[..BLOCK_SIZE]), data[0], data[BLOCK_SIZE], weight);
+        assert_eq!(rolled, hash(&data[1..]));
+    }
+
+    #[test]
+    fn myers_finds_the_shortest_script() {
+        use super::Edit::*;
+        assert_eq!(myers(b"abc", b"abc"), vec![Equal, Equal, Equal]);
+        assert_eq!(myers(b"ac", b"abc"), vec![Equal, Insert, Equal]);
+        assert_eq!(myers(b"abc", b"ac"), vec![Equal, Delete, Equal]);
+        assert_eq!(myers(b"", b"a"), vec![Insert]);
+    }
+
+    #[test]
+    fn report_summarises_hunks_with_previews() {
+        let a = b"abcdef".to_vec();
+        let b = b"abXcdf".to_vec();
+        let hunks = diff(&a, &b);
+
+        let mut out = Vec::new();
+        write_report(&mut out, &a, &b, &hunks).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "inserted a:0x00000002 b:0x00000002..0x00000003 (1)\n\
+             \x20 + 58\n\
+             deleted  a:0x00000004..0x00000005 (1) b:0x00000005\n\
+             \x20 - 65\n\
+             0 changed, 1 inserted, 1 deleted\n"
+        );
+    }
+}
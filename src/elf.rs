@@ -0,0 +1,647 @@
+//! Decoding of ELF headers, program headers and section headers
+//!
+//! Both 32 and 64 bit files of either byte order are supported. Only the headers are read, the
+//! contents of segments and sections are left to the caller.
+use std::error::Error as StdError;
+use std::fmt;
+use std::ops::Range;
+
+use crate::overlay::TreeNode;
+
+/// Section type of sections which take up no space in the file
+const SHT_NOBITS: u32 = 8;
+
+/// Section index stored in the file header when the real index is in the first section header
+const SHN_XINDEX: u16 = 0xFFFF;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The data doesn't start with the ELF signature
+    NotElf,
+    /// The class or byte order in the identification bytes isn't one we know
+    Unsupported(&'static str),
+    /// A header lies past the end of the data
+    Truncated(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NotElf => write!(f, "not an ELF file"),
+            Error::Unsupported(what) => write!(f, "unsupported ELF {}", what),
+            Error::Truncated(what) => write!(f, "truncated ELF {}", what),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Class {
+    Elf32,
+    Elf64,
+}
+
+/// Reads fields of the file's class and byte order
+#[derive(Copy, Clone)]
+struct Reader<'a> {
+    data: &'a [u8],
+    class: Class,
+    little_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        let bytes = self.data.get(offset..offset.checked_add(N)?)?;
+        let mut array = [0; N];
+        array.copy_from_slice(bytes);
+        if !self.little_endian {
+            array.reverse();
+        }
+        Some(array)
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        self.bytes(offset).map(u16::from_le_bytes)
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        self.bytes(offset).map(u32::from_le_bytes)
+    }
+
+    fn u64(&self, offset: usize) -> Option<u64> {
+        self.bytes(offset).map(u64::from_le_bytes)
+    }
+
+    /// Read an address or offset, 4 bytes wide in 32 bit files and 8 in 64 bit ones
+    fn word(&self, offset: usize) -> Option<u64> {
+        match self.class {
+            Class::Elf32 => self.u32(offset).map(u64::from),
+            Class::Elf64 => self.u64(offset),
+        }
+    }
+}
+
+/// An entry of the program header table, describing a segment
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgramHeader {
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub file_size: u64,
+    pub memory_size: u64,
+    /// Bytes of the entry itself
+    pub header: Range<usize>,
+}
+
+/// An entry of the section header table
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub kind: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    /// Bytes of the entry itself
+    pub header: Range<usize>,
+}
+
+impl Section {
+    /// Bytes of the section's contents in the file, `None` for sections like `.bss` which have
+    /// none
+    pub fn data(&self) -> Option<Range<usize>> {
+        if self.kind == SHT_NOBITS {
+            return None;
+        }
+        let start = self.offset as usize;
+        Some(start..start.saturating_add(self.size as usize))
+    }
+}
+
+/// Decoded headers of an ELF file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Elf {
+    pub class: Class,
+    pub little_endian: bool,
+    /// Object file type, such as executable or shared object
+    pub kind: u16,
+    pub machine: u16,
+    pub entry: u64,
+    /// Bytes of the file header
+    pub header: Range<usize>,
+    pub program_headers: Vec<ProgramHeader>,
+    /// Bytes of the program header table
+    pub program_header_table: Range<usize>,
+    pub sections: Vec<Section>,
+    /// Bytes of the section header table
+    pub section_header_table: Range<usize>,
+}
+
+/// Decode the headers of the ELF file in `data`
+pub fn parse(data: &[u8]) -> Result<Elf, Error> {
+    if !data.starts_with(b"\x7FELF") {
+        return Err(Error::NotElf);
+    }
+    let class = match data.get(4) {
+        Some(1) => Class::Elf32,
+        Some(2) => Class::Elf64,
+        _ => return Err(Error::Unsupported("class")),
+    };
+    let little_endian = match data.get(5) {
+        Some(1) => true,
+        Some(2) => false,
+        _ => return Err(Error::Unsupported("byte order")),
+    };
+    let r = Reader {
+        data,
+        class,
+        little_endian,
+    };
+
+    // fields after the entry point move along by the difference in word size
+    let w = match class {
+        Class::Elf32 => 0,
+        Class::Elf64 => 4,
+    };
+    let kind = r.u16(16).ok_or(Error::Truncated("file header"))?;
+    let machine = r.u16(18).ok_or(Error::Truncated("file header"))?;
+    let fields = (|| {
+        Some((
+            r.word(24)?,
+            r.word(28 + w)? as usize,
+            r.word(32 + 2 * w)? as usize,
+            r.u16(40 + 3 * w)? as usize,
+            r.u16(42 + 3 * w)? as usize,
+            r.u16(44 + 3 * w)? as usize,
+            r.u16(46 + 3 * w)? as usize,
+            r.u16(48 + 3 * w)? as usize,
+            r.u16(50 + 3 * w)?,
+        ))
+    })();
+    let (entry, phoff, shoff, ehsize, phentsize, phnum, shentsize, shnum, shstrndx) =
+        fields.ok_or(Error::Truncated("file header"))?;
+
+    let (min_phentsize, min_shentsize) = match class {
+        Class::Elf32 => (32, 40),
+        Class::Elf64 => (56, 64),
+    };
+    if phnum > 0 && phentsize < min_phentsize {
+        return Err(Error::Unsupported("program header size"));
+    }
+
+    let program_header_table = phoff..phoff.saturating_add(phentsize * phnum);
+    let program_headers = (0..phnum)
+        .map(|i| {
+            let offset = phoff.checked_add(i * phentsize)?;
+            program_header(r, offset, phentsize)
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Error::Truncated("program header"))?;
+
+    // the first section header holds the section count and string table index when they don't
+    // fit in the file header
+    let (shnum, shstrndx) = if shoff == 0 {
+        (0, 0)
+    } else if shentsize < min_shentsize {
+        return Err(Error::Unsupported("section header size"));
+    } else {
+        let first =
+            section_header(r, shoff, shentsize).ok_or(Error::Truncated("section header"))?;
+        let shnum = if shnum == 0 {
+            first.size as usize
+        } else {
+            shnum
+        };
+        let shstrndx = if shstrndx == SHN_XINDEX {
+            first.link as usize
+        } else {
+            shstrndx as usize
+        };
+        (shnum, shstrndx)
+    };
+
+    let section_header_table = shoff..shoff.saturating_add(shentsize.saturating_mul(shnum));
+    let headers = (0..shnum)
+        .map(|i| {
+            let offset = shoff.checked_add(i.checked_mul(shentsize)?)?;
+            section_header(r, offset, shentsize)
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Error::Truncated("section header"))?;
+
+    let names = headers
+        .get(shstrndx)
+        .filter(|strings| strings.kind != SHT_NOBITS)
+        .and_then(|strings| {
+            let start = strings.offset as usize;
+            data.get(start..start.saturating_add(strings.size as usize))
+        })
+        .unwrap_or(&[]);
+    let sections = headers
+        .into_iter()
+        .map(|header| Section {
+            name: string_at(names, header.name as usize),
+            kind: header.kind,
+            flags: header.flags,
+            addr: header.addr,
+            offset: header.offset,
+            size: header.size,
+            header: header.range,
+        })
+        .collect();
+
+    Ok(Elf {
+        class,
+        little_endian,
+        kind,
+        machine,
+        entry,
+        header: 0..ehsize.min(data.len()),
+        program_headers,
+        program_header_table,
+        sections,
+        section_header_table,
+    })
+}
+
+fn program_header(r: Reader, offset: usize, size: usize) -> Option<ProgramHeader> {
+    r.data.get(offset..offset.checked_add(size)?)?;
+    let kind = r.u32(offset)?;
+    let (flags, fields) = match r.class {
+        Class::Elf32 => (r.u32(offset + 24)?, offset + 4),
+        Class::Elf64 => (r.u32(offset + 4)?, offset + 8),
+    };
+    let word = match r.class {
+        Class::Elf32 => 4,
+        Class::Elf64 => 8,
+    };
+    Some(ProgramHeader {
+        kind,
+        flags,
+        offset: r.word(fields)?,
+        vaddr: r.word(fields + word)?,
+        file_size: r.word(fields + 3 * word)?,
+        memory_size: r.word(fields + 4 * word)?,
+        header: offset..offset + size,
+    })
+}
+
+/// A section header before its name is looked up
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    range: Range<usize>,
+}
+
+fn section_header(r: Reader, offset: usize, size: usize) -> Option<SectionHeader> {
+    r.data.get(offset..offset.checked_add(size)?)?;
+    let word = match r.class {
+        Class::Elf32 => 4,
+        Class::Elf64 => 8,
+    };
+    Some(SectionHeader {
+        name: r.u32(offset)?,
+        kind: r.u32(offset + 4)?,
+        flags: r.word(offset + 8)?,
+        addr: r.word(offset + 8 + word)?,
+        offset: r.word(offset + 8 + 2 * word)?,
+        size: r.word(offset + 8 + 3 * word)?,
+        link: r.u32(offset + 8 + 4 * word)?,
+        range: offset..offset + size,
+    })
+}
+
+/// Read the zero terminated string starting at `offset` in a string table
+fn string_at(strings: &[u8], offset: usize) -> String {
+    let bytes = strings.get(offset..).unwrap_or(&[]);
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn object_type_name(kind: u16) -> String {
+    match kind {
+        0 => "NONE".to_owned(),
+        1 => "REL".to_owned(),
+        2 => "EXEC".to_owned(),
+        3 => "DYN".to_owned(),
+        4 => "CORE".to_owned(),
+        _ => format!("{:#x}", kind),
+    }
+}
+
+fn machine_name(machine: u16) -> String {
+    match machine {
+        3 => "x86".to_owned(),
+        8 => "MIPS".to_owned(),
+        20 => "PowerPC".to_owned(),
+        21 => "PowerPC64".to_owned(),
+        40 => "ARM".to_owned(),
+        62 => "x86-64".to_owned(),
+        183 => "AArch64".to_owned(),
+        243 => "RISC-V".to_owned(),
+        _ => format!("machine {:#x}", machine),
+    }
+}
+
+fn segment_type_name(kind: u32) -> String {
+    match kind {
+        0 => "NULL".to_owned(),
+        1 => "LOAD".to_owned(),
+        2 => "DYNAMIC".to_owned(),
+        3 => "INTERP".to_owned(),
+        4 => "NOTE".to_owned(),
+        5 => "SHLIB".to_owned(),
+        6 => "PHDR".to_owned(),
+        7 => "TLS".to_owned(),
+        0x6474_E550 => "GNU_EH_FRAME".to_owned(),
+        0x6474_E551 => "GNU_STACK".to_owned(),
+        0x6474_E552 => "GNU_RELRO".to_owned(),
+        0x6474_E553 => "GNU_PROPERTY".to_owned(),
+        _ => format!("{:#x}", kind),
+    }
+}
+
+fn section_type_name(kind: u32) -> String {
+    match kind {
+        0 => "NULL".to_owned(),
+        1 => "PROGBITS".to_owned(),
+        2 => "SYMTAB".to_owned(),
+        3 => "STRTAB".to_owned(),
+        4 => "RELA".to_owned(),
+        5 => "HASH".to_owned(),
+        6 => "DYNAMIC".to_owned(),
+        7 => "NOTE".to_owned(),
+        SHT_NOBITS => "NOBITS".to_owned(),
+        9 => "REL".to_owned(),
+        10 => "SHLIB".to_owned(),
+        11 => "DYNSYM".to_owned(),
+        14 => "INIT_ARRAY".to_owned(),
+        15 => "FINI_ARRAY".to_owned(),
+        16 => "PREINIT_ARRAY".to_owned(),
+        17 => "GROUP".to_owned(),
+        18 => "SYMTAB_SHNDX".to_owned(),
+        0x6FFF_FFF6 => "GNU_HASH".to_owned(),
+        0x6FFF_FFFD => "VERDEF".to_owned(),
+        0x6FFF_FFFE => "VERNEED".to_owned(),
+        0x6FFF_FFFF => "VERSYM".to_owned(),
+        _ => format!("{:#x}", kind),
+    }
+}
+
+/// Segment permissions in the style of `readelf`
+fn segment_flags(flags: u32) -> String {
+    let flag = |bit, c| if flags & bit != 0 { c } else { '-' };
+    [flag(4, 'R'), flag(2, 'W'), flag(1, 'X')].iter().collect()
+}
+
+impl Elf {
+    /// Find the section called `name`
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// Short description of the file, such as "ELF64 LE DYN x86-64"
+    pub fn summary(&self) -> String {
+        format!(
+            "{} {} {} {}",
+            match self.class {
+                Class::Elf32 => "ELF32",
+                Class::Elf64 => "ELF64",
+            },
+            if self.little_endian { "LE" } else { "BE" },
+            object_type_name(self.kind),
+            machine_name(self.machine)
+        )
+    }
+
+    /// Structures of the file arranged for browsing
+    ///
+    /// Program and section header entries cover the bytes of the entry, the sections group covers
+    /// the contents of each section.
+    pub fn tree(&self) -> Vec<TreeNode> {
+        let program_headers = self
+            .program_headers
+            .iter()
+            .enumerate()
+            .map(|(i, segment)| {
+                TreeNode::new(
+                    format!(
+                        "[{}] {} {} offset {:#x} size {:#x}",
+                        i,
+                        segment_type_name(segment.kind),
+                        segment_flags(segment.flags),
+                        segment.offset,
+                        segment.file_size
+                    ),
+                    segment.header.clone(),
+                )
+            })
+            .collect();
+
+        let section_headers = self
+            .sections
+            .iter()
+            .enumerate()
+            .map(|(i, section)| {
+                TreeNode::new(
+                    format!(
+                        "[{}] {} {}",
+                        i,
+                        section.name,
+                        section_type_name(section.kind)
+                    ),
+                    section.header.clone(),
+                )
+            })
+            .collect();
+
+        let sections: Vec<TreeNode> = self
+            .sections
+            .iter()
+            .filter_map(|section| {
+                let data = section.data().filter(|data| !data.is_empty())?;
+                Some(TreeNode::new(
+                    format!(
+                        "{} offset {:#x} size {:#x}",
+                        section.name, section.offset, section.size
+                    ),
+                    data,
+                ))
+            })
+            .collect();
+        let contents = match (
+            sections.iter().map(|node| node.range.start).min(),
+            sections.iter().map(|node| node.range.end).max(),
+        ) {
+            (Some(start), Some(end)) => start..end,
+            _ => 0..0,
+        };
+
+        vec![
+            TreeNode::new(
+                format!("File header, {}", self.summary()),
+                self.header.clone(),
+            ),
+            TreeNode::new(
+                format!("Program headers ({})", self.program_headers.len()),
+                self.program_header_table.clone(),
+            )
+            .children(program_headers),
+            TreeNode::new(
+                format!("Section headers ({})", self.sections.len()),
+                self.section_header_table.clone(),
+            )
+            .children(section_headers),
+            TreeNode::new("Sections".to_owned(), contents).children(sections),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Append `value` to `out` as `size` bytes in the given byte order
+    fn put(out: &mut Vec<u8>, value: u64, size: usize, little_endian: bool) {
+        let bytes = value.to_le_bytes();
+        let mut field = bytes[..size].to_vec();
+        if !little_endian {
+            field.reverse();
+        }
+        out.extend(field);
+    }
+
+    /// Build a file with one LOAD segment, a `.text` section, a `.bss` section and the section
+    /// name table
+    fn build(class: Class, little_endian: bool) -> Vec<u8> {
+        let (word, ehsize, phentsize, shentsize) = match class {
+            Class::Elf32 => (4, 52, 32, 40),
+            Class::Elf64 => (8, 64, 56, 64),
+        };
+        let names = b"\0.text\0.bss\0.shstrtab\0";
+        let text = b"\x90\x90\xC3";
+        let phoff = ehsize;
+        let text_offset = phoff + phentsize;
+        let names_offset = text_offset + text.len();
+        let shoff = names_offset + names.len();
+
+        let mut out = b"\x7FELF".to_vec();
+        out.push(if class == Class::Elf32 { 1 } else { 2 });
+        out.push(if little_endian { 1 } else { 2 });
+        out.extend(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let le = little_endian;
+        put(&mut out, 2, 2, le); // type
+        put(&mut out, 62, 2, le); // machine
+        put(&mut out, 1, 4, le); // version
+        put(&mut out, 0x401000, word, le); // entry
+        put(&mut out, phoff as u64, word, le);
+        put(&mut out, shoff as u64, word, le);
+        put(&mut out, 0, 4, le); // flags
+        put(&mut out, ehsize as u64, 2, le);
+        put(&mut out, phentsize as u64, 2, le);
+        put(&mut out, 1, 2, le);
+        put(&mut out, shentsize as u64, 2, le);
+        put(&mut out, 4, 2, le);
+        put(&mut out, 3, 2, le); // shstrndx
+        assert_eq!(out.len(), ehsize);
+
+        put(&mut out, 1, 4, le); // LOAD
+        if class == Class::Elf64 {
+            put(&mut out, 5, 4, le);
+        }
+        put(&mut out, text_offset as u64, word, le);
+        put(&mut out, 0x401000, word, le);
+        put(&mut out, 0x401000, word, le);
+        put(&mut out, text.len() as u64, word, le);
+        put(&mut out, text.len() as u64, word, le);
+        if class == Class::Elf32 {
+            put(&mut out, 5, 4, le);
+        }
+        put(&mut out, 0x1000, word, le);
+        assert_eq!(out.len(), text_offset);
+
+        out.extend(text);
+        out.extend(&names[..]);
+
+        let sections: [(u32, u32, usize, usize); 4] = [
+            (0, 0, 0, 0),
+            (1, 1, text_offset, text.len()),
+            (7, SHT_NOBITS, shoff, 0x100),
+            (12, 3, names_offset, names.len()),
+        ];
+        for &(name, kind, offset, size) in &sections {
+            put(&mut out, name.into(), 4, le);
+            put(&mut out, kind.into(), 4, le);
+            put(&mut out, 0, word, le); // flags
+            put(&mut out, 0, word, le); // addr
+            put(&mut out, offset as u64, word, le);
+            put(&mut out, size as u64, word, le);
+            put(&mut out, 0, 4, le); // link
+            put(&mut out, 0, 4, le); // info
+            put(&mut out, 0, word, le); // align
+            put(&mut out, 0, word, le); // entsize
+        }
+        out
+    }
+
+    #[test]
+    fn it_decodes_every_class_and_byte_order() {
+        for &class in &[Class::Elf32, Class::Elf64] {
+            for &little_endian in &[true, false] {
+                let data = build(class, little_endian);
+                let elf = parse(&data).unwrap();
+
+                assert_eq!(elf.class, class);
+                assert_eq!(elf.little_endian, little_endian);
+                assert_eq!(elf.machine, 62);
+                assert_eq!(elf.entry, 0x401000);
+                assert_eq!(elf.program_headers.len(), 1);
+
+                let segment = &elf.program_headers[0];
+                assert_eq!((segment.kind, segment.flags), (1, 5));
+                assert_eq!(segment.file_size, 3);
+
+                let names: Vec<&str> = elf.sections.iter().map(|s| s.name.as_str()).collect();
+                assert_eq!(names, vec!["", ".text", ".bss", ".shstrtab"]);
+
+                let text = elf.section(".text").unwrap().data().unwrap();
+                assert_eq!(&data[text], b"\x90\x90\xC3");
+                assert_eq!(elf.section(".bss").unwrap().data(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn it_rejects_other_and_truncated_files() {
+        assert_eq!(parse(b"MZ\x90\x00"), Err(Error::NotElf));
+        assert_eq!(parse(b"\x7FELF\x03\x01"), Err(Error::Unsupported("class")));
+
+        let data = build(Class::Elf64, true);
+        assert_eq!(parse(&data[..40]), Err(Error::Truncated("file header")));
+        assert_eq!(
+            parse(&data[..data.len() - 1]),
+            Err(Error::Truncated("section header"))
+        );
+    }
+
+    #[test]
+    fn the_tree_lists_every_structure() {
+        let elf = parse(&build(Class::Elf64, true)).unwrap();
+        let tree = elf.tree();
+
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree[0].label, "File header, ELF64 LE EXEC x86-64");
+        assert_eq!(tree[1].children[0].range, 64..120);
+        assert_eq!(tree[2].children.len(), 4);
+        // .bss and the empty first section have no contents
+        assert_eq!(tree[3].children.len(), 2);
+        assert_eq!(tree[3].children[0].range, 120..123);
+    }
+}
@@ -0,0 +1,463 @@
+//! Decoding of bytes into characters for the character column
+//!
+//! Every byte gets exactly one glyph so decoded text stays aligned with the cells of the other
+//! display modes. Characters encoded as several bytes are shown in the cell of their first byte,
+//! the remaining cells are marked as continuations.
+//!
+//! Decoding starts afresh at the beginning of each slice it's given, so a sequence split across
+//! two rows is shown as a continuation at the start of the second row and as plain bytes at the
+//! end of the first.
+use std::iter;
+use std::str;
+
+/// Character set used to decode bytes in the character column
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Encoding {
+    Ascii,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Cp437,
+    Ebcdic,
+}
+
+/// How a single byte is displayed in the character column
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Glyph {
+    /// A character one column wide
+    Char(char),
+    /// A character two columns wide, filling the whole cell
+    Wide(char),
+    /// A byte belonging to the character shown in an earlier cell
+    Continuation,
+    /// A byte which isn't valid where it appears
+    Invalid,
+    /// A valid byte with no printable representation, shown as its hex value
+    Byte,
+    /// A stand-in for a byte with no printable representation
+    Picture(char),
+}
+
+/// Stand-in glyph for a byte with no printable representation in ASCII
+///
+/// Control codes are shown as their Unicode Control Pictures, bytes with the high bit set share a
+/// placeholder with 0xFF getting its own.
+fn ascii_picture(byte: u8) -> Glyph {
+    match byte {
+        0x00..=0x1F => Glyph::Picture(char::from_u32(0x2400 + byte as u32).unwrap()),
+        0x7F => Glyph::Picture('␡'),
+        0xFF => Glyph::Picture('▪'),
+        0x80..=0xFE => Glyph::Picture('•'),
+        _ => Glyph::Byte,
+    }
+}
+
+impl Encoding {
+    /// Look up an encoding by the name used in commands
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ascii" => Some(Encoding::Ascii),
+            "utf8" => Some(Encoding::Utf8),
+            "utf16le" => Some(Encoding::Utf16Le),
+            "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" => Some(Encoding::Latin1),
+            "cp437" => Some(Encoding::Cp437),
+            "ebcdic" => Some(Encoding::Ebcdic),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Ascii => "ascii",
+            Encoding::Utf8 => "utf8",
+            Encoding::Utf16Le => "utf16le",
+            Encoding::Utf16Be => "utf16be",
+            Encoding::Latin1 => "latin1",
+            Encoding::Cp437 => "cp437",
+            Encoding::Ebcdic => "ebcdic",
+        }
+    }
+
+    /// Stand-in glyph for a `byte` with no printable representation in this encoding
+    ///
+    /// EBCDIC control codes are shown as the pictures of their ASCII counterparts, those with
+    /// none in ASCII share the placeholder of bytes with the high bit set.
+    pub fn picture(self, byte: u8) -> Glyph {
+        match (self, byte) {
+            (Encoding::Ebcdic, 0x00..=0x3F) => ascii_picture(EBCDIC_CONTROLS[byte as usize]),
+            (Encoding::Ebcdic, _) => Glyph::Picture('•'),
+            _ => ascii_picture(byte),
+        }
+    }
+
+    /// Replace the contents of `glyphs` with one glyph for each of `bytes`
+    pub fn decode(self, bytes: &[u8], glyphs: &mut Vec<Glyph>) {
+        glyphs.clear();
+        match self {
+            Encoding::Utf8 => decode_utf8(bytes, glyphs),
+            Encoding::Utf16Le => decode_utf16(bytes, glyphs, |a, b| u16::from_le_bytes([a, b])),
+            Encoding::Utf16Be => decode_utf16(bytes, glyphs, |a, b| u16::from_be_bytes([a, b])),
+            _ => {
+                for &byte in bytes {
+                    glyphs.push(match self.single_byte(byte) {
+                        Some(c) => glyph(c),
+                        None => Glyph::Byte,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Character of `byte` in a single byte encoding, `None` for control codes
+    fn single_byte(self, byte: u8) -> Option<char> {
+        match self {
+            Encoding::Ascii => match byte {
+                32..=126 => Some(byte.into()),
+                _ => None,
+            },
+            Encoding::Latin1 => match byte {
+                32..=126 | 0xA0..=0xFF => Some(byte.into()),
+                _ => None,
+            },
+            Encoding::Cp437 => match byte {
+                0x00 | 0xFF => None,
+                0x01..=0x1F => Some(CP437_LOW[byte as usize - 0x01]),
+                0x7F => Some('⌂'),
+                0x80..=0xFE => Some(CP437_HIGH[byte as usize - 0x80]),
+                _ => Some(byte.into()),
+            },
+            Encoding::Ebcdic => match byte {
+                0x40..=0xFE => Some(EBCDIC[byte as usize - 0x40]),
+                _ => None,
+            },
+            Encoding::Utf8 | Encoding::Utf16Le | Encoding::Utf16Be => None,
+        }
+    }
+}
+
+/// ASCII, or C1 where ASCII has none, control codes of the EBCDIC ones from 0x00 to 0x3F
+const EBCDIC_CONTROLS: [u8; 64] = [
+    0x00, 0x01, 0x02, 0x03, 0x9C, 0x09, 0x86, 0x7F, 0x97, 0x8D, 0x8E, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F,
+    0x10, 0x11, 0x12, 0x13, 0x9D, 0x85, 0x08, 0x87, 0x18, 0x19, 0x92, 0x8F, 0x1C, 0x1D, 0x1E, 0x1F,
+    0x80, 0x81, 0x82, 0x83, 0x84, 0x0A, 0x17, 0x1B, 0x88, 0x89, 0x8A, 0x8B, 0x8C, 0x05, 0x06, 0x07,
+    0x90, 0x91, 0x16, 0x93, 0x94, 0x95, 0x96, 0x04, 0x98, 0x99, 0x9A, 0x9B, 0x14, 0x15, 0x9E, 0x1A,
+];
+
+/// Glyphs of CP437 for 0x01 to 0x1F
+const CP437_LOW: [char; 31] = [
+    '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼', '►', '◄', '↕', '‼',
+    '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+/// Glyphs of CP437 for 0x80 to 0xFE, 0xFF is a non-breaking space
+const CP437_HIGH: [char; 127] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■',
+];
+
+/// Characters of EBCDIC code page 037 for 0x40 to 0xFE, bytes below 0x40 and 0xFF are controls
+const EBCDIC: [char; 191] = [
+    ' ', '\u{A0}', 'â', 'ä', 'à', 'á', 'ã', 'å', 'ç', 'ñ', '¢', '.', '<', '(', '+', '|', //
+    '&', 'é', 'ê', 'ë', 'è', 'í', 'î', 'ï', 'ì', 'ß', '!', '$', '*', ')', ';', '¬', //
+    '-', '/', 'Â', 'Ä', 'À', 'Á', 'Ã', 'Å', 'Ç', 'Ñ', '¦', ',', '%', '_', '>', '?', //
+    'ø', 'É', 'Ê', 'Ë', 'È', 'Í', 'Î', 'Ï', 'Ì', '`', ':', '#', '@', '\'', '=', '"', //
+    'Ø', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', '«', '»', 'ð', 'ý', 'þ', '±', //
+    '°', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 'ª', 'º', 'æ', '¸', 'Æ', '¤', //
+    'µ', '~', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '¡', '¿', 'Ð', 'Ý', 'Þ', '®', //
+    '^', '£', '¥', '·', '©', '§', '¶', '¼', '½', '¾', '[', ']', '¯', '¨', '´', '×', //
+    '{', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', '\u{AD}', 'ô', 'ö', 'ò', 'ó', 'õ', //
+    '}', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', '¹', 'û', 'ü', 'ù', 'ú', 'ÿ', //
+    '\\', '÷', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '²', 'Ô', 'Ö', 'Ò', 'Ó', 'Õ', //
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '³', 'Û', 'Ü', 'Ù', 'Ú',
+];
+
+/// Glyph for a decoded character, characters without a visible representation of their own are
+/// shown as bytes
+fn glyph(c: char) -> Glyph {
+    if c.is_control() || is_zero_width(c) {
+        Glyph::Byte
+    } else if is_wide(c) {
+        Glyph::Wide(c)
+    } else {
+        Glyph::Char(c)
+    }
+}
+
+/// Combining marks and format characters, which would merge with their neighbours
+fn is_zero_width(c: char) -> bool {
+    matches!(c as u32,
+        0x00AD
+        | 0x0300..=0x036F
+        | 0x0483..=0x0489
+        | 0x0591..=0x05BD
+        | 0x0610..=0x061A
+        | 0x064B..=0x065F
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x200B..=0x200F
+        | 0x2028..=0x202E
+        | 0x2060..=0x206F
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F
+        | 0xFEFF)
+}
+
+/// East Asian wide characters and emoji, which take up two columns in a terminal
+pub(crate) fn is_wide(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD)
+}
+
+/// Push `glyph` for the first byte of a character followed by continuations for the rest
+fn push_character(glyphs: &mut Vec<Glyph>, glyph: Glyph, length: usize) {
+    if glyph == Glyph::Byte {
+        glyphs.extend(iter::repeat_n(Glyph::Byte, length));
+    } else {
+        glyphs.push(glyph);
+        glyphs.extend(iter::repeat_n(Glyph::Continuation, length - 1));
+    }
+}
+
+fn is_utf8_continuation(byte: u8) -> bool {
+    byte & 0xC0 == 0x80
+}
+
+fn decode_utf8(bytes: &[u8], glyphs: &mut Vec<Glyph>) {
+    // continuations at the very start most likely belong to a character on the previous row
+    let mut i = 0;
+    while i < bytes.len() && i < 3 && is_utf8_continuation(bytes[i]) {
+        glyphs.push(Glyph::Continuation);
+        i += 1;
+    }
+
+    while i < bytes.len() {
+        let length = match bytes[i] {
+            0x00..=0x7F => 1,
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => 0,
+        };
+        if length == 0 {
+            glyphs.push(Glyph::Invalid);
+            i += 1;
+            continue;
+        }
+
+        let available = &bytes[i..];
+        if available.len() < length {
+            // a character cut short by the end of the slice is probably completed on the next
+            // row, so its bytes aren't treated as invalid
+            if available[1..].iter().all(|&b| is_utf8_continuation(b)) {
+                glyphs.extend(iter::repeat_n(Glyph::Byte, available.len()));
+                return;
+            }
+        }
+
+        match available
+            .get(..length)
+            .and_then(|sequence| str::from_utf8(sequence).ok())
+        {
+            Some(s) => {
+                let c = s.chars().next().unwrap();
+                push_character(glyphs, glyph(c), length);
+                i += length;
+            }
+            None => {
+                glyphs.push(Glyph::Invalid);
+                i += 1;
+            }
+        }
+    }
+}
+
+fn decode_utf16<F>(bytes: &[u8], glyphs: &mut Vec<Glyph>, unit: F)
+where
+    F: Fn(u8, u8) -> u16,
+{
+    let mut i = 0;
+    while i + 1 < bytes.len() {
+        let first = unit(bytes[i], bytes[i + 1]);
+        match first {
+            0xD800..=0xDBFF => {
+                if i + 3 >= bytes.len() {
+                    // the low surrogate may be on the next row
+                    break;
+                }
+                let second = unit(bytes[i + 2], bytes[i + 3]);
+                match char::decode_utf16([first, second]).next() {
+                    Some(Ok(c)) => {
+                        push_character(glyphs, glyph(c), 4);
+                        i += 4;
+                    }
+                    _ => {
+                        glyphs.extend(iter::repeat_n(Glyph::Invalid, 2));
+                        i += 2;
+                    }
+                }
+            }
+            0xDC00..=0xDFFF => {
+                glyphs.extend(iter::repeat_n(Glyph::Invalid, 2));
+                i += 2;
+            }
+            _ => {
+                let c = char::from_u32(first.into()).unwrap();
+                push_character(glyphs, glyph(c), 2);
+                i += 2;
+            }
+        }
+    }
+
+    // bytes left over at the end of the slice can't be decoded on their own
+    glyphs.extend(iter::repeat_n(Glyph::Byte, bytes.len() - i));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoding, Glyph};
+
+    fn decode(encoding: Encoding, bytes: &[u8]) -> Vec<Glyph> {
+        let mut glyphs = Vec::new();
+        encoding.decode(bytes, &mut glyphs);
+        assert_eq!(glyphs.len(), bytes.len());
+        glyphs
+    }
+
+    #[test]
+    fn ascii_shows_printable_characters() {
+        assert_eq!(
+            decode(Encoding::Ascii, b"a \x00\xE9"),
+            vec![Glyph::Char('a'), Glyph::Char(' '), Glyph::Byte, Glyph::Byte]
+        );
+    }
+
+    #[test]
+    fn utf8_marks_continuation_bytes() {
+        assert_eq!(
+            decode(Encoding::Utf8, "aé€".as_bytes()),
+            vec![
+                Glyph::Char('a'),
+                Glyph::Char('é'),
+                Glyph::Continuation,
+                Glyph::Char('€'),
+                Glyph::Continuation,
+                Glyph::Continuation,
+            ]
+        );
+        assert_eq!(
+            decode(Encoding::Utf8, "語".as_bytes())[0],
+            Glyph::Wide('語')
+        );
+    }
+
+    #[test]
+    fn utf8_marks_invalid_sequences() {
+        assert_eq!(
+            decode(Encoding::Utf8, b"\xC3a\xFFb"),
+            vec![
+                Glyph::Invalid,
+                Glyph::Char('a'),
+                Glyph::Invalid,
+                Glyph::Char('b')
+            ]
+        );
+    }
+
+    #[test]
+    fn utf8_sequences_cut_off_at_either_end_are_not_invalid() {
+        assert_eq!(
+            decode(Encoding::Utf8, b"\xA9a\xE2\x82"),
+            vec![
+                Glyph::Continuation,
+                Glyph::Char('a'),
+                Glyph::Byte,
+                Glyph::Byte
+            ]
+        );
+    }
+
+    #[test]
+    fn utf16_decodes_both_byte_orders() {
+        assert_eq!(
+            decode(Encoding::Utf16Le, b"h\x00\x3D\xD8\x00\xDE!"),
+            vec![
+                Glyph::Char('h'),
+                Glyph::Continuation,
+                Glyph::Wide('😀'),
+                Glyph::Continuation,
+                Glyph::Continuation,
+                Glyph::Continuation,
+                Glyph::Byte,
+            ]
+        );
+        assert_eq!(
+            decode(Encoding::Utf16Be, b"\x00h\xDC\x00"),
+            vec![
+                Glyph::Char('h'),
+                Glyph::Continuation,
+                Glyph::Invalid,
+                Glyph::Invalid
+            ]
+        );
+    }
+
+    #[test]
+    fn pictures_stand_in_for_control_codes() {
+        let ascii = Encoding::Ascii;
+        assert_eq!(ascii.picture(0x00), Glyph::Picture('␀'));
+        assert_eq!(ascii.picture(b'\n'), Glyph::Picture('␊'));
+        assert_eq!(ascii.picture(b'\r'), Glyph::Picture('␍'));
+        assert_eq!(ascii.picture(0x1B), Glyph::Picture('␛'));
+        assert_eq!(ascii.picture(0x80), Glyph::Picture('•'));
+        assert_eq!(ascii.picture(b'a'), Glyph::Byte);
+
+        let ebcdic = Encoding::Ebcdic;
+        assert_eq!(ebcdic.picture(0x00), Glyph::Picture('␀'));
+        assert_eq!(ebcdic.picture(0x25), Glyph::Picture('␊'));
+        assert_eq!(ebcdic.picture(0x0D), Glyph::Picture('␍'));
+        assert_eq!(ebcdic.picture(0x27), Glyph::Picture('␛'));
+        assert_eq!(ebcdic.picture(0x0A), Glyph::Picture('•'));
+        assert_eq!(ebcdic.picture(0xFF), Glyph::Picture('•'));
+    }
+
+    #[test]
+    fn single_byte_encodings() {
+        assert_eq!(decode(Encoding::Latin1, b"\xE9"), vec![Glyph::Char('é')]);
+        assert_eq!(decode(Encoding::Latin1, b"\x85"), vec![Glyph::Byte]);
+        assert_eq!(
+            decode(Encoding::Cp437, b"\x01\xB0\xFF"),
+            vec![Glyph::Char('☺'), Glyph::Char('░'), Glyph::Byte]
+        );
+        assert_eq!(
+            decode(Encoding::Ebcdic, b"\xC8\x89\x5A\x15"),
+            vec![
+                Glyph::Char('H'),
+                Glyph::Char('i'),
+                Glyph::Char('!'),
+                Glyph::Byte
+            ]
+        );
+    }
+}
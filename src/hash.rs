@@ -0,0 +1,500 @@
+//! Checksums and hashes of byte ranges, for checking blocks against known values
+use std::fmt::Write;
+
+/// A checksum or hash algorithm, named as in the `:hash` command
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Algorithm {
+    Crc(&'static Crc),
+    Adler32,
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+/// Parameters of a CRC, as given in the catalogue of parametrised CRC algorithms
+#[derive(Debug, PartialEq)]
+pub struct Crc {
+    pub name: &'static str,
+    /// Bits in the result, from 8 to 32
+    width: u32,
+    poly: u32,
+    init: u32,
+    /// Whether bytes are processed least significant bit first
+    reflect: bool,
+    xor_out: u32,
+}
+
+/// The CRC presets that can be asked for by name
+pub const CRCS: [Crc; 10] = [
+    Crc {
+        name: "crc8",
+        width: 8,
+        poly: 0x07,
+        init: 0,
+        reflect: false,
+        xor_out: 0,
+    },
+    Crc {
+        name: "crc8-maxim",
+        width: 8,
+        poly: 0x31,
+        init: 0,
+        reflect: true,
+        xor_out: 0,
+    },
+    Crc {
+        name: "crc16",
+        width: 16,
+        poly: 0x8005,
+        init: 0,
+        reflect: true,
+        xor_out: 0,
+    },
+    Crc {
+        name: "crc16-modbus",
+        width: 16,
+        poly: 0x8005,
+        init: 0xFFFF,
+        reflect: true,
+        xor_out: 0,
+    },
+    Crc {
+        name: "crc16-ccitt",
+        width: 16,
+        poly: 0x1021,
+        init: 0xFFFF,
+        reflect: false,
+        xor_out: 0,
+    },
+    Crc {
+        name: "crc16-xmodem",
+        width: 16,
+        poly: 0x1021,
+        init: 0,
+        reflect: false,
+        xor_out: 0,
+    },
+    Crc {
+        name: "crc16-kermit",
+        width: 16,
+        poly: 0x1021,
+        init: 0,
+        reflect: true,
+        xor_out: 0,
+    },
+    Crc {
+        name: "crc32",
+        width: 32,
+        poly: 0x04C1_1DB7,
+        init: 0xFFFF_FFFF,
+        reflect: true,
+        xor_out: 0xFFFF_FFFF,
+    },
+    Crc {
+        name: "crc32c",
+        width: 32,
+        poly: 0x1EDC_6F41,
+        init: 0xFFFF_FFFF,
+        reflect: true,
+        xor_out: 0xFFFF_FFFF,
+    },
+    Crc {
+        name: "crc32-bzip2",
+        width: 32,
+        poly: 0x04C1_1DB7,
+        init: 0xFFFF_FFFF,
+        reflect: false,
+        xor_out: 0xFFFF_FFFF,
+    },
+];
+
+impl Algorithm {
+    /// Look up an algorithm by the name used in commands
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "adler32" => Some(Algorithm::Adler32),
+            "md5" => Some(Algorithm::Md5),
+            "sha1" => Some(Algorithm::Sha1),
+            "sha256" => Some(Algorithm::Sha256),
+            _ => CRCS.iter().find(|crc| crc.name == name).map(Algorithm::Crc),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Crc(crc) => crc.name,
+            Algorithm::Adler32 => "adler32",
+            Algorithm::Md5 => "md5",
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Sha256 => "sha256",
+        }
+    }
+
+    /// The checksum or digest of `data` in hex, as it's usually written
+    pub fn digest(self, data: &[u8]) -> String {
+        match self {
+            Algorithm::Crc(crc) => {
+                format!(
+                    "{:0width$x}",
+                    crc.compute(data),
+                    width = crc.width as usize / 4
+                )
+            }
+            Algorithm::Adler32 => format!("{:08x}", adler32(data)),
+            Algorithm::Md5 => hex(&md5(data)),
+            Algorithm::Sha1 => hex(&sha1(data)),
+            Algorithm::Sha256 => hex(&sha256(data)),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(text, "{:02x}", byte).unwrap();
+    }
+    text
+}
+
+impl Crc {
+    pub fn compute(&self, data: &[u8]) -> u32 {
+        let mask = u32::MAX >> (32 - self.width);
+        let table = self.table();
+        let mut crc = if self.reflect {
+            reflect(self.init, self.width)
+        } else {
+            self.init
+        };
+        for &byte in data {
+            crc = if self.reflect {
+                table[((crc ^ u32::from(byte)) & 0xFF) as usize] ^ (crc >> 8)
+            } else {
+                let index = ((crc >> (self.width - 8)) ^ u32::from(byte)) & 0xFF;
+                (table[index as usize] ^ (crc << 8)) & mask
+            };
+        }
+        (crc ^ self.xor_out) & mask
+    }
+
+    /// The CRC of each byte value on its own, so data is processed a byte at a time
+    fn table(&self) -> [u32; 256] {
+        let mut table = [0; 256];
+        for (byte, entry) in table.iter_mut().enumerate() {
+            let mut crc = byte as u32;
+            if self.reflect {
+                let poly = reflect(self.poly, self.width);
+                for _ in 0..8 {
+                    crc = if crc & 1 == 1 {
+                        (crc >> 1) ^ poly
+                    } else {
+                        crc >> 1
+                    };
+                }
+            } else {
+                let top = 1 << (self.width - 1);
+                crc <<= self.width - 8;
+                for _ in 0..8 {
+                    crc = if crc & top != 0 {
+                        (crc << 1) ^ self.poly
+                    } else {
+                        crc << 1
+                    };
+                }
+                crc &= u32::MAX >> (32 - self.width);
+            }
+            *entry = crc;
+        }
+        table
+    }
+}
+
+/// The lowest `width` bits of `value` in reverse order
+fn reflect(value: u32, width: u32) -> u32 {
+    value.reverse_bits() >> (32 - width)
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    // most bytes that can be summed before `b` could overflow
+    const CHUNK: usize = 5552;
+
+    let (mut a, mut b) = (1, 0);
+    for chunk in data.chunks(CHUNK) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    (b << 16) | a
+}
+
+/// Pass each 64 byte block of `data` to `compress`, followed by the padding MD5 and the SHA
+/// family add: a one bit, zeroes, then the length in bits
+fn blocks<F: FnMut(&[u8])>(data: &[u8], big_endian: bool, mut compress: F) {
+    let mut chunks = data.chunks_exact(64);
+    for block in &mut chunks {
+        compress(block);
+    }
+
+    let bits = (data.len() as u64).wrapping_mul(8);
+    let mut tail = chunks.remainder().to_vec();
+    tail.push(0x80);
+    while tail.len() % 64 != 56 {
+        tail.push(0);
+    }
+    tail.extend_from_slice(&if big_endian {
+        bits.to_be_bytes()
+    } else {
+        bits.to_le_bytes()
+    });
+    for block in tail.chunks_exact(64) {
+        compress(block);
+    }
+}
+
+fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    let constants: Vec<u32> = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32)
+        .collect();
+
+    let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+    blocks(data, false, |block| {
+        let mut words = [0u32; 16];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f
+                .wrapping_add(a)
+                .wrapping_add(constants[i])
+                .wrapping_add(words[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[i / 16 * 4 + i % 4]));
+        }
+        for (value, added) in state.iter_mut().zip(&[a, b, c, d]) {
+            *value = value.wrapping_add(*added);
+        }
+    });
+
+    let mut digest = [0; 16];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(&state) {
+        bytes.copy_from_slice(&value.to_le_bytes());
+    }
+    digest
+}
+
+/// The big endian words of a block of the SHA family
+fn words(block: &[u8], words: &mut [u32]) {
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [
+        0x6745_2301,
+        0xEFCD_AB89,
+        0x98BA_DCFE,
+        0x1032_5476,
+        0xC3D2_E1F0,
+    ];
+    blocks(data, true, |block| {
+        let mut w = [0u32; 80];
+        words(block, &mut w[..16]);
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5A82_7999),
+                1 => (b ^ c ^ d, 0x6ED9_EBA1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, added) in state.iter_mut().zip(&[a, b, c, d, e]) {
+            *value = value.wrapping_add(*added);
+        }
+    });
+
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(&state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+const SHA256_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    blocks(data, true, |block| {
+        let mut w = [0u32; 64];
+        words(block, &mut w[..16]);
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for (&word, &k) in w.iter().zip(&SHA256_CONSTANTS) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+        for (value, added) in state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+            *value = value.wrapping_add(*added);
+        }
+    });
+
+    let mut digest = [0; 32];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(&state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECK: &[u8] = b"123456789";
+    const TWO_BLOCKS: &[u8] = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+
+    fn digest(name: &str, data: &[u8]) -> String {
+        Algorithm::from_name(name).unwrap().digest(data)
+    }
+
+    #[test]
+    fn crcs_match_the_catalogue_check_values() {
+        let expected = [
+            ("crc8", "f4"),
+            ("crc8-maxim", "a1"),
+            ("crc16", "bb3d"),
+            ("crc16-modbus", "4b37"),
+            ("crc16-ccitt", "29b1"),
+            ("crc16-xmodem", "31c3"),
+            ("crc16-kermit", "2189"),
+            ("crc32", "cbf43926"),
+            ("crc32c", "e3069283"),
+            ("crc32-bzip2", "fc891918"),
+        ];
+        for &(name, check) in &expected {
+            assert_eq!(digest(name, CHECK), check, "{}", name);
+        }
+        assert_eq!(digest("crc32", b""), "00000000");
+    }
+
+    #[test]
+    fn adler32_matches_known_values() {
+        assert_eq!(digest("adler32", b""), "00000001");
+        assert_eq!(digest("adler32", b"Wikipedia"), "11e60398");
+        assert_eq!(digest("adler32", &[0xFF; 100_000]), "149a302c");
+    }
+
+    #[test]
+    fn hashes_match_the_standard_vectors() {
+        assert_eq!(digest("md5", b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(digest("md5", b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            digest("md5", &b"1234567890".repeat(8)),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+
+        assert_eq!(
+            digest("sha1", b""),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+        );
+        assert_eq!(
+            digest("sha1", b"abc"),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            digest("sha1", TWO_BLOCKS),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+
+        assert_eq!(
+            digest("sha256", b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            digest("sha256", b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            digest("sha256", TWO_BLOCKS),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for name in &[
+            "crc8",
+            "crc16-kermit",
+            "crc32c",
+            "adler32",
+            "md5",
+            "sha1",
+            "sha256",
+        ] {
+            assert_eq!(Algorithm::from_name(name).unwrap().name(), *name);
+        }
+        assert_eq!(Algorithm::from_name("crc64"), None);
+    }
+}
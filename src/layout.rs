@@ -0,0 +1,412 @@
+//! Arrangement of windows within the data frame
+//!
+//! Windows are leaves of a binary tree, each split divides its area between two children
+//! according to a ratio so layouts adapt when the area they're computed for changes size.
+use crate::screen::{Dimension, Point};
+
+/// Ratios are stored in thousandths of the split area
+const RATIO_SCALE: u32 = 1000;
+
+/// Orientation of the divider between two windows
+///
+/// Following vim, a horizontal split stacks windows on top of each other and a vertical split
+/// places them side by side.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Direction {
+    Horizontal,
+    Vertical,
+}
+
+/// A rectangular area of the terminal, positions are 1-based as in termion
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub fn position(&self) -> Point {
+        Point {
+            x: self.x,
+            y: self.y,
+        }
+    }
+
+    pub fn dimensions(&self) -> Dimension {
+        Dimension {
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Split into two areas, vertical splits leave a column between them for a separator
+    fn split(&self, direction: Direction, ratio: u32) -> (Rect, Rect) {
+        match direction {
+            Direction::Horizontal => {
+                let first = share(self.height, ratio);
+                (
+                    Rect {
+                        height: first,
+                        ..*self
+                    },
+                    Rect {
+                        y: self.y + first,
+                        height: self.height - first,
+                        ..*self
+                    },
+                )
+            }
+            Direction::Vertical => {
+                let available = self.width.saturating_sub(1);
+                let first = share(available, ratio);
+                let separator = if self.width > 0 { 1 } else { 0 };
+                (
+                    Rect {
+                        width: first,
+                        ..*self
+                    },
+                    Rect {
+                        x: self.x + first + separator,
+                        width: available - first,
+                        ..*self
+                    },
+                )
+            }
+        }
+    }
+}
+
+/// Portion of `total` given to the first child of a split, each child keeps at least one cell
+/// when there's space for it
+fn share(total: u16, ratio: u32) -> u16 {
+    if total < 2 {
+        return total;
+    }
+    let first = (total as u32 * ratio / RATIO_SCALE) as u16;
+    first.max(1).min(total - 1)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Window(usize),
+    Split {
+        direction: Direction,
+        ratio: u32,
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+}
+
+/// Tree of windows, identified by their index in the screen's window list
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    root: Node,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            root: Node::Window(0),
+        }
+    }
+}
+
+impl Layout {
+    /// Compute the area of every window within `area`, in window order
+    pub fn compute(&self, area: Rect) -> Vec<(usize, Rect)> {
+        let mut areas = Vec::new();
+        compute(&self.root, area, &mut areas);
+        areas.sort_by_key(|&(window, _)| window);
+        areas
+    }
+
+    /// Position of vertical separators within `area`, as `(x, y, height)`
+    pub fn separators(&self, area: Rect) -> Vec<(u16, u16, u16)> {
+        let mut separators = Vec::new();
+        collect_separators(&self.root, area, &mut separators);
+        separators
+    }
+
+    /// Split `window` in two, `new_window` takes the top or left half
+    pub fn split(&mut self, window: usize, direction: Direction, new_window: usize) {
+        if let Some(node) = find(&mut self.root, window) {
+            *node = Node::Split {
+                direction,
+                ratio: RATIO_SCALE / 2,
+                first: Box::new(Node::Window(new_window)),
+                second: Box::new(Node::Window(window)),
+            };
+        }
+    }
+
+    /// Remove `window`, its sibling takes over its space
+    ///
+    /// Windows with a higher index are renumbered to match removal from the window list. The
+    /// last window can't be removed.
+    pub fn remove(&mut self, window: usize) {
+        if remove(&mut self.root, window) {
+            renumber(&mut self.root, window);
+        }
+    }
+
+    /// Resize `window` to `size` cells along `direction`
+    ///
+    /// The closest split in that direction containing the window is adjusted, when there's none
+    /// the window already fills the available space and nothing happens.
+    pub fn resize(&mut self, window: usize, direction: Direction, size: u16, area: Rect) {
+        resize(&mut self.root, window, direction, size, area);
+    }
+}
+
+fn compute(node: &Node, area: Rect, areas: &mut Vec<(usize, Rect)>) {
+    match node {
+        Node::Window(window) => areas.push((*window, area)),
+        Node::Split {
+            direction,
+            ratio,
+            first,
+            second,
+        } => {
+            let (a, b) = area.split(*direction, *ratio);
+            compute(first, a, areas);
+            compute(second, b, areas);
+        }
+    }
+}
+
+fn collect_separators(node: &Node, area: Rect, separators: &mut Vec<(u16, u16, u16)>) {
+    if let Node::Split {
+        direction,
+        ratio,
+        first,
+        second,
+    } = node
+    {
+        let (a, b) = area.split(*direction, *ratio);
+        if *direction == Direction::Vertical && area.width > 0 {
+            separators.push((a.x + a.width, area.y, area.height));
+        }
+        collect_separators(first, a, separators);
+        collect_separators(second, b, separators);
+    }
+}
+
+fn contains(node: &Node, window: usize) -> bool {
+    match node {
+        Node::Window(w) => *w == window,
+        Node::Split { first, second, .. } => contains(first, window) || contains(second, window),
+    }
+}
+
+fn find(node: &mut Node, window: usize) -> Option<&mut Node> {
+    match node {
+        Node::Window(w) if *w == window => Some(node),
+        Node::Window(..) => None,
+        Node::Split { first, second, .. } => {
+            if contains(first, window) {
+                find(first, window)
+            } else {
+                find(second, window)
+            }
+        }
+    }
+}
+
+fn remove(node: &mut Node, window: usize) -> bool {
+    let replacement = match node {
+        Node::Window(..) => return false,
+        Node::Split { first, second, .. } => {
+            if **first == Node::Window(window) {
+                (**second).clone()
+            } else if **second == Node::Window(window) {
+                (**first).clone()
+            } else {
+                return remove(first, window) || remove(second, window);
+            }
+        }
+    };
+    *node = replacement;
+    true
+}
+
+fn renumber(node: &mut Node, removed: usize) {
+    match node {
+        Node::Window(w) => {
+            if *w > removed {
+                *w -= 1;
+            }
+        }
+        Node::Split { first, second, .. } => {
+            renumber(first, removed);
+            renumber(second, removed);
+        }
+    }
+}
+
+fn resize(node: &mut Node, window: usize, target: Direction, size: u16, area: Rect) -> bool {
+    if let Node::Split {
+        direction,
+        ratio,
+        first,
+        second,
+    } = node
+    {
+        let (a, b) = area.split(*direction, *ratio);
+        let in_first = contains(first, window);
+
+        // prefer the innermost matching split
+        let resized = if in_first {
+            resize(first, window, target, size, a)
+        } else {
+            resize(second, window, target, size, b)
+        };
+        if resized || *direction != target {
+            return resized;
+        }
+
+        let total = match *direction {
+            Direction::Horizontal => area.height,
+            Direction::Vertical => area.width.saturating_sub(1),
+        };
+        if total == 0 {
+            return true;
+        }
+        let size = size.min(total);
+        let first_size = if in_first { size } else { total - size };
+        // round up so computing the layout gives back exactly the requested size
+        *ratio = (first_size as u32 * RATIO_SCALE).div_ceil(total as u32);
+        return true;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area() -> Rect {
+        Rect {
+            x: 1,
+            y: 1,
+            width: 81,
+            height: 20,
+        }
+    }
+
+    #[test]
+    fn a_single_window_fills_the_area() {
+        assert_eq!(Layout::default().compute(area()), vec![(0, area())]);
+    }
+
+    #[test]
+    fn horizontal_splits_stack_windows() {
+        let mut layout = Layout::default();
+        layout.split(0, Direction::Horizontal, 1);
+
+        assert_eq!(
+            layout.compute(area()),
+            vec![
+                (
+                    0,
+                    Rect {
+                        y: 11,
+                        height: 10,
+                        ..area()
+                    }
+                ),
+                (
+                    1,
+                    Rect {
+                        height: 10,
+                        ..area()
+                    }
+                ),
+            ]
+        );
+        assert_eq!(layout.separators(area()), vec![]);
+    }
+
+    #[test]
+    fn vertical_splits_leave_room_for_a_separator() {
+        let mut layout = Layout::default();
+        layout.split(0, Direction::Vertical, 1);
+
+        assert_eq!(
+            layout.compute(area()),
+            vec![
+                (
+                    0,
+                    Rect {
+                        x: 42,
+                        width: 40,
+                        ..area()
+                    }
+                ),
+                (
+                    1,
+                    Rect {
+                        width: 40,
+                        ..area()
+                    }
+                ),
+            ]
+        );
+        assert_eq!(layout.separators(area()), vec![(41, 1, 20)]);
+    }
+
+    #[test]
+    fn removing_a_window_gives_its_space_to_the_sibling() {
+        let mut layout = Layout::default();
+        layout.split(0, Direction::Vertical, 1);
+        layout.split(1, Direction::Horizontal, 2);
+
+        layout.remove(1);
+        assert_eq!(layout.compute(area()).len(), 2);
+        assert_eq!(layout.compute(area())[1].1.width, 40);
+
+        layout.remove(1);
+        layout.remove(0);
+        assert_eq!(layout, Layout::default());
+    }
+
+    #[test]
+    fn resizing_adjusts_the_closest_matching_split() {
+        let mut layout = Layout::default();
+        layout.split(0, Direction::Horizontal, 1);
+        layout.split(1, Direction::Vertical, 2);
+
+        layout.resize(2, Direction::Horizontal, 5, area());
+        let areas = layout.compute(area());
+        assert_eq!(areas[2].1.height, 5);
+        assert_eq!(areas[0].1.height, 15);
+
+        layout.resize(0, Direction::Horizontal, 18, area());
+        assert_eq!(layout.compute(area())[0].1.height, 18);
+
+        layout.resize(2, Direction::Vertical, 10, area());
+        assert_eq!(layout.compute(area())[2].1.width, 10);
+    }
+
+    #[test]
+    fn tiny_areas_do_not_underflow() {
+        let mut layout = Layout::default();
+        layout.split(0, Direction::Vertical, 1);
+        layout.split(0, Direction::Horizontal, 2);
+
+        for width in 0..4 {
+            for height in 0..4 {
+                let area = Rect {
+                    x: 1,
+                    y: 1,
+                    width,
+                    height,
+                };
+                for (_, rect) in layout.compute(area) {
+                    assert!(rect.width <= width && rect.height <= height);
+                }
+            }
+        }
+    }
+}
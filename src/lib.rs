@@ -3,9 +3,28 @@
 #[macro_use]
 extern crate nom;
 
+pub mod bookmark;
+pub mod buffer;
+pub mod clipboard;
 pub mod command_prompt;
 pub mod context;
+pub mod diff;
+pub mod elf;
+pub mod encoding;
+pub mod hash;
+pub mod layout;
+pub mod macros;
+pub mod magic;
+pub mod minimap;
+pub mod overlay;
+pub mod pe;
+pub mod resize;
 pub mod screen;
+pub mod script;
+pub mod session;
+pub mod strings;
+pub mod template;
+pub mod transform;
 pub use crate::screen::Frame;
 pub use crate::screen::State;
 
@@ -14,28 +33,68 @@ pub mod status_bar {
 
     use termion::{color, cursor};
 
+    use super::encoding::Encoding;
     use super::screen::Screen;
 
     pub fn render<T: Write>(screen: &mut Screen<T>, path: &str) {
-        let message_right = format!(
+        let mut message_right = format!(
             "{}|o:{}|sy:{}|sx:{}|w:{}",
             &screen.state, screen.offset, screen.scroll_y, screen.scroll_x, screen.bytes_per_row
         );
-        let bar = line_of_spaces(screen.status_bar_dimensions().width as usize);
+        if screen.format.group_size > 1 {
+            message_right.push_str(&format!("|g:{}", screen.format.group_size));
+        }
+        if screen.format.encoding != Encoding::Ascii {
+            message_right.push_str(&format!("|{}", screen.format.encoding.name()));
+        }
+        if screen.format.little_endian {
+            message_right.push_str("|le");
+        }
+        if screen.wrap {
+            message_right.push_str("|wrap");
+        }
+        if let Some(count) = screen.count() {
+            message_right = format!("{}|{}", count, message_right);
+        }
+        if let Some(register) = screen.macros.recording() {
+            message_right.push_str(&format!("|recording @{}", register));
+        }
+        let width = screen.status_bar_dimensions().width as usize;
+        let bar = line_of_spaces(width);
 
         let status_bar_position = screen.status_bar_position();
 
+        // the right hand message is dropped when there's no room for it, the path is truncated
+        let mut path = match screen.buffer().context.file_type {
+            Some(file_type) => format!("{} [{}]", path, file_type.name()),
+            None => path.to_owned(),
+        };
+        match screen.buffer().edits.len() {
+            0 => (),
+            1 => path.push_str(" [1 edit]"),
+            edits => path.push_str(&format!(" [{} edits]", edits)),
+        }
+        if screen.buffer().preview.is_some() {
+            path.push_str(" [preview]");
+        }
+        let path: String = path.chars().take(width).collect();
+        let message_right = if message_right.len() < width {
+            format!(
+                "{}{}",
+                cursor::Goto((width - message_right.len()) as u16, status_bar_position.y),
+                message_right
+            )
+        } else {
+            String::new()
+        };
+
         let bar_full = format!(
-            "{}{}{}{}{}{}{}{}{}",
+            "{}{}{}{}{}{}{}{}",
             color::Bg(color::Black),
             color::Fg(color::White),
             bar,
             cursor::Goto(status_bar_position.x, status_bar_position.y),
             path,
-            cursor::Goto(
-                (bar.len() - message_right.len()) as u16,
-                status_bar_position.y
-            ),
             message_right,
             color::Bg(color::Reset),
             color::Fg(color::Reset),
@@ -67,23 +126,46 @@ pub mod status_bar {
 }
 
 pub mod byte_display {
+    use std::cmp;
     use std::io::Write;
 
-    use super::line::Line;
-    use super::screen::Screen;
-    use termion::{clear, cursor};
+    use super::buffer::View;
+    use super::line::{Format, Highlight, Line, Palette};
+    use super::screen::{Dimension, Point};
+    use termion::cursor;
 
-    pub fn render<T: Write>(screen: &mut Screen<T>, data: &[u8]) {
-        use std::cmp;
+    /// Render rows of `data` into the area at `position`
+    ///
+    /// Bytes are coloured by class when given a `palette`, and drawn on a coloured background
+    /// when in one of `highlights`. `data` starts at the view's offset, highlights are positioned
+    /// from the start of the buffer.
+    pub fn render<T: Write>(
+        out: &mut T,
+        view: &View,
+        position: Point,
+        dimensions: Dimension,
+        data: &[u8],
+        palette: Option<Palette>,
+        highlights: &[Highlight],
+    ) {
+        if view.wrap {
+            return render_wrapped(out, view, position, dimensions, data, palette, highlights);
+        }
 
-        let scroll = screen.scroll_y;
-        let bytes_per_row = screen.bytes_per_row;
-        let main_panel_height = screen.data_frame_height();
+        let scroll = view.scroll_y;
+        let bytes_per_row = view.bytes_per_row;
+        let main_panel_height = dimensions.height;
         let mut rows = data.chunks(bytes_per_row).skip(scroll);
 
-        let mut line = Line::new(screen.data_frame_width() as usize, screen.text_display_mode);
+        let mut line = Line::with_format(dimensions.width as usize, view.format);
+        line.palette(palette);
+        line.highlights(highlights);
+
+        // scrolling always starts at a group boundary so words aren't split
+        let scroll_x = view.scroll_x - view.scroll_x % view.format.group_size;
 
         for i in 0..main_panel_height {
+            let row_start = view.offset + (scroll + i as usize) * bytes_per_row;
             if let Some(row) = rows.next() {
                 // construct and print a view into the row, which satisfies two conditions:
                 //  * contains only as many bytes as can fit onto a line
@@ -97,120 +179,627 @@ pub mod byte_display {
                 // We constrain the start index by the end index to ensure if we're ever told to
                 // scroll past the end of the row (probably shouldn't happen) we don't blow up.
                 let end = cmp::min(
-                    screen.scroll_x + max_bytes(screen.data_frame_width()),
+                    scroll_x + max_bytes(dimensions.width, &view.format),
                     row.len(),
                 );
-                let start = cmp::min(screen.scroll_x, end);
+                let start = cmp::min(scroll_x, end);
                 let view = &row[start..end];
 
                 write!(
-                    screen.out,
+                    out,
                     "{}{}",
-                    cursor::Goto(1, (i + 1) as u16),
-                    line.format(view),
+                    cursor::Goto(position.x, position.y + i),
+                    line.format_at(view, row_start + start),
                 )
                 .unwrap();
             } else {
+                // blank the row with spaces rather than clearing the line, other windows may
+                // share it
                 write!(
-                    screen.out,
+                    out,
                     "{}{}",
-                    cursor::Goto(1, (i + 1) as u16),
-                    clear::CurrentLine
+                    cursor::Goto(position.x, position.y + i),
+                    line.format(&[]),
                 )
                 .unwrap();
             }
         }
     }
 
-    /// Calculate number of bytes which can be displayed per line
-    fn max_bytes(line_length: u16) -> usize {
-        if line_length > 0 {
-            (line_length as usize + 1) / 3
+    /// Marker shown in the gutter of continuation lines when wrapping
+    const CONTINUATION_MARKER: char = '↪';
+
+    /// Render rows split across as many lines as it takes to show every byte
+    ///
+    /// A one column gutter on the left marks continuation lines. Scrolling vertically moves
+    /// through rows, so the top line always shows the start of a row.
+    fn render_wrapped<T: Write>(
+        out: &mut T,
+        view: &View,
+        position: Point,
+        dimensions: Dimension,
+        data: &[u8],
+        palette: Option<Palette>,
+        highlights: &[Highlight],
+    ) {
+        let line_width = dimensions.width.saturating_sub(1);
+        let bytes_per_line = max_bytes(line_width, &view.format);
+        let mut line = Line::with_format(line_width as usize, view.format);
+        line.palette(palette);
+        line.highlights(highlights);
+        let blank = Line::with_format(dimensions.width as usize, view.format)
+            .format(&[])
+            .to_owned();
+
+        let mut rows = data.chunks(view.bytes_per_row).skip(view.scroll_y);
+        let mut row_start = view.offset + view.scroll_y * view.bytes_per_row;
+        let mut y = 0;
+
+        while y < dimensions.height {
+            match rows.next() {
+                Some(row) if bytes_per_line > 0 => {
+                    let start = row_start;
+                    row_start += view.bytes_per_row;
+                    for (i, chunk) in row.chunks(bytes_per_line).enumerate() {
+                        if y >= dimensions.height {
+                            break;
+                        }
+                        let marker = if i == 0 { ' ' } else { CONTINUATION_MARKER };
+                        write!(
+                            out,
+                            "{}{}{}",
+                            cursor::Goto(position.x, position.y + y),
+                            marker,
+                            line.format_at(chunk, start + i * bytes_per_line),
+                        )
+                        .unwrap();
+                        y += 1;
+                    }
+                }
+                _ => {
+                    write!(out, "{}{}", cursor::Goto(position.x, position.y + y), blank).unwrap();
+                    y += 1;
+                }
+            }
+        }
+    }
+
+    /// Number of lines a row of `bytes_per_row` bytes takes up when wrapped to `line_length`
+    pub fn lines_per_row(bytes_per_row: usize, line_length: u16, format: &Format) -> usize {
+        let bytes_per_line = max_bytes(line_length.saturating_sub(1), format);
+        if bytes_per_line == 0 {
+            1
         } else {
-            0
+            cmp::max(1, bytes_per_row.div_ceil(bytes_per_line))
         }
     }
 
+    /// Calculate number of bytes which can be displayed per line
+    fn max_bytes(line_length: u16, format: &Format) -> usize {
+        format.bytes_in(line_length as usize)
+    }
+
     #[cfg(test)]
     mod tests {
-        use super::max_bytes;
+        use super::{lines_per_row, max_bytes, render};
+        use crate::buffer::View;
+        use crate::line::{Format, Highlight, Mode};
+        use crate::screen::{Dimension, Point};
+
+        fn render_to_string(view: &View, width: u16, height: u16, data: &[u8]) -> String {
+            let mut out = Vec::new();
+            render(
+                &mut out,
+                view,
+                Point { x: 1, y: 1 },
+                Dimension { width, height },
+                data,
+                None,
+                &[],
+            );
+            String::from_utf8(out).unwrap()
+        }
+
+        #[test]
+        fn wrapping_splits_rows_onto_continuation_lines() {
+            let view = View {
+                bytes_per_row: 4,
+                wrap: true,
+                ..View::default()
+            };
+            let output = render_to_string(&view, 7, 5, &[1, 2, 3, 4, 5, 6]);
+
+            assert_eq!(
+                output,
+                "\x1b[1;1H 01 02 \
+                 \x1b[2;1H↪03 04 \
+                 \x1b[3;1H 05 06 \
+                 \x1b[4;1H       \
+                 \x1b[5;1H       "
+            );
+        }
+
+        #[test]
+        fn wrapping_skips_rows_when_scrolled() {
+            let view = View {
+                bytes_per_row: 4,
+                scroll_y: 1,
+                wrap: true,
+                ..View::default()
+            };
+            let output = render_to_string(&view, 7, 1, &[1, 2, 3, 4, 5, 6]);
+
+            assert_eq!(output, "\x1b[1;1H 05 06 ");
+        }
+
+        #[test]
+        fn highlights_are_positioned_from_the_start_of_the_buffer() {
+            let view = View {
+                offset: 2,
+                bytes_per_row: 2,
+                scroll_y: 1,
+                ..View::default()
+            };
+            let highlights = [Highlight {
+                range: 5..6,
+                colour: 24,
+            }];
+            let mut out = Vec::new();
+            render(
+                &mut out,
+                &view,
+                Point { x: 1, y: 1 },
+                Dimension {
+                    width: 5,
+                    height: 1,
+                },
+                &[2, 3, 4, 5],
+                None,
+                &highlights,
+            );
+
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "\x1b[1;1H04 \x1b[48;5;24m05\x1b[49m"
+            );
+        }
+
+        #[test]
+        fn lines_per_row_accounts_for_the_gutter() {
+            assert_eq!(lines_per_row(4, 9, &Format::default()), 2);
+            assert_eq!(lines_per_row(4, 12, &Format::default()), 1);
+            assert_eq!(lines_per_row(32, 1, &Format::default()), 1);
+            assert_eq!(lines_per_row(32, 0, &Format::default()), 1);
+        }
+
+        #[test]
+        fn rendering_never_panics_in_tiny_areas() {
+            let data = [0xAB; 100];
+            for &wrap in &[false, true] {
+                for width in 0..12 {
+                    for height in 0..4 {
+                        let view = View {
+                            wrap,
+                            scroll_x: 3,
+                            ..View::default()
+                        };
+                        render_to_string(&view, width, height, &data);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn grouped_rows_scroll_by_whole_groups() {
+            let view = View {
+                bytes_per_row: 8,
+                scroll_x: 3,
+                format: Format {
+                    group_size: 2,
+                    ..Format::default()
+                },
+                ..View::default()
+            };
+            let output = render_to_string(&view, 10, 1, &[1, 2, 3, 4, 5, 6, 7, 8]);
+
+            assert_eq!(output, "\x1b[1;1H0304 0506 ");
+        }
+
+        #[test]
+        fn max_bytes_fits_whole_groups() {
+            let format = Format {
+                group_size: 4,
+                ..Format::default()
+            };
+            assert_eq!(max_bytes(7, &format), 3);
+            assert_eq!(max_bytes(8, &format), 4);
+            assert_eq!(max_bytes(16, &format), 4);
+            assert_eq!(max_bytes(17, &format), 8);
+        }
+
+        #[test]
+        fn max_bytes_depends_on_cell_width() {
+            let format = Format {
+                mode: Mode::Binary,
+                ..Format::default()
+            };
+            assert_eq!(max_bytes(7, &format), 0);
+            assert_eq!(max_bytes(16, &format), 1);
+            assert_eq!(max_bytes(17, &format), 2);
+        }
 
         #[test]
         fn max_bytes_when_line_length_is_less_than_2() {
-            assert_eq!(max_bytes(1), 0);
-            assert_eq!(max_bytes(0), 0);
+            assert_eq!(max_bytes(1, &Format::default()), 0);
+            assert_eq!(max_bytes(0, &Format::default()), 0);
         }
 
         #[test]
         fn max_bytes_when_line_length_accounting_for_padding() {
-            assert_eq!(max_bytes(2), 1);
-            assert_eq!(max_bytes(3), 1);
-            assert_eq!(max_bytes(4), 1);
-            assert_eq!(max_bytes(5), 2);
-            assert_eq!(max_bytes(6), 2);
-            assert_eq!(max_bytes(7), 2);
-            assert_eq!(max_bytes(8), 3);
+            assert_eq!(max_bytes(2, &Format::default()), 1);
+            assert_eq!(max_bytes(3, &Format::default()), 1);
+            assert_eq!(max_bytes(4, &Format::default()), 1);
+            assert_eq!(max_bytes(5, &Format::default()), 2);
+            assert_eq!(max_bytes(6, &Format::default()), 2);
+            assert_eq!(max_bytes(7, &Format::default()), 2);
+            assert_eq!(max_bytes(8, &Format::default()), 3);
         }
     }
 }
 
 pub mod line {
     use std::fmt::Write;
+    use std::ops::Range;
+
+    use termion::color;
+
+    use crate::encoding::{Encoding, Glyph};
 
     const LOOKUP: [char; 16] = [
         '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'A', 'B', 'C', 'D', 'E', 'F',
     ];
 
-    #[derive(Copy, Clone, Debug)]
+    #[derive(Copy, Clone, Debug, PartialEq)]
     pub enum Mode {
         Ascii,
         Hex,
+        Binary,
+        Octal,
+        Decimal,
+        SignedDecimal,
+    }
+
+    impl Mode {
+        /// Look up a mode by the name used in commands
+        pub fn from_name(name: &str) -> Option<Self> {
+            match name {
+                "ascii" => Some(Mode::Ascii),
+                "hex" => Some(Mode::Hex),
+                "bin" => Some(Mode::Binary),
+                "oct" => Some(Mode::Octal),
+                "dec" => Some(Mode::Decimal),
+                "sdec" => Some(Mode::SignedDecimal),
+                _ => None,
+            }
+        }
+
+        pub fn name(self) -> &'static str {
+            match self {
+                Mode::Ascii => "ascii",
+                Mode::Hex => "hex",
+                Mode::Binary => "bin",
+                Mode::Octal => "oct",
+                Mode::Decimal => "dec",
+                Mode::SignedDecimal => "sdec",
+            }
+        }
+
+        /// Number of columns taken up by a single byte
+        pub fn cell_width(self) -> usize {
+            match self {
+                Mode::Ascii | Mode::Hex => 2,
+                Mode::Binary => 8,
+                Mode::Octal | Mode::Decimal => 3,
+                Mode::SignedDecimal => 4,
+            }
+        }
+    }
+
+    /// Broad categories of byte values, each displayed in its own colour
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum ByteClass {
+        Null,
+        Printable,
+        Whitespace,
+        Control,
+        HighBit,
+        Full,
+    }
+
+    impl ByteClass {
+        /// Every class, in the order of their indexes
+        pub const ALL: [ByteClass; 6] = [
+            ByteClass::Null,
+            ByteClass::Printable,
+            ByteClass::Whitespace,
+            ByteClass::Control,
+            ByteClass::HighBit,
+            ByteClass::Full,
+        ];
+
+        pub fn of(byte: u8) -> Self {
+            match byte {
+                0x00 => ByteClass::Null,
+                b'\t' | b'\n' | 0x0B | 0x0C | b'\r' | b' ' => ByteClass::Whitespace,
+                0x21..=0x7E => ByteClass::Printable,
+                0x01..=0x1F | 0x7F => ByteClass::Control,
+                0xFF => ByteClass::Full,
+                _ => ByteClass::HighBit,
+            }
+        }
+
+        /// Look up a class by the name used in commands
+        pub fn from_name(name: &str) -> Option<Self> {
+            match name {
+                "null" => Some(ByteClass::Null),
+                "printable" => Some(ByteClass::Printable),
+                "whitespace" => Some(ByteClass::Whitespace),
+                "control" => Some(ByteClass::Control),
+                "high" => Some(ByteClass::HighBit),
+                "ff" => Some(ByteClass::Full),
+                _ => None,
+            }
+        }
+
+        pub(crate) fn index(self) -> usize {
+            self as usize
+        }
+    }
+
+    /// Colour of each byte class as a 256 colour palette index, `None` uses the terminal's
+    /// default foreground colour
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct Palette {
+        colours: [Option<u8>; 6],
+    }
+
+    impl Default for Palette {
+        fn default() -> Self {
+            let mut palette = Palette { colours: [None; 6] };
+            palette.set(ByteClass::Null, Some(242));
+            palette.set(ByteClass::Printable, Some(6));
+            palette.set(ByteClass::Whitespace, Some(2));
+            palette.set(ByteClass::Control, Some(5));
+            palette.set(ByteClass::HighBit, Some(3));
+            palette.set(ByteClass::Full, Some(1));
+            palette
+        }
+    }
+
+    impl Palette {
+        pub fn colour(&self, class: ByteClass) -> Option<u8> {
+            self.colours[class.index()]
+        }
+
+        pub fn set(&mut self, class: ByteClass, colour: Option<u8>) {
+            self.colours[class.index()] = colour;
+        }
+    }
+
+    /// How bytes are laid out along a line
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct Format {
+        pub mode: Mode,
+        /// Number of bytes packed together between spaces
+        pub group_size: usize,
+        /// Display each group as a little-endian word, with its bytes reversed
+        pub little_endian: bool,
+        /// Character set used to decode bytes in ASCII mode
+        pub encoding: Encoding,
+        /// Show stand-in glyphs instead of hex for bytes without a character in ASCII mode
+        pub pictures: bool,
+    }
+
+    impl Default for Format {
+        fn default() -> Self {
+            Format {
+                mode: Mode::Hex,
+                group_size: 1,
+                little_endian: false,
+                encoding: Encoding::Ascii,
+                pictures: false,
+            }
+        }
+    }
+
+    impl Format {
+        /// Number of columns taken up by a single byte
+        pub fn cell_width(&self) -> usize {
+            self.mode.cell_width()
+        }
+
+        /// Number of columns taken up by `bytes` bytes
+        pub fn width_of(&self, bytes: usize) -> usize {
+            if bytes == 0 {
+                0
+            } else {
+                bytes * self.cell_width() + bytes.div_ceil(self.group_size) - 1
+            }
+        }
+
+        /// Number of bytes which can be displayed in `line_length` columns
+        ///
+        /// Only whole groups are displayed, unless not even one group fits in which case as many
+        /// bytes as fit are shown.
+        pub fn bytes_in(&self, line_length: usize) -> usize {
+            let group_width = self.group_size * self.cell_width();
+            let groups = (line_length + 1) / (group_width + 1);
+            if groups > 0 {
+                groups * self.group_size
+            } else {
+                line_length / self.cell_width()
+            }
+        }
+
+        /// Whether bytes within a group are displayed in reverse order
+        fn reversed(&self) -> bool {
+            self.little_endian && self.mode != Mode::Ascii
+        }
+    }
+
+    /// A range of bytes drawn on a coloured background
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Highlight {
+        pub range: Range<usize>,
+        /// Colour from the 256 colour palette
+        pub colour: u8,
     }
 
     pub struct Line {
         length: usize,
         text: String,
-        mode: Mode,
+        format: Format,
+        palette: Option<Palette>,
+        highlights: Vec<Highlight>,
+        /// Decoded characters of the bytes being formatted in ASCII mode
+        glyphs: Vec<Glyph>,
     }
 
     impl Line {
         pub fn new(length: usize, mode: Mode) -> Line {
+            Line::with_format(
+                length,
+                Format {
+                    mode,
+                    ..Format::default()
+                },
+            )
+        }
+
+        pub fn with_format(length: usize, format: Format) -> Line {
             Line {
                 length,
-                mode,
+                format,
                 text: String::with_capacity(length),
+                palette: None,
+                highlights: Vec::new(),
+                glyphs: Vec::new(),
             }
         }
 
+        /// Colour bytes by class using `palette`, or display them uncoloured when `None`
+        pub fn palette(&mut self, palette: Option<Palette>) {
+            self.palette = palette;
+        }
+
+        /// Draw bytes within any of `highlights` on their colour, later highlights are drawn over
+        /// earlier ones
+        pub fn highlights(&mut self, highlights: &[Highlight]) {
+            self.highlights = highlights.to_vec();
+        }
+
+        /// Background colour of the byte at `position`
+        fn background(&self, position: usize) -> Option<u8> {
+            self.highlights
+                .iter()
+                .rev()
+                .find(|highlight| highlight.range.contains(&position))
+                .map(|highlight| highlight.colour)
+        }
+
         pub fn ascii(&mut self) {
-            self.mode = Mode::Ascii;
+            self.format.mode = Mode::Ascii;
         }
 
         pub fn hex(&mut self) {
-            self.mode = Mode::Hex;
+            self.format.mode = Mode::Hex;
         }
 
+        /// Format `bytes` padded with spaces to the length of the line
+        ///
+        /// Bytes are split into groups separated by spaces, a trailing group may be shorter than
+        /// the rest. Bytes which don't fit in the line are left out.
         pub fn format(&mut self, bytes: &[u8]) -> &str {
-            let formatted_length = if bytes.len() == 0 {
-                0
-            } else {
-                bytes.len() * 2 + bytes.len() - 1
-            };
+            self.format_at(bytes, 0)
+        }
 
-            assert!(formatted_length <= self.length);
+        /// Format `bytes` which start at `position` in the data, highlighting the bytes in any of
+        /// the line's highlights
+        pub fn format_at(&mut self, bytes: &[u8], position: usize) -> &str {
+            let bytes = &bytes[..bytes.len().min(self.format.bytes_in(self.length))];
+            let formatted_length = self.format.width_of(bytes.len());
 
             self.text.clear();
 
-            // translate bytes into hex representations
-            for (i, &byte) in bytes.iter().enumerate() {
-                let byte = Byte::new(byte);
-                match self.mode {
-                    Mode::Ascii => byte.write_ascii(&mut self.text),
-                    Mode::Hex => byte.write(&mut self.text),
-                }
-                if i < bytes.len() - 1 {
+            // colour escapes are only written where the colour changes, consecutive bytes of the
+            // same class share one
+            let mut current_colour = None;
+            let mut current_background = None;
+
+            if self.format.mode == Mode::Ascii {
+                self.format.encoding.decode(bytes, &mut self.glyphs);
+            }
+
+            let group_size = self.format.group_size;
+            let reversed = self.format.reversed();
+            for (i, group) in bytes.chunks(group_size).enumerate() {
+                if i > 0 {
+                    // the gap between groups is only highlighted when the highlight carries on
+                    // into the next group
+                    let first = if reversed { group.len() - 1 } else { 0 };
+                    if current_background.is_some()
+                        && self.background(position + i * group_size + first).is_none()
+                    {
+                        write!(self.text, "{}", color::Bg(color::Reset)).unwrap();
+                        current_background = None;
+                    }
                     self.text.push(' ');
                 }
+
+                for j in 0..group.len() {
+                    let index = if reversed { group.len() - 1 - j } else { j };
+                    let byte = group[index];
+
+                    let background = self.background(position + i * group_size + index);
+                    if background != current_background {
+                        match background {
+                            Some(n) => write!(self.text, "{}", color::Bg(color::AnsiValue(n))),
+                            None => write!(self.text, "{}", color::Bg(color::Reset)),
+                        }
+                        .unwrap();
+                        current_background = background;
+                    }
+
+                    if let Some(palette) = self.palette {
+                        let colour = palette.colour(ByteClass::of(byte));
+                        if colour != current_colour {
+                            match colour {
+                                Some(n) => write!(self.text, "{}", color::Fg(color::AnsiValue(n))),
+                                None => write!(self.text, "{}", color::Fg(color::Reset)),
+                            }
+                            .unwrap();
+                            current_colour = colour;
+                        }
+                    }
+
+                    let glyph = self
+                        .glyphs
+                        .get(i * group_size + index)
+                        .copied()
+                        .unwrap_or(Glyph::Byte);
+                    let glyph = match glyph {
+                        Glyph::Byte if self.format.pictures => self.format.encoding.picture(byte),
+                        glyph => glyph,
+                    };
+                    Byte(byte).write(self.format.mode, glyph, &mut self.text);
+                }
+            }
+
+            if current_colour.is_some() {
+                write!(self.text, "{}", color::Fg(color::Reset)).unwrap();
+            }
+            if current_background.is_some() {
+                write!(self.text, "{}", color::Bg(color::Reset)).unwrap();
             }
 
             // pad string with spaces
@@ -222,50 +811,65 @@ pub mod line {
         }
     }
 
-    enum Byte {
-        Ascii(u8),
-        Other(u8),
-    }
+    /// Marks the cells of bytes after the first in a multi-byte character
+    const CONTINUATION: &str = "··";
+
+    /// Marks the cells of bytes which are invalid in the current encoding
+    const INVALID: &str = "××";
+
+    struct Byte(u8);
 
     impl Byte {
-        fn new(byte: u8) -> Self {
-            if byte >= 32 && byte <= 126 {
-                Byte::Ascii(byte)
-            } else {
-                Byte::Other(byte)
+        /// Write the byte as a cell of `mode`'s width, `glyph` is shown in the character column
+        fn write<T: Write>(&self, mode: Mode, glyph: Glyph, wtr: &mut T) {
+            let byte = self.0;
+            match mode {
+                Mode::Ascii => self.write_glyph(glyph, wtr),
+                Mode::Hex => self.write_hex(wtr),
+                Mode::Binary => {
+                    for shift in (0..8).rev() {
+                        wtr.write_char(LOOKUP[((byte >> shift) & 1) as usize])
+                            .unwrap();
+                    }
+                }
+                Mode::Octal => {
+                    wtr.write_char(LOOKUP[(byte >> 6) as usize]).unwrap();
+                    wtr.write_char(LOOKUP[((byte >> 3) & 7) as usize]).unwrap();
+                    wtr.write_char(LOOKUP[(byte & 7) as usize]).unwrap();
+                }
+                Mode::Decimal => write!(wtr, "{:>3}", byte).unwrap(),
+                Mode::SignedDecimal => write!(wtr, "{:>4}", byte as i8).unwrap(),
             }
         }
 
-        fn write_ascii<T: Write>(&self, wtr: &mut T) {
-            match self {
-                Byte::Ascii(byte) => {
+        fn write_glyph<T: Write>(&self, glyph: Glyph, wtr: &mut T) {
+            match glyph {
+                Glyph::Char(c) => {
                     wtr.write_char('.').unwrap();
-                    wtr.write_char((*byte).into()).unwrap();
+                    wtr.write_char(c).unwrap();
                 }
-                Byte::Other(byte) => {
-                    wtr.write_char(LOOKUP[(byte >> 4) as usize]).unwrap();
-                    wtr.write_char(LOOKUP[(byte & 0xF) as usize]).unwrap();
+                Glyph::Wide(c) => wtr.write_char(c).unwrap(),
+                Glyph::Continuation => wtr.write_str(CONTINUATION).unwrap(),
+                Glyph::Invalid => wtr.write_str(INVALID).unwrap(),
+                Glyph::Byte => self.write_hex(wtr),
+                Glyph::Picture(c) => {
+                    wtr.write_char(' ').unwrap();
+                    wtr.write_char(c).unwrap();
                 }
             }
         }
 
-        fn write<T: Write>(&self, wtr: &mut T) {
-            match self {
-                Byte::Ascii(byte) => {
-                    wtr.write_char(LOOKUP[(byte >> 4) as usize]).unwrap();
-                    wtr.write_char(LOOKUP[(byte & 0xF) as usize]).unwrap();
-                }
-                Byte::Other(byte) => {
-                    wtr.write_char(LOOKUP[(byte >> 4) as usize]).unwrap();
-                    wtr.write_char(LOOKUP[(byte & 0xF) as usize]).unwrap();
-                }
-            }
+        fn write_hex<T: Write>(&self, wtr: &mut T) {
+            let byte = self.0;
+            wtr.write_char(LOOKUP[(byte >> 4) as usize]).unwrap();
+            wtr.write_char(LOOKUP[(byte & 0xF) as usize]).unwrap();
         }
     }
 
     #[cfg(test)]
     mod tests {
-        use super::{Line, Mode};
+        use super::{ByteClass, Format, Highlight, Line, Mode, Palette};
+        use crate::encoding::{self, Encoding};
 
         #[test]
         fn format_represents_bytes_as_hex_values() {
@@ -296,9 +900,168 @@ pub mod line {
         }
 
         #[test]
-        #[should_panic]
-        fn format_panics_if_given_more_bytes_than_there_is_line_space() {
-            Line::new(4, Mode::Hex).format(&[111, 222, 000]);
+        fn byte_classes() {
+            assert_eq!(ByteClass::of(0), ByteClass::Null);
+            assert_eq!(ByteClass::of(b'a'), ByteClass::Printable);
+            assert_eq!(ByteClass::of(b'~'), ByteClass::Printable);
+            assert_eq!(ByteClass::of(b' '), ByteClass::Whitespace);
+            assert_eq!(ByteClass::of(b'\n'), ByteClass::Whitespace);
+            assert_eq!(ByteClass::of(0x1B), ByteClass::Control);
+            assert_eq!(ByteClass::of(0x7F), ByteClass::Control);
+            assert_eq!(ByteClass::of(0x80), ByteClass::HighBit);
+            assert_eq!(ByteClass::of(0xFE), ByteClass::HighBit);
+            assert_eq!(ByteClass::of(0xFF), ByteClass::Full);
+        }
+
+        #[test]
+        fn format_colours_runs_of_bytes_by_class() {
+            let mut line = Line::new(14, Mode::Hex);
+            line.palette(Some(Palette::default()));
+
+            assert_eq!(
+                line.format(&[0, 0, 0x41, 0x42]),
+                "\x1b[38;5;242m00 00 \x1b[38;5;6m41 42\x1b[39m   "
+            );
+        }
+
+        #[test]
+        fn format_uses_the_default_colour_for_unset_classes() {
+            let mut palette = Palette::default();
+            palette.set(ByteClass::Printable, None);
+            let mut line = Line::new(8, Mode::Hex);
+            line.palette(Some(palette));
+
+            assert_eq!(
+                line.format(&[0x41, 0xFF, 0x41]),
+                "41 \x1b[38;5;1mFF \x1b[39m41"
+            );
+        }
+
+        #[test]
+        fn format_leaves_out_bytes_which_do_not_fit() {
+            assert_eq!(Line::new(4, Mode::Hex).format(&[111, 222, 0]), "6F  ");
+            assert_eq!(Line::new(1, Mode::Hex).format(&[111]), " ");
+        }
+
+        #[test]
+        fn format_packs_bytes_within_groups() {
+            let format = Format {
+                group_size: 2,
+                ..Format::default()
+            };
+            let mut line = Line::with_format(14, format);
+            assert_eq!(line.format(&[1, 2, 3, 4, 5]), "0102 0304 05  ");
+            assert_eq!(line.format(&[1, 2, 3, 4, 5, 6, 7, 8]), "0102 0304 0506");
+        }
+
+        #[test]
+        fn format_reverses_little_endian_groups() {
+            let format = Format {
+                group_size: 4,
+                little_endian: true,
+                ..Format::default()
+            };
+            let mut line = Line::with_format(20, format);
+            assert_eq!(line.format(&[1, 2, 3, 4, 5, 6]), "04030201 0605       ");
+
+            line.ascii();
+            assert_eq!(line.format(b"ab"), ".a.b                ");
+        }
+
+        #[test]
+        fn format_decodes_the_character_column() {
+            let format = Format {
+                mode: Mode::Ascii,
+                encoding: Encoding::Utf8,
+                ..Format::default()
+            };
+            let mut line = Line::with_format(17, format);
+            assert_eq!(line.format("aé語".as_bytes()), ".a .é ·· 語 ·· ··");
+            assert_eq!(line.format(b"\xFFa"), "×× .a            ");
+        }
+
+        #[test]
+        fn format_shows_pictures_for_bytes_without_characters() {
+            let format = Format {
+                mode: Mode::Ascii,
+                pictures: true,
+                ..Format::default()
+            };
+            let mut line = Line::with_format(11, format);
+            assert_eq!(line.format(b"a\n\x1b\xF0"), ".a  ␊  ␛  •");
+        }
+
+        #[test]
+        fn format_at_highlights_bytes_by_position() {
+            let format = Format {
+                group_size: 2,
+                ..Format::default()
+            };
+            let mut line = Line::with_format(9, format);
+            line.highlights(&[Highlight {
+                range: 11..13,
+                colour: 24,
+            }]);
+
+            assert_eq!(
+                line.format_at(&[1, 2, 3, 4], 10),
+                "01\x1b[48;5;24m02 03\x1b[49m04",
+            );
+            assert_eq!(
+                line.format_at(&[1, 2, 3, 4], 9),
+                "0102 \x1b[48;5;24m0304\x1b[49m",
+            );
+            assert_eq!(line.format_at(&[1, 2], 0), "0102     ");
+        }
+
+        #[test]
+        fn format_keeps_the_line_length_in_every_encoding() {
+            let bytes: Vec<u8> = (0..=255).collect();
+            for &encoding in &[
+                Encoding::Ascii,
+                Encoding::Utf8,
+                Encoding::Utf16Le,
+                Encoding::Utf16Be,
+                Encoding::Latin1,
+                Encoding::Cp437,
+                Encoding::Ebcdic,
+            ] {
+                for &pictures in &[false, true] {
+                    let format = Format {
+                        mode: Mode::Ascii,
+                        encoding,
+                        pictures,
+                        ..Format::default()
+                    };
+                    let mut line = Line::with_format(767, format);
+                    let width: usize = line
+                        .format(&bytes)
+                        .chars()
+                        .map(|c| if encoding::is_wide(c) { 2 } else { 1 })
+                        .sum();
+                    assert_eq!(width, 767);
+                }
+            }
+        }
+
+        #[test]
+        fn format_represents_bytes_in_numeric_modes() {
+            assert_eq!(
+                Line::new(17, Mode::Binary).format(&[5, 255]),
+                "00000101 11111111"
+            );
+            assert_eq!(Line::new(7, Mode::Octal).format(&[8, 255]), "010 377");
+            assert_eq!(Line::new(7, Mode::Decimal).format(&[7, 255]), "  7 255");
+            assert_eq!(
+                Line::new(9, Mode::SignedDecimal).format(&[127, 128]),
+                " 127 -128"
+            );
+        }
+
+        #[test]
+        fn format_fits_bytes_by_cell_width() {
+            assert_eq!(Line::new(10, Mode::Binary).format(&[1, 2]), "00000001  ");
+            assert_eq!(Line::new(8, Mode::Decimal).format(&[1, 2, 3]), "  1   2 ");
         }
     }
 }
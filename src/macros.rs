@@ -0,0 +1,320 @@
+//! Keystrokes recorded into registers with `q{register}` and replayed with `@{register}`
+//!
+//! Macros can also be written out in vim's key notation, plain characters along with names in
+//! angle brackets like `<CR>`, `<Esc>` and `<C-d>`, to define them with `:macro` in the config
+//! file.
+use std::collections::HashMap;
+
+use termion::event::{Event, Key};
+
+/// Nesting of macros replaying macros beyond which replaying stops, to stop loops
+pub const MAX_DEPTH: usize = 16;
+
+/// Registers holding macros, and the one being recorded into
+#[derive(Default)]
+pub struct Macros {
+    registers: HashMap<char, Vec<Event>>,
+    recording: Option<(char, Vec<Event>)>,
+    /// Register last replayed, repeated by `@@`
+    last: Option<char>,
+    /// Number of macros being replayed, events aren't recorded while it's above zero
+    depth: usize,
+}
+
+impl Macros {
+    /// Whether `c` can name a register
+    pub fn is_register(c: char) -> bool {
+        c.is_ascii_alphanumeric()
+    }
+
+    /// Start recording events into `register`, replacing what it held
+    pub fn start(&mut self, register: char) {
+        self.recording = Some((register, Vec::new()));
+    }
+
+    /// Register being recorded into
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    /// Add `event` to the macro being recorded, if there is one and it isn't being replayed
+    pub fn record(&mut self, event: &Event) {
+        if self.depth > 0 {
+            return;
+        }
+        if let Some((_, events)) = self.recording.as_mut() {
+            events.push(event.clone());
+        }
+    }
+
+    /// Stop recording, keeping the macro without the last event recorded, which was the key
+    /// stopping it
+    pub fn stop(&mut self) -> Option<char> {
+        let (register, mut events) = self.recording.take()?;
+        events.pop();
+        self.registers.insert(register, events);
+        Some(register)
+    }
+
+    pub fn get(&self, register: char) -> Option<&[Event]> {
+        self.registers.get(&register).map(Vec::as_slice)
+    }
+
+    pub fn set(&mut self, register: char, events: Vec<Event>) {
+        self.registers.insert(register, events);
+    }
+
+    /// Start replaying the macro in `register`, `@` being the register last replayed, giving its
+    /// events `count` times over
+    ///
+    /// Every replay started has to be finished with [`Macros::finish_replay`].
+    pub fn replay(
+        &mut self,
+        register: char,
+        count: usize,
+    ) -> Result<impl Iterator<Item = Event>, String> {
+        let register = match register {
+            '@' => self.last.ok_or("no macro replayed yet")?,
+            _ => register,
+        };
+        let events = self
+            .get(register)
+            .ok_or_else(|| format!("register {} is empty", register))?
+            .to_vec();
+        if self.depth >= MAX_DEPTH {
+            return Err("macros nested too deeply".to_owned());
+        }
+        self.last = Some(register);
+        self.depth += 1;
+        Ok((0..count).flat_map(move |_| events.clone()))
+    }
+
+    /// Finish the innermost replay
+    pub fn finish_replay(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    /// Whether a macro is being replayed
+    pub fn replaying(&self) -> bool {
+        self.depth > 0
+    }
+}
+
+/// Names of keys written in angle brackets, other than `<C-x>` for control keys
+const NAMED_KEYS: [(&str, Key); 15] = [
+    ("cr", Key::Char('\n')),
+    ("enter", Key::Char('\n')),
+    ("esc", Key::Esc),
+    ("bs", Key::Backspace),
+    ("tab", Key::Char('\t')),
+    ("space", Key::Char(' ')),
+    ("lt", Key::Char('<')),
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+];
+
+/// Events typed by the keys in `notation`, `None` if it names an unknown key
+///
+/// A `<` which doesn't start a name in angle brackets, without spaces, stands for itself.
+pub fn parse(notation: &str) -> Option<Vec<Event>> {
+    let mut events = Vec::new();
+    let mut rest = notation;
+    while let Some(c) = rest.chars().next() {
+        let named = rest
+            .strip_prefix('<')
+            .and_then(|after| after.split_once('>'))
+            .filter(|(name, _)| !name.is_empty() && !name.contains(['<', ' ']));
+        match named {
+            Some((name, after)) => {
+                events.push(Event::Key(named_key(name)?));
+                rest = after;
+            }
+            None => {
+                events.push(Event::Key(Key::Char(c)));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    Some(events)
+}
+
+fn named_key(name: &str) -> Option<Key> {
+    let name = name.to_lowercase();
+    if let Some(c) = name.strip_prefix("c-") {
+        let mut chars = c.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(Key::Ctrl(c)),
+            _ => None,
+        };
+    }
+    NAMED_KEYS
+        .iter()
+        .find(|(key_name, _)| *key_name == name)
+        .map(|(_, key)| *key)
+}
+
+/// `events` in key notation, the reverse of [`parse`]
+///
+/// Events other than keys are left out. A space at the end is written `<Space>` so it isn't
+/// lost from the end of a command.
+pub fn notation(events: &[Event]) -> String {
+    let mut text = String::new();
+    for event in events {
+        match event {
+            Event::Key(Key::Char('<')) => text.push_str("<lt>"),
+            Event::Key(Key::Char('\n')) => text.push_str("<CR>"),
+            Event::Key(Key::Char('\t')) => text.push_str("<Tab>"),
+            Event::Key(Key::Char(c)) => text.push(*c),
+            Event::Key(Key::Ctrl(c)) => text.push_str(&format!("<C-{}>", c)),
+            Event::Key(Key::Esc) => text.push_str("<Esc>"),
+            Event::Key(Key::Backspace) => text.push_str("<BS>"),
+            Event::Key(Key::Up) => text.push_str("<Up>"),
+            Event::Key(Key::Down) => text.push_str("<Down>"),
+            Event::Key(Key::Left) => text.push_str("<Left>"),
+            Event::Key(Key::Right) => text.push_str("<Right>"),
+            Event::Key(Key::Home) => text.push_str("<Home>"),
+            Event::Key(Key::End) => text.push_str("<End>"),
+            Event::Key(Key::PageUp) => text.push_str("<PageUp>"),
+            Event::Key(Key::PageDown) => text.push_str("<PageDown>"),
+            _ => {}
+        }
+    }
+    if text.ends_with(' ') {
+        text.pop();
+        text.push_str("<Space>");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: &[Key]) -> Vec<Event> {
+        keys.iter().map(|&key| Event::Key(key)).collect()
+    }
+
+    #[test]
+    fn key_notation_is_parsed() {
+        assert_eq!(
+            parse(":w 16<CR><c-D>j<lt><a <Esc>"),
+            Some(keys(&[
+                Key::Char(':'),
+                Key::Char('w'),
+                Key::Char(' '),
+                Key::Char('1'),
+                Key::Char('6'),
+                Key::Char('\n'),
+                Key::Ctrl('d'),
+                Key::Char('j'),
+                Key::Char('<'),
+                Key::Char('<'),
+                Key::Char('a'),
+                Key::Char(' '),
+                Key::Esc,
+            ]))
+        );
+        assert_eq!(
+            parse("<PageDown><>"),
+            Some(keys(&[Key::PageDown, Key::Char('<'), Key::Char('>')]))
+        );
+        assert_eq!(parse("<a b>"), parse("<lt>a b>"));
+        assert_eq!(parse("<F1>"), None);
+        assert_eq!(parse("<C-ab>"), None);
+    }
+
+    #[test]
+    fn notation_round_trips() {
+        let events = keys(&[
+            Key::Char(':'),
+            Key::Char('f'),
+            Key::Char(' '),
+            Key::Char('\n'),
+            Key::Ctrl('w'),
+            Key::Char('<'),
+            Key::Down,
+            Key::Char(' '),
+        ]);
+        let text = notation(&events);
+        assert_eq!(text, ":f <CR><C-w><lt><Down><Space>");
+        assert_eq!(parse(&text), Some(events));
+    }
+
+    #[test]
+    fn recording_drops_the_key_stopping_it() {
+        let mut macros = Macros::default();
+        macros.record(&Event::Key(Key::Char('x')));
+        macros.start('a');
+        assert_eq!(macros.recording(), Some('a'));
+        macros.record(&Event::Key(Key::Char('j')));
+        macros.record(&Event::Key(Key::Char('q')));
+        assert_eq!(macros.stop(), Some('a'));
+        assert_eq!(macros.recording(), None);
+        assert_eq!(macros.get('a'), Some(&keys(&[Key::Char('j')])[..]));
+        assert_eq!(macros.stop(), None);
+    }
+
+    #[test]
+    fn replays_repeat_by_count_and_with_at() {
+        let mut macros = Macros::default();
+        assert_eq!(
+            macros.replay('@', 1).err(),
+            Some("no macro replayed yet".to_owned())
+        );
+        assert_eq!(
+            macros.replay('a', 1).err(),
+            Some("register a is empty".to_owned())
+        );
+        macros.set('a', keys(&[Key::Char('j'), Key::Char('l')]));
+
+        let events: Vec<Event> = macros.replay('a', 3).unwrap().collect();
+        assert_eq!(events, parse("jljljl").unwrap());
+        assert!(macros.replaying());
+        macros.finish_replay();
+        assert!(!macros.replaying());
+
+        let events: Vec<Event> = macros.replay('@', 2).unwrap().collect();
+        assert_eq!(events, parse("jljl").unwrap());
+        macros.finish_replay();
+    }
+
+    #[test]
+    fn replays_stop_nesting_at_the_limit() {
+        let mut macros = Macros::default();
+        macros.set('a', parse("@a").unwrap());
+        for _ in 0..MAX_DEPTH {
+            assert!(macros.replay('a', 1).is_ok());
+        }
+        assert_eq!(
+            macros.replay('a', 1).err(),
+            Some("macros nested too deeply".to_owned())
+        );
+        for _ in 0..MAX_DEPTH {
+            macros.finish_replay();
+        }
+        assert!(!macros.replaying());
+    }
+
+    #[test]
+    fn replayed_events_arent_recorded() {
+        let mut macros = Macros::default();
+        macros.set('a', parse("j").unwrap());
+        macros.start('b');
+        macros.record(&Event::Key(Key::Char('@')));
+        macros.record(&Event::Key(Key::Char('a')));
+        let replayed: Vec<Event> = macros.replay('a', 1).unwrap().collect();
+        for event in &replayed {
+            macros.record(event);
+        }
+        macros.finish_replay();
+        macros.record(&Event::Key(Key::Char('q')));
+        macros.stop();
+        assert_eq!(macros.get('b'), Some(&parse("@a").unwrap()[..]));
+    }
+}
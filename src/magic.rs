@@ -0,0 +1,253 @@
+//! Detection of file types from the signatures at the start of their data
+use crate::buffer::View;
+use crate::line::Mode;
+
+/// Types of file recognised by their signature
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FileType {
+    Elf,
+    Pe,
+    /// MS-DOS executable without a PE header
+    Dos,
+    MachO,
+    Png,
+    Jpeg,
+    Gif,
+    Zip,
+    Gzip,
+    Bzip2,
+    Xz,
+    SevenZip,
+    Pdf,
+    Sqlite,
+    Tar,
+    Wasm,
+}
+
+/// Bytes found at `offset` in every file of a type
+struct Signature {
+    file_type: FileType,
+    offset: usize,
+    magic: &'static [u8],
+}
+
+/// Signatures checked in order, the first match wins
+const SIGNATURES: &[Signature] = &[
+    Signature {
+        file_type: FileType::Elf,
+        offset: 0,
+        magic: b"\x7FELF",
+    },
+    Signature {
+        file_type: FileType::MachO,
+        offset: 0,
+        magic: b"\xFE\xED\xFA\xCE",
+    },
+    Signature {
+        file_type: FileType::MachO,
+        offset: 0,
+        magic: b"\xCE\xFA\xED\xFE",
+    },
+    Signature {
+        file_type: FileType::MachO,
+        offset: 0,
+        magic: b"\xFE\xED\xFA\xCF",
+    },
+    Signature {
+        file_type: FileType::MachO,
+        offset: 0,
+        magic: b"\xCF\xFA\xED\xFE",
+    },
+    Signature {
+        file_type: FileType::MachO,
+        offset: 0,
+        magic: b"\xCA\xFE\xBA\xBE",
+    },
+    Signature {
+        file_type: FileType::Png,
+        offset: 0,
+        magic: b"\x89PNG\r\n\x1A\n",
+    },
+    Signature {
+        file_type: FileType::Jpeg,
+        offset: 0,
+        magic: b"\xFF\xD8\xFF",
+    },
+    Signature {
+        file_type: FileType::Gif,
+        offset: 0,
+        magic: b"GIF87a",
+    },
+    Signature {
+        file_type: FileType::Gif,
+        offset: 0,
+        magic: b"GIF89a",
+    },
+    Signature {
+        file_type: FileType::Zip,
+        offset: 0,
+        magic: b"PK\x03\x04",
+    },
+    Signature {
+        file_type: FileType::Zip,
+        offset: 0,
+        magic: b"PK\x05\x06",
+    },
+    Signature {
+        file_type: FileType::Gzip,
+        offset: 0,
+        magic: b"\x1F\x8B",
+    },
+    Signature {
+        file_type: FileType::Bzip2,
+        offset: 0,
+        magic: b"BZh",
+    },
+    Signature {
+        file_type: FileType::Xz,
+        offset: 0,
+        magic: b"\xFD7zXZ\x00",
+    },
+    Signature {
+        file_type: FileType::SevenZip,
+        offset: 0,
+        magic: b"7z\xBC\xAF\x27\x1C",
+    },
+    Signature {
+        file_type: FileType::Pdf,
+        offset: 0,
+        magic: b"%PDF-",
+    },
+    Signature {
+        file_type: FileType::Sqlite,
+        offset: 0,
+        magic: b"SQLite format 3\x00",
+    },
+    Signature {
+        file_type: FileType::Tar,
+        offset: 257,
+        magic: b"ustar",
+    },
+    Signature {
+        file_type: FileType::Wasm,
+        offset: 0,
+        magic: b"\x00asm",
+    },
+];
+
+/// Offset of the field holding the offset of the PE header in an MS-DOS header
+const PE_POINTER_OFFSET: usize = 0x3C;
+
+impl FileType {
+    pub fn name(self) -> &'static str {
+        match self {
+            FileType::Elf => "ELF",
+            FileType::Pe => "PE",
+            FileType::Dos => "MS-DOS",
+            FileType::MachO => "Mach-O",
+            FileType::Png => "PNG",
+            FileType::Jpeg => "JPEG",
+            FileType::Gif => "GIF",
+            FileType::Zip => "ZIP",
+            FileType::Gzip => "gzip",
+            FileType::Bzip2 => "bzip2",
+            FileType::Xz => "xz",
+            FileType::SevenZip => "7-Zip",
+            FileType::Pdf => "PDF",
+            FileType::Sqlite => "SQLite",
+            FileType::Tar => "tar",
+            FileType::Wasm => "WebAssembly",
+        }
+    }
+
+    /// View to open files of this type with
+    ///
+    /// Executables and databases are made of structures aligned to 16 bytes or less, so they're
+    /// shown 16 bytes to a row. PDFs are mostly text and start out in ASCII mode.
+    pub fn default_view(self) -> View {
+        let mut view = View::default();
+        match self {
+            FileType::Elf | FileType::Pe | FileType::Dos | FileType::MachO | FileType::Sqlite => {
+                view.bytes_per_row = 16
+            }
+            FileType::Pdf => view.format.mode = Mode::Ascii,
+            _ => {}
+        }
+        view
+    }
+}
+
+/// Identify the type of a file from its `data`
+pub fn detect(data: &[u8]) -> Option<FileType> {
+    if data.starts_with(b"MZ") {
+        return Some(if has_pe_header(data) {
+            FileType::Pe
+        } else {
+            FileType::Dos
+        });
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|signature| {
+            data.get(signature.offset..)
+                .is_some_and(|data| data.starts_with(signature.magic))
+        })
+        .map(|signature| signature.file_type)
+}
+
+/// Whether the MS-DOS header at the start of `data` points to a PE header
+fn has_pe_header(data: &[u8]) -> bool {
+    let pointer = match data.get(PE_POINTER_OFFSET..PE_POINTER_OFFSET + 4) {
+        Some(bytes) => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize,
+        None => return false,
+    };
+    data.get(pointer..)
+        .is_some_and(|header| header.starts_with(b"PE\x00\x00"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_detects_files_by_signature() {
+        assert_eq!(detect(b"\x7FELF\x02\x01\x01"), Some(FileType::Elf));
+        assert_eq!(detect(b"\x89PNG\r\n\x1A\n\x00"), Some(FileType::Png));
+        assert_eq!(detect(b"PK\x03\x04\x14\x00"), Some(FileType::Zip));
+        assert_eq!(detect(b"%PDF-1.7\n"), Some(FileType::Pdf));
+        assert_eq!(
+            detect(b"SQLite format 3\x00\x10\x00"),
+            Some(FileType::Sqlite)
+        );
+        assert_eq!(detect(b"\x1F\x8B\x08"), Some(FileType::Gzip));
+
+        let mut tar = vec![0; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(detect(&tar), Some(FileType::Tar));
+    }
+
+    #[test]
+    fn it_detects_nothing_in_unknown_or_short_data() {
+        assert_eq!(detect(b""), None);
+        assert_eq!(detect(b"\x7FEL"), None);
+        assert_eq!(detect(b"plain text"), None);
+    }
+
+    #[test]
+    fn pe_files_are_told_apart_from_dos_executables() {
+        let mut data = vec![0; 0x80];
+        data[..2].copy_from_slice(b"MZ");
+        assert_eq!(detect(&data), Some(FileType::Dos));
+
+        data[PE_POINTER_OFFSET] = 0x40;
+        data[0x40..0x44].copy_from_slice(b"PE\x00\x00");
+        assert_eq!(detect(&data), Some(FileType::Pe));
+    }
+
+    #[test]
+    fn executables_default_to_16_bytes_per_row() {
+        assert_eq!(FileType::Elf.default_view().bytes_per_row, 16);
+        assert_eq!(FileType::Png.default_view(), View::default());
+    }
+}
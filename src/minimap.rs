@@ -0,0 +1,324 @@
+//! Overview of a whole buffer in a narrow column beside the data frame
+//!
+//! Data is summarised in fixed size blocks, a few at a time, so opening a large file doesn't stall
+//! the interface. Each cell of the minimap covers an equal share of the data and shows either the
+//! average Shannon entropy of its blocks or the byte class most of its bytes belong to.
+use std::io::Write;
+use std::ops::Range;
+
+use termion::{color, cursor};
+
+use crate::layout::Rect;
+use crate::line::{ByteClass, Palette};
+
+/// Number of bytes summarised together
+pub const BLOCK_SIZE: usize = 4096;
+
+/// Number of blocks summarised by each call to `Summary::update`
+pub const BLOCKS_PER_STEP: usize = 256;
+
+/// Columns taken up by the minimap, a viewport marker followed by the cells
+pub const WIDTH: u16 = 2;
+
+/// Marks the cells covering the bytes visible in the focused window
+const VIEWPORT_MARKER: char = '▶';
+
+/// Shown in cells whose blocks haven't been summarised yet
+const PENDING: char = '·';
+
+/// Shades for entropy from 0 to 8 bits when colours are disabled
+const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// First colour of the 24 step greyscale ramp of the 256 colour palette
+const GREYSCALE_START: u8 = 232;
+
+/// What the cells of the minimap show
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Measure {
+    Entropy,
+    ByteClass,
+}
+
+impl Measure {
+    /// Look up a measure by the name used in commands
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "entropy" => Some(Measure::Entropy),
+            "class" => Some(Measure::ByteClass),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Block {
+    /// Shannon entropy in bits per byte
+    entropy: f64,
+    /// Number of bytes of each class
+    classes: [usize; 6],
+}
+
+impl Block {
+    fn new(bytes: &[u8]) -> Self {
+        let mut counts = [0usize; 256];
+        for &byte in bytes {
+            counts[byte as usize] += 1;
+        }
+
+        let total = bytes.len() as f64;
+        let mut entropy = 0.0;
+        let mut classes = [0; 6];
+        for (byte, &count) in counts.iter().enumerate() {
+            if count > 0 {
+                classes[ByteClass::of(byte as u8).index()] += count;
+                let p = count as f64 / total;
+                entropy -= p * p.log2();
+            }
+        }
+
+        Block { entropy, classes }
+    }
+}
+
+/// What a single cell of the minimap shows
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Cell {
+    /// Not every block in the cell has been summarised
+    Pending,
+    Entropy(f64),
+    Class(ByteClass),
+}
+
+/// Statistics of a buffer's data, filled in block by block
+#[derive(Clone, Debug, Default)]
+pub struct Summary {
+    blocks: Vec<Block>,
+}
+
+impl Summary {
+    /// Whether every block of `data` has been summarised
+    pub fn is_complete(&self, data: &[u8]) -> bool {
+        self.blocks.len() >= data.len().div_ceil(BLOCK_SIZE)
+    }
+
+    /// Summarise up to `max_blocks` more blocks of `data`
+    pub fn update(&mut self, data: &[u8], max_blocks: usize) {
+        let start = (self.blocks.len() * BLOCK_SIZE).min(data.len());
+        self.blocks.extend(
+            data[start..]
+                .chunks(BLOCK_SIZE)
+                .take(max_blocks)
+                .map(Block::new),
+        );
+    }
+
+    /// Value of the cell covering `region` of the data
+    pub fn cell(&self, region: Range<usize>, measure: Measure) -> Cell {
+        let first = region.start / BLOCK_SIZE;
+        let last = region.end.div_ceil(BLOCK_SIZE).max(first + 1);
+        if last > self.blocks.len() {
+            return Cell::Pending;
+        }
+        let blocks = &self.blocks[first..last];
+
+        match measure {
+            Measure::Entropy => {
+                let total: f64 = blocks.iter().map(|block| block.entropy).sum();
+                Cell::Entropy(total / blocks.len() as f64)
+            }
+            Measure::ByteClass => {
+                let mut totals = [0; 6];
+                for block in blocks {
+                    for (total, count) in totals.iter_mut().zip(block.classes.iter()) {
+                        *total += count;
+                    }
+                }
+                let dominant = (0..totals.len()).max_by_key(|&i| totals[i]).unwrap();
+                Cell::Class(ByteClass::ALL[dominant])
+            }
+        }
+    }
+}
+
+/// Range of bytes covered by cell `index` of a minimap `height` cells tall over `len` bytes
+pub fn region(index: usize, height: usize, len: usize) -> Range<usize> {
+    index * len / height..(index + 1) * len / height
+}
+
+/// Index of the cell covering the byte at `position`
+pub fn cell_of(position: usize, height: usize, len: usize) -> usize {
+    if len == 0 || height == 0 {
+        return 0;
+    }
+    (((position + 1) * height - 1) / len).min(height - 1)
+}
+
+/// Draw the minimap for `data_len` bytes filling the height of `area`
+///
+/// Cells overlapping `viewport` are marked. Without a `palette`, entropy is shown with shading
+/// characters and byte classes by the initial of their name.
+pub fn render<T: Write>(
+    out: &mut T,
+    summary: &Summary,
+    data_len: usize,
+    measure: Measure,
+    area: Rect,
+    viewport: Range<usize>,
+    palette: Option<Palette>,
+) {
+    let cells = area.height as usize;
+    for i in 0..cells {
+        write!(out, "{}", cursor::Goto(area.x, area.y + i as u16)).unwrap();
+        if data_len == 0 {
+            write!(out, "  ").unwrap();
+            continue;
+        }
+
+        let region = region(i, cells, data_len);
+        let visible =
+            region.start < viewport.end && viewport.start < region.end.max(region.start + 1);
+        let marker = if visible { VIEWPORT_MARKER } else { ' ' };
+
+        let (colour, glyph) = match summary.cell(region, measure) {
+            Cell::Pending => (None, PENDING),
+            Cell::Entropy(entropy) => match palette {
+                Some(..) => (
+                    Some(GREYSCALE_START + (entropy / 8.0 * 23.0).round() as u8),
+                    '█',
+                ),
+                None => (None, SHADES[(entropy / 8.0 * 4.0).round() as usize]),
+            },
+            Cell::Class(class) => match palette {
+                Some(palette) => (palette.colour(class), '█'),
+                None => (None, initial(class)),
+            },
+        };
+
+        match colour {
+            Some(n) => write!(
+                out,
+                "{}{}{}{}",
+                marker,
+                color::Fg(color::AnsiValue(n)),
+                glyph,
+                color::Fg(color::Reset)
+            ),
+            None => write!(out, "{}{}", marker, glyph),
+        }
+        .unwrap();
+    }
+}
+
+/// Initial of the name used for `class` in commands
+fn initial(class: ByteClass) -> char {
+    match class {
+        ByteClass::Null => 'n',
+        ByteClass::Printable => 'p',
+        ByteClass::Whitespace => 'w',
+        ByteClass::Control => 'c',
+        ByteClass::HighBit => 'h',
+        ByteClass::Full => 'f',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summarise(data: &[u8]) -> Summary {
+        let mut summary = Summary::default();
+        summary.update(data, usize::MAX);
+        summary
+    }
+
+    #[test]
+    fn entropy_ranges_from_constant_to_uniform_data() {
+        let constant = vec![7; BLOCK_SIZE];
+        let uniform: Vec<u8> = (0..BLOCK_SIZE).map(|i| i as u8).collect();
+
+        assert_eq!(
+            summarise(&constant).cell(0..BLOCK_SIZE, Measure::Entropy),
+            Cell::Entropy(0.0)
+        );
+        assert_eq!(
+            summarise(&uniform).cell(0..BLOCK_SIZE, Measure::Entropy),
+            Cell::Entropy(8.0)
+        );
+    }
+
+    #[test]
+    fn cells_show_the_dominant_byte_class() {
+        let mut data = vec![0; BLOCK_SIZE];
+        data.extend(vec![b'a'; BLOCK_SIZE * 2]);
+        let summary = summarise(&data);
+
+        assert_eq!(
+            summary.cell(0..BLOCK_SIZE, Measure::ByteClass),
+            Cell::Class(ByteClass::Null)
+        );
+        assert_eq!(
+            summary.cell(0..data.len(), Measure::ByteClass),
+            Cell::Class(ByteClass::Printable)
+        );
+    }
+
+    #[test]
+    fn summaries_are_built_a_few_blocks_at_a_time() {
+        let data = vec![0; BLOCK_SIZE * 3 + 1];
+        let mut summary = Summary::default();
+
+        summary.update(&data, 2);
+        assert!(!summary.is_complete(&data));
+        assert_eq!(
+            summary.cell(0..BLOCK_SIZE * 2, Measure::Entropy),
+            Cell::Entropy(0.0)
+        );
+        assert_eq!(summary.cell(0..data.len(), Measure::Entropy), Cell::Pending);
+
+        summary.update(&data, 2);
+        assert!(summary.is_complete(&data));
+        summary.update(&data, 2);
+        assert_eq!(summary.blocks.len(), 4);
+    }
+
+    #[test]
+    fn cells_cover_the_data_without_gaps() {
+        for &(height, len) in &[(10, 1000), (7, 3), (3, 7), (1, 5)] {
+            let mut end = 0;
+            for i in 0..height {
+                let region = region(i, height, len);
+                assert_eq!(region.start, end);
+                end = region.end;
+                for position in region {
+                    assert_eq!(cell_of(position, height, len), i);
+                }
+            }
+            assert_eq!(end, len);
+        }
+    }
+
+    #[test]
+    fn rendering_marks_the_viewport() {
+        let data = vec![0; 40];
+        let mut out = Vec::new();
+        render(
+            &mut out,
+            &summarise(&data),
+            data.len(),
+            Measure::ByteClass,
+            Rect {
+                x: 9,
+                y: 1,
+                width: WIDTH,
+                height: 4,
+            },
+            10..20,
+            None,
+        );
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[1;9H n\x1b[2;9H▶n\x1b[3;9H n\x1b[4;9H n"
+        );
+    }
+}
@@ -0,0 +1,350 @@
+use std::io::Write;
+use std::ops::Range;
+
+use termion::{color, cursor};
+
+use crate::screen::{Dimension, Point};
+
+/// What an overlay is listing, decides what happens when an item is selected
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OverlayKind {
+    Bookmarks,
+    Buffers,
+    Strings,
+    Structures,
+}
+
+/// A labelled range of bytes, possibly made up of smaller ones, listed as a tree in an overlay
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeNode {
+    pub label: String,
+    pub range: Range<usize>,
+    pub children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    pub fn new(label: String, range: Range<usize>) -> Self {
+        TreeNode {
+            label,
+            range,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn children(mut self, children: Vec<TreeNode>) -> Self {
+        self.children = children;
+        self
+    }
+}
+
+/// A scrollable list drawn over the data frame
+///
+/// Each item carries a value, such as a buffer index or a byte offset, which is what the screen
+/// acts on when the item is selected. Filterable overlays only list items containing the text
+/// typed while they're open.
+pub struct Overlay {
+    pub kind: OverlayKind,
+    pub title: String,
+    pub items: Vec<String>,
+    pub values: Vec<usize>,
+    /// Bytes covered by each item, empty for overlays which aren't listing structures
+    pub ranges: Vec<Range<usize>>,
+    /// Position of the selection among the items matching the filter
+    pub selected: usize,
+    pub filterable: bool,
+    filter: String,
+    /// Indexes of the items matching the filter
+    matches: Vec<usize>,
+    scroll: usize,
+}
+
+impl Overlay {
+    /// Create an overlay listing `items`, the value of each item is its index
+    pub fn new(kind: OverlayKind, title: String, items: Vec<String>) -> Self {
+        let values = (0..items.len()).collect();
+        let matches = (0..items.len()).collect();
+        Overlay {
+            kind,
+            title,
+            items,
+            values,
+            ranges: Vec::new(),
+            selected: 0,
+            filterable: false,
+            filter: String::new(),
+            matches,
+            scroll: 0,
+        }
+    }
+
+    /// Create an overlay listing `nodes` as an indented tree, the value of each item is the start
+    /// of its range
+    pub fn tree(kind: OverlayKind, title: String, nodes: &[TreeNode]) -> Self {
+        let mut items = Vec::new();
+        let mut ranges = Vec::new();
+        flatten(nodes, "", &mut items, &mut ranges);
+        let values = ranges.iter().map(|range| range.start).collect();
+        let mut overlay = Overlay::new(kind, title, items).values(values);
+        overlay.ranges = ranges;
+        overlay
+    }
+
+    /// Set the value of each item, there should be one for every item
+    pub fn values(mut self, values: Vec<usize>) -> Self {
+        self.values = values;
+        self
+    }
+
+    /// Filter the items by text typed while the overlay is open
+    pub fn filterable(mut self) -> Self {
+        self.filterable = true;
+        self
+    }
+
+    /// Value of the selected item, `None` when no items match the filter
+    pub fn selected_value(&self) -> Option<usize> {
+        self.matches
+            .get(self.selected)
+            .and_then(|&index| self.values.get(index))
+            .cloned()
+    }
+
+    /// Bytes covered by the selected item, `None` when no items match the filter or the overlay
+    /// isn't listing structures
+    pub fn selected_range(&self) -> Option<Range<usize>> {
+        self.matches
+            .get(self.selected)
+            .and_then(|&index| self.ranges.get(index))
+            .cloned()
+    }
+
+    /// Move the selection up one item
+    pub fn up(&mut self) {
+        if self.selected > 0 {
+            self.selected -= 1;
+        }
+    }
+
+    /// Move the selection down one item
+    pub fn down(&mut self) {
+        if self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+        }
+    }
+
+    /// Add `c` to the end of the filter
+    pub fn push_filter(&mut self, c: char) {
+        self.filter.push(c);
+        self.apply_filter();
+    }
+
+    /// Remove the last character of the filter
+    pub fn pop_filter(&mut self) {
+        self.filter.pop();
+        self.apply_filter();
+    }
+
+    /// List only the items containing the filter, ignoring case
+    fn apply_filter(&mut self) {
+        let filter = self.filter.to_lowercase();
+        self.matches = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| item.to_lowercase().contains(&filter))
+            .map(|(index, _)| index)
+            .collect();
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    /// Adjust scroll so the selected item is visible in a list `height` items tall
+    fn scroll_to_selected(&mut self, height: usize) {
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if height > 0 && self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+    }
+}
+
+/// Add a line for each of `nodes` and their descendants, drawing the branches of the tree
+fn flatten(
+    nodes: &[TreeNode],
+    indent: &str,
+    items: &mut Vec<String>,
+    ranges: &mut Vec<Range<usize>>,
+) {
+    for (i, node) in nodes.iter().enumerate() {
+        let last = i + 1 == nodes.len();
+        let branch = if last { "└─ " } else { "├─ " };
+        items.push(format!("{}{}{}", indent, branch, node.label));
+        ranges.push(node.range.clone());
+
+        let indent = format!("{}{}", indent, if last { "   " } else { "│  " });
+        flatten(&node.children, &indent, items, ranges);
+    }
+}
+
+/// Draw `overlay` filling the area at `position`
+///
+/// The first row holds the title, the remaining rows list items with the selected one
+/// highlighted. Text is truncated to fit the area.
+pub fn render<T: Write>(
+    out: &mut T,
+    overlay: &mut Overlay,
+    position: Point,
+    dimensions: Dimension,
+) {
+    if dimensions.height == 0 || dimensions.width == 0 {
+        return;
+    }
+
+    let width = dimensions.width as usize;
+    let list_height = dimensions.height as usize - 1;
+    overlay.scroll_to_selected(list_height);
+
+    let title = if overlay.filterable {
+        format!("{} /{}", overlay.title, overlay.filter)
+    } else {
+        overlay.title.clone()
+    };
+
+    write!(
+        out,
+        "{}{}{}{}{}{}",
+        cursor::Goto(position.x, position.y),
+        color::Bg(color::White),
+        color::Fg(color::Black),
+        fit(&title, width),
+        color::Bg(color::Reset),
+        color::Fg(color::Reset),
+    )
+    .unwrap();
+
+    for row in 0..list_height {
+        let index = overlay.scroll + row;
+        let text = overlay
+            .matches
+            .get(index)
+            .map(|&item| overlay.items[item].as_str())
+            .unwrap_or("");
+        let y = position.y + 1 + row as u16;
+
+        if index == overlay.selected && index < overlay.matches.len() {
+            write!(
+                out,
+                "{}{}{}{}{}",
+                cursor::Goto(position.x, y),
+                color::Bg(color::Blue),
+                fit(text, width),
+                color::Bg(color::Reset),
+                color::Fg(color::Reset),
+            )
+            .unwrap();
+        } else {
+            write!(out, "{}{}", cursor::Goto(position.x, y), fit(text, width)).unwrap();
+        }
+    }
+}
+
+/// Truncate or pad `text` with spaces to exactly `width` characters
+pub(crate) fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text.chars().take(width).collect();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - len));
+    fitted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fit, Overlay, OverlayKind, TreeNode};
+
+    fn overlay(items: usize) -> Overlay {
+        let items = (0..items).map(|i| i.to_string()).collect();
+        Overlay::new(OverlayKind::Buffers, "title".to_owned(), items)
+    }
+
+    #[test]
+    fn fit_truncates_and_pads_text() {
+        assert_eq!(fit("hello", 3), "hel");
+        assert_eq!(fit("hi", 4), "hi  ");
+        assert_eq!(fit("", 0), "");
+    }
+
+    #[test]
+    fn selection_is_clamped_to_items() {
+        let mut overlay = overlay(2);
+        overlay.up();
+        assert_eq!(overlay.selected, 0);
+        overlay.down();
+        overlay.down();
+        assert_eq!(overlay.selected, 1);
+    }
+
+    #[test]
+    fn filtering_lists_matching_items() {
+        let items = vec!["Alpha".to_owned(), "beta".to_owned(), "gamma".to_owned()];
+        let mut overlay = Overlay::new(OverlayKind::Strings, "title".to_owned(), items)
+            .values(vec![10, 20, 30])
+            .filterable();
+
+        overlay.down();
+        assert_eq!(overlay.selected_value(), Some(20));
+
+        overlay.push_filter('a');
+        overlay.push_filter('m');
+        assert_eq!(overlay.selected_value(), Some(30));
+        overlay.down();
+        assert_eq!(overlay.selected_value(), Some(30));
+
+        overlay.pop_filter();
+        overlay.push_filter('L');
+        assert_eq!(overlay.selected_value(), Some(10));
+
+        overlay.push_filter('x');
+        assert_eq!(overlay.selected_value(), None);
+    }
+
+    #[test]
+    fn trees_are_listed_with_branches() {
+        let nodes = vec![
+            TreeNode::new("header".to_owned(), 0..4).children(vec![
+                TreeNode::new("magic".to_owned(), 0..2),
+                TreeNode::new("version".to_owned(), 2..4),
+            ]),
+            TreeNode::new("body".to_owned(), 4..10)
+                .children(vec![TreeNode::new("data".to_owned(), 4..10)]),
+        ];
+        let mut overlay = Overlay::tree(OverlayKind::Structures, "title".to_owned(), &nodes);
+
+        assert_eq!(
+            overlay.items,
+            vec![
+                "├─ header",
+                "│  ├─ magic",
+                "│  └─ version",
+                "└─ body",
+                "   └─ data",
+            ]
+        );
+        overlay.down();
+        overlay.down();
+        assert_eq!(overlay.selected_value(), Some(2));
+        assert_eq!(overlay.selected_range(), Some(2..4));
+    }
+
+    #[test]
+    fn scroll_follows_selection() {
+        let mut overlay = overlay(10);
+        for _ in 0..5 {
+            overlay.down();
+        }
+        overlay.scroll_to_selected(3);
+        assert_eq!(overlay.scroll, 3);
+
+        overlay.selected = 1;
+        overlay.scroll_to_selected(3);
+        assert_eq!(overlay.scroll, 1);
+    }
+}
@@ -0,0 +1,519 @@
+//! Decoding of the headers of PE/COFF executables
+//!
+//! Covers the DOS header, the NT headers with both PE32 and PE32+ optional headers, the data
+//! directories and the section table. Relative virtual addresses can be translated to file
+//! offsets through the section table.
+use std::error::Error as StdError;
+use std::fmt;
+use std::ops::Range;
+
+use crate::overlay::TreeNode;
+
+/// Size of the MS-DOS header
+const DOS_HEADER_SIZE: usize = 64;
+
+/// Offset in the DOS header of the offset of the NT headers
+const LFANEW_OFFSET: usize = 0x3C;
+
+/// Size of the COFF file header following the PE signature
+const FILE_HEADER_SIZE: usize = 20;
+
+/// Size of each entry of the section table
+const SECTION_HEADER_SIZE: usize = 40;
+
+/// Size of each data directory entry
+const DATA_DIRECTORY_SIZE: usize = 8;
+
+/// Index of the certificate table, whose address is a file offset rather than an RVA
+const CERTIFICATE_TABLE: usize = 4;
+
+const DATA_DIRECTORY_NAMES: [&str; 16] = [
+    "Export",
+    "Import",
+    "Resource",
+    "Exception",
+    "Certificate",
+    "Base relocation",
+    "Debug",
+    "Architecture",
+    "Global pointer",
+    "TLS",
+    "Load config",
+    "Bound import",
+    "IAT",
+    "Delay import",
+    "CLR runtime",
+    "Reserved",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The data doesn't start with an MS-DOS header pointing to a PE signature
+    NotPe,
+    /// The optional header has a magic number we don't know
+    Unsupported(&'static str),
+    /// A header lies past the end of the data
+    Truncated(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NotPe => write!(f, "not a PE file"),
+            Error::Unsupported(what) => write!(f, "unsupported PE {}", what),
+            Error::Truncated(what) => write!(f, "truncated PE {}", what),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from(u32_at(data, offset)?) | u64::from(u32_at(data, offset + 4)?) << 32)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    Pe32,
+    /// 64 bit images, with wider address fields in the optional header
+    Pe32Plus,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataDirectory {
+    pub rva: u32,
+    pub size: u32,
+    /// Bytes of the entry itself
+    pub header: Range<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub raw_size: u32,
+    pub raw_offset: u32,
+    pub characteristics: u32,
+    /// Bytes of the entry itself
+    pub header: Range<usize>,
+}
+
+impl Section {
+    /// Bytes of the section's contents in the file
+    pub fn data(&self) -> Range<usize> {
+        let start = self.raw_offset as usize;
+        start..start.saturating_add(self.raw_size as usize)
+    }
+}
+
+/// Decoded headers of a PE file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pe {
+    pub format: Format,
+    pub machine: u16,
+    pub characteristics: u16,
+    pub entry: u32,
+    pub image_base: u64,
+    /// Size of all the headers, which are mapped at the start of the image
+    pub headers_size: u32,
+    pub dos_header: Range<usize>,
+    /// Bytes of the signature, file header and optional header
+    pub nt_headers: Range<usize>,
+    pub file_header: Range<usize>,
+    pub optional_header: Range<usize>,
+    pub data_directories: Vec<DataDirectory>,
+    pub sections: Vec<Section>,
+    /// Bytes of the section table
+    pub section_table: Range<usize>,
+}
+
+/// Decode the headers of the PE file in `data`
+pub fn parse(data: &[u8]) -> Result<Pe, Error> {
+    if !data.starts_with(b"MZ") {
+        return Err(Error::NotPe);
+    }
+    let nt = u32_at(data, LFANEW_OFFSET).ok_or(Error::Truncated("DOS header"))? as usize;
+    if !data.get(nt..).is_some_and(|nt| nt.starts_with(b"PE\0\0")) {
+        return Err(Error::NotPe);
+    }
+
+    let file_header = nt + 4..nt + 4 + FILE_HEADER_SIZE;
+    let fields = (|| {
+        Some((
+            u16_at(data, file_header.start)?,
+            u16_at(data, file_header.start + 2)? as usize,
+            u16_at(data, file_header.start + 16)? as usize,
+            u16_at(data, file_header.start + 18)?,
+        ))
+    })();
+    let (machine, section_count, optional_size, characteristics) =
+        fields.ok_or(Error::Truncated("file header"))?;
+
+    let optional_header = file_header.end..file_header.end + optional_size;
+    let optional = data
+        .get(optional_header.clone())
+        .ok_or(Error::Truncated("optional header"))?;
+    let format = match u16_at(optional, 0) {
+        Some(0x10B) => Format::Pe32,
+        Some(0x20B) => Format::Pe32Plus,
+        _ => return Err(Error::Unsupported("optional header")),
+    };
+    let (image_base, directories) = match format {
+        Format::Pe32 => (u32_at(optional, 28).map(u64::from), 92),
+        Format::Pe32Plus => (u64_at(optional, 24), 108),
+    };
+    let fields = (|| {
+        Some((
+            u32_at(optional, 16)?,
+            image_base?,
+            u32_at(optional, 60)?,
+            u32_at(optional, directories)? as usize,
+        ))
+    })();
+    let (entry, image_base, headers_size, directory_count) =
+        fields.ok_or(Error::Truncated("optional header"))?;
+
+    // the directory count isn't trusted beyond what fits in the optional header
+    let first_directory = directories + 4;
+    let directory_count =
+        directory_count.min(optional_size.saturating_sub(first_directory) / DATA_DIRECTORY_SIZE);
+    let data_directories = (0..directory_count)
+        .map(|i| {
+            let offset = first_directory + i * DATA_DIRECTORY_SIZE;
+            let start = optional_header.start + offset;
+            DataDirectory {
+                rva: u32_at(optional, offset).unwrap_or(0),
+                size: u32_at(optional, offset + 4).unwrap_or(0),
+                header: start..start + DATA_DIRECTORY_SIZE,
+            }
+        })
+        .collect();
+
+    let section_table =
+        optional_header.end..optional_header.end + section_count * SECTION_HEADER_SIZE;
+    let sections = (0..section_count)
+        .map(|i| section(data, section_table.start + i * SECTION_HEADER_SIZE))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Error::Truncated("section table"))?;
+
+    Ok(Pe {
+        format,
+        machine,
+        characteristics,
+        entry,
+        image_base,
+        headers_size,
+        dos_header: 0..DOS_HEADER_SIZE.min(data.len()),
+        nt_headers: nt..optional_header.end,
+        file_header,
+        optional_header,
+        data_directories,
+        sections,
+        section_table,
+    })
+}
+
+fn section(data: &[u8], offset: usize) -> Option<Section> {
+    let header = offset..offset + SECTION_HEADER_SIZE;
+    let bytes = data.get(header.clone())?;
+    let name = &bytes[..8];
+    let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    Some(Section {
+        name: String::from_utf8_lossy(&name[..end]).into_owned(),
+        virtual_size: u32_at(bytes, 8)?,
+        virtual_address: u32_at(bytes, 12)?,
+        raw_size: u32_at(bytes, 16)?,
+        raw_offset: u32_at(bytes, 20)?,
+        characteristics: u32_at(bytes, 36)?,
+        header,
+    })
+}
+
+fn machine_name(machine: u16) -> String {
+    match machine {
+        0x014C => "x86".to_owned(),
+        0x01C0 => "ARM".to_owned(),
+        0x01C4 => "ARMv7".to_owned(),
+        0x0200 => "IA-64".to_owned(),
+        0x8664 => "x86-64".to_owned(),
+        0xAA64 => "ARM64".to_owned(),
+        _ => format!("machine {:#x}", machine),
+    }
+}
+
+/// Section permissions from its characteristics, in the style of `readelf`
+fn section_flags(characteristics: u32) -> String {
+    let flag = |bit, c| if characteristics & bit != 0 { c } else { '-' };
+    [
+        flag(0x4000_0000, 'R'),
+        flag(0x8000_0000, 'W'),
+        flag(0x2000_0000, 'X'),
+    ]
+    .iter()
+    .collect()
+}
+
+impl Pe {
+    /// Translate a relative virtual address to an offset in the file
+    ///
+    /// Addresses within the headers map to the same offset, others are looked up in the section
+    /// table. `None` if the address isn't backed by data in the file, such as uninitialised data
+    /// at the end of a section.
+    pub fn rva_to_offset(&self, rva: u32) -> Option<usize> {
+        if rva < self.headers_size {
+            return Some(rva as usize);
+        }
+        self.sections.iter().find_map(|section| {
+            let delta = rva.checked_sub(section.virtual_address)?;
+            // a section's data is cut short by its virtual size when that's given
+            let mapped = match section.virtual_size {
+                0 => section.raw_size,
+                size => size.min(section.raw_size),
+            };
+            if delta < mapped {
+                Some(section.raw_offset as usize + delta as usize)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Bytes of the contents of data directory `index`, if it's present in the file
+    fn directory_data(&self, index: usize) -> Option<Range<usize>> {
+        let directory = &self.data_directories[index];
+        if directory.rva == 0 || directory.size == 0 {
+            return None;
+        }
+        let start = if index == CERTIFICATE_TABLE {
+            directory.rva as usize
+        } else {
+            self.rva_to_offset(directory.rva)?
+        };
+        Some(start..start.saturating_add(directory.size as usize))
+    }
+
+    /// Short description of the file, such as "PE32+ x86-64"
+    pub fn summary(&self) -> String {
+        format!(
+            "{} {}",
+            match self.format {
+                Format::Pe32 => "PE32",
+                Format::Pe32Plus => "PE32+",
+            },
+            machine_name(self.machine)
+        )
+    }
+
+    /// Structures of the file arranged for browsing
+    ///
+    /// Data directory and section entries cover the bytes of the entry, with a child covering
+    /// the contents they point to when those are in the file.
+    pub fn tree(&self) -> Vec<TreeNode> {
+        let directories = self
+            .data_directories
+            .iter()
+            .enumerate()
+            .map(|(i, directory)| {
+                let name = DATA_DIRECTORY_NAMES.get(i).unwrap_or(&"Unknown");
+                let node = TreeNode::new(
+                    format!(
+                        "[{}] {} rva {:#x} size {:#x}",
+                        i, name, directory.rva, directory.size
+                    ),
+                    directory.header.clone(),
+                );
+                match self.directory_data(i) {
+                    Some(data) => node.children(vec![TreeNode::new(
+                        format!("contents at offset {:#x}", data.start),
+                        data,
+                    )]),
+                    None => node,
+                }
+            })
+            .collect();
+
+        let sections = self
+            .sections
+            .iter()
+            .enumerate()
+            .map(|(i, section)| {
+                let node = TreeNode::new(
+                    format!(
+                        "[{}] {} {} rva {:#x} size {:#x}",
+                        i,
+                        section.name,
+                        section_flags(section.characteristics),
+                        section.virtual_address,
+                        section.virtual_size
+                    ),
+                    section.header.clone(),
+                );
+                let data = section.data();
+                if data.is_empty() {
+                    node
+                } else {
+                    node.children(vec![TreeNode::new(
+                        format!("contents at offset {:#x}", data.start),
+                        data,
+                    )])
+                }
+            })
+            .collect();
+
+        vec![
+            TreeNode::new("DOS header".to_owned(), self.dos_header.clone()),
+            TreeNode::new("NT headers".to_owned(), self.nt_headers.clone()).children(vec![
+                TreeNode::new(
+                    "Signature".to_owned(),
+                    self.nt_headers.start..self.file_header.start,
+                ),
+                TreeNode::new(
+                    format!(
+                        "File header, {} {} sections",
+                        machine_name(self.machine),
+                        self.sections.len()
+                    ),
+                    self.file_header.clone(),
+                ),
+                TreeNode::new(
+                    format!(
+                        "Optional header, {} entry rva {:#x} image base {:#x}",
+                        self.summary(),
+                        self.entry,
+                        self.image_base
+                    ),
+                    self.optional_header.clone(),
+                )
+                .children(vec![TreeNode::new(
+                    format!("Data directories ({})", self.data_directories.len()),
+                    self.data_directories
+                        .first()
+                        .map_or(0, |first| first.header.start)
+                        ..self.optional_header.end,
+                )
+                .children(directories)]),
+            ]),
+            TreeNode::new(
+                format!("Section table ({})", self.sections.len()),
+                self.section_table.clone(),
+            )
+            .children(sections),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a PE32+ file with `.text` at RVA 0x1000 and `.bss` at RVA 0x2000 and an import
+    /// directory pointing into `.text`
+    fn build() -> Vec<u8> {
+        let mut data = vec![0; 0x200];
+        data[..2].copy_from_slice(b"MZ");
+        data[LFANEW_OFFSET] = 0x40;
+        data[0x40..0x44].copy_from_slice(b"PE\0\0");
+
+        let put16 = |data: &mut Vec<u8>, at: usize, v: u16| {
+            data[at..at + 2].copy_from_slice(&v.to_le_bytes())
+        };
+        let put32 = |data: &mut Vec<u8>, at: usize, v: u32| {
+            data[at..at + 4].copy_from_slice(&v.to_le_bytes())
+        };
+
+        // file header
+        put16(&mut data, 0x44, 0x8664);
+        put16(&mut data, 0x46, 2);
+        put16(&mut data, 0x54, 112 + 2 * 8);
+        // optional header
+        let opt = 0x58;
+        put16(&mut data, opt, 0x20B);
+        put32(&mut data, opt + 16, 0x1010);
+        put32(&mut data, opt + 24, 0x4000_0000);
+        put32(&mut data, opt + 60, 0x200);
+        put32(&mut data, opt + 108, 16);
+        put32(&mut data, opt + 112 + 8, 0x1020);
+        put32(&mut data, opt + 112 + 12, 0x28);
+        // sections
+        let table = opt + 128;
+        data[table..table + 5].copy_from_slice(b".text");
+        put32(&mut data, table + 8, 0x80);
+        put32(&mut data, table + 12, 0x1000);
+        put32(&mut data, table + 16, 0x100);
+        put32(&mut data, table + 20, 0x100);
+        put32(&mut data, table + 36, 0x6000_0020);
+        data[table + 40..table + 44].copy_from_slice(b".bss");
+        put32(&mut data, table + 48, 0x400);
+        put32(&mut data, table + 52, 0x2000);
+        data
+    }
+
+    #[test]
+    fn it_decodes_the_headers() {
+        let pe = parse(&build()).unwrap();
+
+        assert_eq!(pe.format, Format::Pe32Plus);
+        assert_eq!(pe.summary(), "PE32+ x86-64");
+        assert_eq!(pe.entry, 0x1010);
+        assert_eq!(pe.image_base, 0x4000_0000);
+        assert_eq!(pe.nt_headers, 0x40..0xD8);
+        // only two directories fit in the optional header
+        assert_eq!(pe.data_directories.len(), 2);
+        assert_eq!(pe.data_directories[1].rva, 0x1020);
+
+        let names: Vec<&str> = pe.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec![".text", ".bss"]);
+        assert_eq!(pe.sections[0].data(), 0x100..0x200);
+    }
+
+    #[test]
+    fn rvas_are_translated_through_the_section_table() {
+        let pe = parse(&build()).unwrap();
+
+        assert_eq!(pe.rva_to_offset(0x40), Some(0x40));
+        assert_eq!(pe.rva_to_offset(0x1000), Some(0x100));
+        assert_eq!(pe.rva_to_offset(0x107F), Some(0x17F));
+        // past the virtual size of .text, and uninitialised .bss
+        assert_eq!(pe.rva_to_offset(0x1080), None);
+        assert_eq!(pe.rva_to_offset(0x2000), None);
+    }
+
+    #[test]
+    fn it_rejects_other_and_truncated_files() {
+        assert_eq!(parse(b"\x7FELF"), Err(Error::NotPe));
+
+        let mut dos = vec![0; 0x40];
+        dos[..2].copy_from_slice(b"MZ");
+        assert_eq!(parse(&dos), Err(Error::NotPe));
+
+        let data = build();
+        assert_eq!(parse(&data[..0x50]), Err(Error::Truncated("file header")));
+        assert_eq!(
+            parse(&data[..0x120]),
+            Err(Error::Truncated("section table"))
+        );
+    }
+
+    #[test]
+    fn the_tree_points_at_directory_contents() {
+        let pe = parse(&build()).unwrap();
+        let tree = pe.tree();
+
+        let directories = &tree[1].children[2].children[0];
+        assert_eq!(directories.children[0].children.len(), 0);
+        assert_eq!(directories.children[1].children[0].range, 0x120..0x148);
+        // .bss has nothing in the file
+        assert_eq!(tree[2].children[1].children.len(), 0);
+    }
+}
@@ -0,0 +1,59 @@
+//! Notification of terminal size changes
+//!
+//! termion has no resize events, so SIGWINCH is caught directly. The signal handler writes to a
+//! pipe (one of the few things which is safe to do from a handler) and a thread blocked reading
+//! the other end queries the new terminal size.
+use std::io;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+
+use crate::screen::Frame;
+
+/// Write end of the pipe used by the signal handler, -1 until `watch` is called
+static PIPE_WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_sigwinch(_: libc::c_int) {
+    let fd = PIPE_WRITE_FD.load(Ordering::Relaxed);
+    if fd >= 0 {
+        let byte = 0u8;
+        unsafe {
+            libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+        }
+    }
+}
+
+/// Call `on_resize` from a background thread with the new frame whenever the terminal is resized
+pub fn watch<F>(mut on_resize: F) -> io::Result<()>
+where
+    F: FnMut(Frame) + Send + 'static,
+{
+    let mut fds = [0 as libc::c_int; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let [read_fd, write_fd] = fds;
+    PIPE_WRITE_FD.store(write_fd, Ordering::Relaxed);
+
+    let handler = on_sigwinch as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    if unsafe { libc::signal(libc::SIGWINCH, handler) } == libc::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+
+    thread::spawn(move || {
+        let mut byte = 0u8;
+        loop {
+            let read = unsafe { libc::read(read_fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+            if read < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            if read <= 0 {
+                break;
+            }
+            if let Ok((width, height)) = termion::terminal_size() {
+                on_resize(Frame { width, height });
+            }
+        }
+    });
+
+    Ok(())
+}
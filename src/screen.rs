@@ -1,14 +1,50 @@
+use std::cmp;
+use std::convert::TryFrom;
 use std::error::Error as StdError;
 use std::fmt;
+use std::fs;
 use std::io::Write;
-
-use crate::context::Context;
-use crate::line::Mode;
+use std::ops::Range;
+
+use termion::event::Event;
+
+use crate::bookmark::{self, Bookmark};
+use crate::buffer::{Buffer, View};
+use crate::clipboard;
+use crate::elf;
+use crate::encoding::Encoding;
+use crate::hash::Algorithm;
+use crate::layout::{Direction, Layout, Rect};
+use crate::line::{ByteClass, Format, Highlight, Mode, Palette};
+use crate::macros::{self, Macros};
+use crate::magic::FileType;
+use crate::minimap::{self, Measure};
+use crate::overlay::{Overlay, OverlayKind};
+use crate::pe;
+use crate::strings;
+use crate::template::{self, panel, Template};
+use crate::transform::Transform;
+
+/// Background colour of the selected structure
+const STRUCTURE_HIGHLIGHT: u8 = 24;
+
+/// Background colour of bytes changed since the buffer was saved
+const EDIT_HIGHLIGHT: u8 = 88;
+
+/// Background colour of a transform being previewed
+const PREVIEW_HIGHLIGHT: u8 = 54;
+
+/// Background colours of bookmarks, taken in turn so neighbouring bookmarks can be told apart
+const BOOKMARK_COLOURS: [u8; 4] = [22, 58, 94, 60];
+
+/// Narrowest data area the template panel is shown beside
+const MIN_DATA_WIDTH_BESIDE_PANEL: u16 = 16;
 
 #[derive(Debug, PartialEq)]
 pub enum State {
     Wait,
     Prompt,
+    Overlay,
 }
 
 impl fmt::Display for State {
@@ -16,18 +52,29 @@ impl fmt::Display for State {
         match *self {
             State::Wait => write!(f, "State::Wait"),
             State::Prompt => write!(f, "State::Prompt"),
+            State::Overlay => write!(f, "State::Overlay"),
         }
     }
 }
 
 /// Represents the screen's display dimensions
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Frame {
     pub width: u16,
     pub height: u16,
 }
 
+/// A viewport into one of the open buffers
+pub struct Window {
+    pub buffer: usize,
+    pub view: View,
+}
+
 /// Abstracts rendering, and mainting state for a viewport with a prompt bar
-pub struct Screen<'a, T>
+///
+/// The data frame can be split into several windows, the public position fields always describe
+/// the focused window. Other windows keep their state in the window list until focused.
+pub struct Screen<T>
 where
     T: Write,
 {
@@ -40,11 +87,34 @@ where
     data_frame_dirty: bool,
     prompt_bar_dirty: bool,
     status_bar_dirty: bool,
+    clear_before_render: bool,
     pub switch_focus_to_prompt: bool,
-    pub data: &'a [u8],
+    buffers: Vec<Buffer>,
+    current_buffer: usize,
+    windows: Vec<Window>,
+    focused_window: usize,
+    layout: Layout,
     pub out: T,
     prompt_text: String,
-    pub text_display_mode: Mode,
+    message: Option<String>,
+    /// The last failure reported with `error`, kept until taken so scripts can tell it happened
+    error: Option<String>,
+    /// Output isn't a terminal, so nothing can be sent through it to the clipboard
+    headless: bool,
+    pub format: Format,
+    pub wrap: bool,
+    /// Colour bytes by class using the palette
+    pub colours: bool,
+    pub palette: Palette,
+    /// What the minimap beside the data frame shows, hidden when `None`
+    pub minimap: Option<Measure>,
+    overlay: Option<Overlay>,
+    /// Set once quitting with unsaved changes has been warned about
+    quit_pending: bool,
+    /// Keystrokes recorded with `q{register}`, replayed with `@{register}`
+    pub macros: Macros,
+    /// Digits typed before a key, shown in the status bar until the key is pressed
+    count: Option<usize>,
 }
 
 pub struct Point {
@@ -57,25 +127,993 @@ pub struct Dimension {
     pub height: u16,
 }
 
-impl<'a, T: Write> Screen<'a, T> {
-    pub fn new(data: &'a [u8], frame: Frame, out: T) -> Screen<T> {
+impl<T: Write> Screen<T> {
+    pub fn new(buffer: Buffer, frame: Frame, out: T) -> Screen<T> {
         let default_prompt_capacity = 256;
+        let view = buffer.view.clone();
         Screen {
             state: State::Wait,
-            frame: frame,
-            offset: 0,
-            scroll_y: 0,
-            scroll_x: 0,
-            bytes_per_row: 32,
-            data: data,
+            frame,
+            offset: view.offset,
+            scroll_y: view.scroll_y,
+            scroll_x: view.scroll_x,
+            bytes_per_row: view.bytes_per_row,
+            buffers: vec![buffer],
+            current_buffer: 0,
+            windows: vec![Window {
+                buffer: 0,
+                view: view.clone(),
+            }],
+            focused_window: 0,
+            layout: Layout::default(),
             data_frame_dirty: true,
             prompt_bar_dirty: true,
             status_bar_dirty: true,
+            clear_before_render: false,
             switch_focus_to_prompt: false,
             out,
             prompt_text: String::with_capacity(default_prompt_capacity),
-            text_display_mode: Mode::Hex,
+            message: None,
+            error: None,
+            headless: false,
+            format: view.format,
+            wrap: view.wrap,
+            colours: true,
+            palette: Palette::default(),
+            minimap: None,
+            overlay: None,
+            quit_pending: false,
+            macros: Macros::default(),
+            count: None,
+        }
+    }
+
+    /// Data held by the current buffer
+    pub fn data(&self) -> &[u8] {
+        &self.buffers[self.current_buffer].data
+    }
+
+    /// The buffer currently being displayed
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffers[self.current_buffer]
+    }
+
+    /// Every open buffer, the current one holding the latest view
+    pub fn buffers(&mut self) -> &[Buffer] {
+        self.buffers[self.current_buffer].view = self.view();
+        &self.buffers
+    }
+
+    /// Snapshot of the current position and display settings
+    pub fn view(&self) -> View {
+        View {
+            offset: self.offset,
+            scroll_y: self.scroll_y,
+            scroll_x: self.scroll_x,
+            bytes_per_row: self.bytes_per_row,
+            format: self.format,
+            wrap: self.wrap,
+        }
+    }
+
+    fn load_view(&mut self, view: View) {
+        self.offset = view.offset;
+        self.scroll_y = view.scroll_y;
+        self.scroll_x = view.scroll_x;
+        self.bytes_per_row = view.bytes_per_row;
+        self.format = view.format;
+        self.wrap = view.wrap;
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.data_frame_dirty = true;
+        self.prompt_bar_dirty = true;
+        self.status_bar_dirty = true;
+    }
+
+    /// Add a buffer to the buffer list and switch to it
+    pub fn open_buffer(&mut self, buffer: Buffer) {
+        self.buffers.push(buffer);
+        self.switch_buffer(self.buffers.len() - 1);
+    }
+
+    /// Switch to the buffer at `index` in the buffer list
+    ///
+    /// The view state of the buffer being left is saved and restored when switching back to it.
+    pub fn switch_buffer(&mut self, index: usize) {
+        if index >= self.buffers.len() {
+            return;
+        }
+        self.mark_all_dirty();
+        self.state = State::Wait;
+        // the template panel comes and goes with the buffers' templates
+        if self.buffers[index].template.is_some() != self.buffer().template.is_some() {
+            self.clear_before_render = true;
+        }
+
+        self.buffers[self.current_buffer].view = self.view();
+        self.current_buffer = index;
+        self.windows[self.focused_window].buffer = index;
+        let view = self.buffers[index].view.clone();
+        self.load_view(view);
+    }
+
+    /// Switch to the next buffer, wrapping around at the end of the list
+    pub fn next_buffer(&mut self) {
+        let index = (self.current_buffer + 1) % self.buffers.len();
+        self.switch_buffer(index);
+    }
+
+    /// Switch to the previous buffer, wrapping around at the start of the list
+    pub fn previous_buffer(&mut self) {
+        let len = self.buffers.len();
+        let index = (self.current_buffer + len - 1) % len;
+        self.switch_buffer(index);
+    }
+
+    /// Split the focused window in two, the new window takes the top or left half and focus
+    ///
+    /// The new window shows `buffer` when given, otherwise the same buffer and position as the
+    /// focused window.
+    pub fn split(&mut self, direction: Direction, buffer: Option<Buffer>) {
+        let view = self.view();
+        let new_window = self.windows.len();
+        self.windows.push(Window {
+            buffer: self.current_buffer,
+            view,
+        });
+        self.layout
+            .split(self.focused_window, direction, new_window);
+        self.focus_window(new_window);
+
+        if let Some(buffer) = buffer {
+            self.open_buffer(buffer);
+        }
+    }
+
+    /// Move focus to the window at `index` in the window list
+    pub fn focus_window(&mut self, index: usize) {
+        if index >= self.windows.len() {
+            return;
+        }
+        self.mark_all_dirty();
+        self.state = State::Wait;
+
+        self.windows[self.focused_window] = Window {
+            buffer: self.current_buffer,
+            view: self.view(),
+        };
+        self.load_window(index);
+    }
+
+    fn load_window(&mut self, index: usize) {
+        self.focused_window = index;
+        self.current_buffer = self.windows[index].buffer;
+        let view = self.windows[index].view.clone();
+        self.load_view(view);
+    }
+
+    /// Move focus to the next window, wrapping around at the end of the list
+    pub fn next_window(&mut self) {
+        let index = (self.focused_window + 1) % self.windows.len();
+        self.focus_window(index);
+    }
+
+    /// Move focus to the previous window, wrapping around at the start of the list
+    pub fn previous_window(&mut self) {
+        let len = self.windows.len();
+        let index = (self.focused_window + len - 1) % len;
+        self.focus_window(index);
+    }
+
+    /// Close the focused window, the last window can't be closed
+    pub fn close_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.error("can't close the last window");
+            return;
+        }
+        self.mark_all_dirty();
+        self.state = State::Wait;
+
+        self.windows.remove(self.focused_window);
+        self.layout.remove(self.focused_window);
+        let index = self.focused_window.min(self.windows.len() - 1);
+        self.load_window(index);
+    }
+
+    /// Resize the focused window to `size` rows or columns depending on `direction`
+    pub fn resize_window(&mut self, direction: Direction, size: usize) {
+        self.mark_all_dirty();
+        self.state = State::Wait;
+
+        let size = u16::try_from(size).unwrap_or(u16::MAX);
+        let area = self.data_area();
+        self.layout
+            .resize(self.focused_window, direction, size, area);
+    }
+
+    /// Number of windows the data frame is split into
+    pub fn window_count(&self) -> usize {
+        self.windows.len()
+    }
+
+    /// Area above the status bar shared between windows, left of the template panel and minimap
+    fn data_area(&self) -> Rect {
+        let minimap_width = if self.minimap_shown() {
+            minimap::WIDTH
+        } else {
+            0
+        };
+        let panel_width = if self.panel_shown() { panel::WIDTH } else { 0 };
+        Rect {
+            x: 1,
+            y: 1,
+            width: self.frame.width - minimap_width - panel_width,
+            height: self
+                .frame
+                .height
+                .saturating_sub(Self::status_bar_height() + Self::prompt_height()),
+        }
+    }
+
+    /// Whether the minimap is enabled and there's room for it beside some data
+    fn minimap_shown(&self) -> bool {
+        self.minimap.is_some() && self.frame.width > minimap::WIDTH
+    }
+
+    /// Area of the minimap at the right edge of the frame
+    fn minimap_area(&self) -> Rect {
+        Rect {
+            x: self.frame.width - minimap::WIDTH + 1,
+            width: minimap::WIDTH,
+            ..self.data_area()
+        }
+    }
+
+    /// Whether the current buffer has a template applied and there's room for its fields beside
+    /// some data
+    fn panel_shown(&self) -> bool {
+        let minimap_width = if self.minimap_shown() {
+            minimap::WIDTH
+        } else {
+            0
+        };
+        self.buffer().template.is_some()
+            && self.frame.width > minimap_width + panel::WIDTH + MIN_DATA_WIDTH_BESIDE_PANEL
+    }
+
+    /// Area of the template panel, between the data and the minimap
+    fn panel_area(&self) -> Rect {
+        let data_area = self.data_area();
+        Rect {
+            x: data_area.x + data_area.width,
+            width: panel::WIDTH,
+            ..data_area
+        }
+    }
+
+    /// Apply the template in the file at `path` to the current buffer
+    ///
+    /// The template is laid over the data from `offset`, or from the top left byte when not
+    /// given. Problems reading the template or applying it are shown as messages.
+    pub fn apply_template(&mut self, path: &str, offset: Option<usize>) {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => return self.error(&format!("{}: {}", path, error)),
+        };
+        let template = match template::parse(&source) {
+            Ok(template) => template,
+            Err(error) => return self.error(&format!("{}: {}", path, error)),
+        };
+        self.lay_over(&template, offset);
+    }
+
+    /// Apply the struct or union `name` from the C header at `path` as a template
+    pub fn apply_c_struct(&mut self, path: &str, name: &str, offset: Option<usize>) {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => return self.error(&format!("{}: {}", path, error)),
+        };
+        let header = match template::c::parse(&source) {
+            Ok(header) => header,
+            Err(error) => return self.error(&format!("{}: {}", path, error)),
+        };
+        match header.template(name) {
+            Some(template) => self.lay_over(&template, offset),
+            None => self.error(&format!("{}: no struct or union called {}", path, name)),
+        }
+    }
+
+    /// Apply `template` to the current buffer at `offset`, or the top of the view
+    fn lay_over(&mut self, template: &Template, offset: Option<usize>) {
+        let offset = offset
+            .unwrap_or_else(|| top_left_byte_index(self.offset, self.scroll_y, self.bytes_per_row));
+        let applied = template.apply(self.data(), offset);
+        let error = applied.error.clone();
+        let message = format!("{} fields from {:#x}", applied.fields.len(), offset);
+        self.buffers[self.current_buffer].template = Some(applied);
+        self.mark_all_dirty();
+        self.clear_before_render = true;
+        match error {
+            Some(error) => self.error(&error),
+            None => self.message(&message),
+        }
+    }
+
+    /// Remove the template applied to the current buffer
+    pub fn clear_template(&mut self) {
+        self.buffers[self.current_buffer].template = None;
+        self.mark_all_dirty();
+        self.clear_before_render = true;
+    }
+
+    /// Show `measure` in the minimap, or hide it when `None`
+    pub fn set_minimap(&mut self, measure: Option<Measure>) {
+        self.mark_all_dirty();
+        self.clear_before_render = true;
+        self.state = State::Wait;
+
+        self.minimap = measure;
+    }
+
+    /// Show entropy in the minimap, or hide it if it's showing anything
+    pub fn toggle_minimap(&mut self) {
+        let measure = match self.minimap {
+            Some(..) => None,
+            None => Some(Measure::Entropy),
+        };
+        self.set_minimap(measure);
+    }
+
+    /// Summarise another few blocks of the current buffer for the minimap
+    ///
+    /// Returns false once there's nothing left to summarise.
+    pub fn summarise(&mut self) -> bool {
+        if !self.minimap_shown() {
+            return false;
+        }
+        let buffer = &mut self.buffers[self.current_buffer];
+        if buffer.summary.is_complete(&buffer.data) {
+            return false;
+        }
+
+        self.data_frame_dirty = true;
+        buffer
+            .summary
+            .update(&buffer.data, minimap::BLOCKS_PER_STEP);
+        true
+    }
+
+    /// Jump to the start of the next region of the minimap
+    pub fn next_region(&mut self) {
+        let height = self.minimap_area().height as usize;
+        let len = self.data().len();
+        if !self.minimap_shown() || height == 0 {
+            self.message("minimap is off");
+            return;
+        }
+
+        // regions starting within the top row would be jumped to without moving
+        let top = top_left_byte_index(self.offset, self.scroll_y, self.bytes_per_row);
+        let next = (0..height)
+            .map(|i| minimap::region(i, height, len).start)
+            .find(|&start| start >= top + self.bytes_per_row && start < len);
+        if let Some(start) = next {
+            self.goto(start);
+        }
+    }
+
+    /// Jump to the start of the previous region of the minimap
+    pub fn previous_region(&mut self) {
+        let height = self.minimap_area().height as usize;
+        let len = self.data().len();
+        if !self.minimap_shown() || height == 0 {
+            self.message("minimap is off");
+            return;
+        }
+
+        let top = top_left_byte_index(self.offset, self.scroll_y, self.bytes_per_row);
+        let previous = (0..height)
+            .rev()
+            .map(|i| minimap::region(i, height, len).start)
+            .find(|&start| start < top);
+        if let Some(start) = previous {
+            self.goto(start);
+        }
+    }
+
+    /// Area of each window, with the rows reserved for window headers
+    fn window_areas(&self) -> Vec<(usize, Rect)> {
+        self.layout.compute(self.data_area())
+    }
+
+    /// Area available for displaying data in a window
+    ///
+    /// When the data frame is split each window gets a header row naming its buffer.
+    fn content_area(&self, area: Rect) -> Rect {
+        if self.windows.len() > 1 && area.height > 0 {
+            Rect {
+                y: area.y + 1,
+                height: area.height - 1,
+                ..area
+            }
+        } else {
+            area
+        }
+    }
+
+    fn focused_content_area(&self) -> Rect {
+        let area = self
+            .window_areas()
+            .into_iter()
+            .find(|&(window, _)| window == self.focused_window)
+            .map(|(_, area)| area)
+            .unwrap_or_else(|| self.data_area());
+        self.content_area(area)
+    }
+
+    /// Show the list of open buffers
+    pub fn show_buffer_list(&mut self) {
+        let items = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(i, buffer)| {
+                let current = if i == self.current_buffer { '%' } else { ' ' };
+                format!(
+                    "{:>3}{} {} ({} bytes)",
+                    i + 1,
+                    current,
+                    buffer.path(),
+                    buffer.data.len()
+                )
+            })
+            .collect();
+
+        let mut overlay = Overlay::new(OverlayKind::Buffers, "Buffers".to_owned(), items);
+        overlay.selected = self.current_buffer;
+        self.show_overlay(overlay);
+    }
+
+    /// List runs of at least `min_length` characters in the current buffer
+    ///
+    /// Both ASCII and UTF-16LE strings are listed unless an `encoding` is given. Selecting a
+    /// string jumps to it.
+    pub fn show_strings(&mut self, min_length: usize, encoding: Option<Encoding>) {
+        let encodings = match encoding {
+            Some(encoding) => vec![encoding],
+            None => vec![Encoding::Ascii, Encoding::Utf16Le],
+        };
+        let found = strings::find(self.data(), min_length, &encodings);
+        if found.is_empty() {
+            self.reset_prompt();
+            self.message("no strings found");
+            return;
+        }
+
+        let items = found
+            .iter()
+            .map(|string| {
+                format!(
+                    "{:#010x} {:<7} {}",
+                    string.offset,
+                    string.encoding.name(),
+                    string.text
+                )
+            })
+            .collect();
+        let offsets = found.iter().map(|string| string.offset).collect();
+        let title = format!("Strings ({})", found.len());
+        let overlay = Overlay::new(OverlayKind::Strings, title, items)
+            .values(offsets)
+            .filterable();
+        self.show_overlay(overlay);
+    }
+
+    /// Show the structures of the current buffer as a tree, for file types with a known layout
+    ///
+    /// Selecting a structure highlights its bytes and jumps to its start.
+    pub fn show_structures(&mut self) {
+        let buffer = self.buffer();
+        let (title, tree) = match buffer.context.file_type {
+            Some(FileType::Elf) => match elf::parse(&buffer.data) {
+                Ok(elf) => (elf.summary(), elf.tree()),
+                Err(error) => return self.error(&error.to_string()),
+            },
+            Some(FileType::Pe) => match pe::parse(&buffer.data) {
+                Ok(pe) => (pe.summary(), pe.tree()),
+                Err(error) => return self.error(&error.to_string()),
+            },
+            Some(file_type) => {
+                let text = format!("no structures known for {} files", file_type.name());
+                return self.error(&text);
+            }
+            None => return self.error("unknown file type"),
+        };
+
+        let overlay = Overlay::tree(OverlayKind::Structures, title, &tree).filterable();
+        self.show_overlay(overlay);
+    }
+
+    /// Highlight the contents of the ELF section called `name` and jump to them
+    pub fn goto_section(&mut self, name: &str) {
+        let range = match elf::parse(self.data()) {
+            Ok(elf) => match elf.section(name) {
+                Some(section) => section.data(),
+                None => return self.error(&format!("no section named {}", name)),
+            },
+            Err(error) => return self.error(&error.to_string()),
+        };
+        match range {
+            Some(range) => self.select_structure(range),
+            None => self.error(&format!("section {} has no data in the file", name)),
+        }
+    }
+
+    /// Jump to the byte at `offset`, refusing offsets past the end of the buffer
+    pub fn goto_offset(&mut self, offset: usize) {
+        if offset >= self.data().len() {
+            return self.error(&format!("offset {:#x} is past the end of the file", offset));
+        }
+        self.goto(offset);
+    }
+
+    /// Jump to the byte a relative virtual address of the current PE file is loaded from
+    pub fn goto_rva(&mut self, rva: usize) {
+        let pe = match pe::parse(self.data()) {
+            Ok(pe) => pe,
+            Err(error) => return self.error(&error.to_string()),
+        };
+        let offset = u32::try_from(rva)
+            .ok()
+            .and_then(|rva| pe.rva_to_offset(rva));
+        match offset {
+            Some(offset) => self.goto_offset(offset),
+            None => self.error(&format!("rva {:#x} isn't backed by the file", rva)),
+        }
+    }
+
+    /// Show the `algorithm` checksum of the current buffer, or of `length` bytes from `start`
+    ///
+    /// With `copy`, the result is also put on the clipboard through the terminal, when there is
+    /// one.
+    pub fn hash(&mut self, algorithm: Algorithm, range: Option<(usize, usize)>, copy: bool) {
+        let range = match range {
+            None => 0..self.data().len(),
+            Some((start, length)) => match self.range(start, length) {
+                Some(range) => range,
+                None => return,
+            },
+        };
+        let digest = algorithm.digest(&self.data()[range.clone()]);
+
+        let mut message = format!(
+            "{} {:#x}..{:#x}: {}",
+            algorithm.name(),
+            range.start,
+            range.end,
+            digest
+        );
+        if copy && self.headless {
+            message.push_str(" (not copied: headless)");
+        } else if copy {
+            let copied =
+                write!(self.out, "{}", clipboard::osc52(&digest)).and_then(|_| self.out.flush());
+            message.push_str(match copied {
+                Ok(()) => " (copied)",
+                Err(_) => " (couldn't copy)",
+            });
+        }
+        self.message(&message);
+    }
+
+    /// Write `pattern` repeatedly over `length` bytes from `start`, as a single edit
+    pub fn fill(&mut self, start: usize, length: usize, pattern: &[u8]) {
+        if self.preview_pending() {
+            return;
+        }
+        if length == 0 {
+            self.message("nothing to fill");
+            return;
+        }
+        let range = match self.range(start, length) {
+            Some(range) => range,
+            None => return,
+        };
+        let bytes: Vec<u8> = pattern.iter().cycle().take(length).cloned().collect();
+        self.buffers[self.current_buffer].write(range.start, &bytes, "fill");
+        self.quit_pending = false;
+        self.mark_all_dirty();
+        self.message(&format!("filled {:#x} bytes from {:#x}", length, start));
+    }
+
+    /// Preview `transform` over `length` bytes from `start`, or over the selected structure or
+    /// the whole buffer when no range is given
+    ///
+    /// The result replaces any earlier preview and is only kept once committed.
+    pub fn transform(&mut self, transform: Transform, range: Option<(usize, usize)>) {
+        let buffer = &self.buffers[self.current_buffer];
+        let range = match range {
+            Some((start, length)) => match self.range(start, length) {
+                Some(range) => range,
+                None => return,
+            },
+            None => buffer
+                .highlight
+                .clone()
+                .map(|range| range.start.min(buffer.data.len())..range.end.min(buffer.data.len()))
+                .unwrap_or(0..buffer.data.len()),
+        };
+        let buffer = &mut self.buffers[self.current_buffer];
+        self.quit_pending = false;
+        // a new preview replaces the old one rather than transforming its result
+        let mut bytes = buffer.unpreviewed(range.clone());
+        transform.apply(&mut bytes);
+        buffer.set_preview(range.start, &bytes, transform.name());
+        self.mark_all_dirty();
+        self.message(&format!(
+            "previewing {} over {:#x}..{:#x}, :commit to keep it or :revert",
+            transform, range.start, range.end
+        ));
+    }
+
+    /// Keep the transform being previewed as an edit
+    pub fn commit(&mut self) {
+        let buffer = &mut self.buffers[self.current_buffer];
+        let committed = match buffer.preview.as_ref().map(|preview| preview.name.clone()) {
+            Some(name) if buffer.commit() => Some(name),
+            _ => None,
+        };
+        self.mark_all_dirty();
+        match committed {
+            Some(name) => self.message(&format!("committed {}", name)),
+            None => self.error("nothing to commit"),
+        }
+    }
+
+    /// Undo the transform being previewed, or the latest edit
+    pub fn revert(&mut self) {
+        let reverted = self.buffers[self.current_buffer].revert();
+        self.mark_all_dirty();
+        match reverted {
+            Some(edit) => self.message(&format!(
+                "reverted {} over {:#x}..{:#x}",
+                edit.name, edit.range.start, edit.range.end
+            )),
+            None => self.error("nothing to revert"),
+        }
+    }
+
+    /// Whether a preview is waiting to be committed or reverted, saying so if it is
+    fn preview_pending(&mut self) -> bool {
+        let pending = self.buffers[self.current_buffer].preview.is_some();
+        if pending {
+            self.error("commit or revert the preview first");
+        }
+        pending
+    }
+
+    /// The `length` bytes from `start` in the current buffer, or a message if they don't fit
+    fn range(&mut self, start: usize, length: usize) -> Option<Range<usize>> {
+        match start.checked_add(length) {
+            Some(end) if end <= self.data().len() => Some(start..end),
+            _ => {
+                self.error(&format!(
+                    "{:#x} bytes from {:#x} runs past the end of the file",
+                    length, start
+                ));
+                None
+            }
+        }
+    }
+
+    /// Write the current buffer to `path`, or back to its own file
+    pub fn save(&mut self, path: Option<&str>) {
+        if self.preview_pending() {
+            return;
+        }
+        let buffer = &mut self.buffers[self.current_buffer];
+        let target = path.unwrap_or_else(|| buffer.path()).to_owned();
+        let saved = buffer
+            .save(path)
+            .map(|()| format!("wrote {:#x} bytes to {}", buffer.data.len(), target))
+            .map_err(|error| format!("{}: {}", target, error));
+        self.quit_pending = false;
+        self.mark_all_dirty();
+        match saved {
+            Ok(message) => self.message(&message),
+            Err(error) => self.error(&error),
+        }
+    }
+
+    /// Whether to quit, which needs asking twice when there are unsaved changes
+    pub fn confirm_quit(&mut self) -> bool {
+        let modified = self
+            .buffers
+            .iter()
+            .filter(|buffer| buffer.modified())
+            .count();
+        if modified == 0 || self.quit_pending {
+            return true;
+        }
+        self.quit_pending = true;
+        let buffers = if modified == 1 {
+            "buffer has"
+        } else {
+            "buffers have"
+        };
+        self.message(&format!(
+            "{} {} unsaved changes, :save or :q again to quit",
+            modified, buffers
+        ));
+        false
+    }
+
+    /// Record keystrokes into `register` until recording is stopped
+    pub fn start_recording(&mut self, register: char) {
+        self.status_bar_dirty = true;
+        self.macros.start(register);
+        self.message(&format!("recording @{}, q to stop", register));
+    }
+
+    /// Stop recording keystrokes, keeping what was recorded
+    pub fn stop_recording(&mut self) {
+        self.status_bar_dirty = true;
+        if let Some(register) = self.macros.stop() {
+            let events = self.macros.get(register).unwrap_or_default();
+            let text = format!("recorded @{}: {}", register, macros::notation(events));
+            self.message(&text);
+        }
+    }
+
+    /// Set the macro in `register` to `events`, or show the macro when not given any
+    pub fn define_macro(&mut self, register: char, events: Option<Vec<Event>>) {
+        match events {
+            Some(events) => self.macros.set(register, events),
+            None => {
+                let text = match self.macros.get(register) {
+                    Some(events) => format!("@{}: {}", register, macros::notation(events)),
+                    None => format!("register {} is empty", register),
+                };
+                self.message(&text);
+            }
+        }
+    }
+
+    /// Highlight `range` and jump to its start
+    fn select_structure(&mut self, range: Range<usize>) {
+        let start = range.start.min(self.data().len().saturating_sub(1));
+        self.buffers[self.current_buffer].highlight = Some(range);
+        self.mark_all_dirty();
+        self.goto(start);
+    }
+
+    /// Stop highlighting the selected structure
+    pub fn clear_highlight(&mut self) {
+        self.buffers[self.current_buffer].highlight = None;
+        self.mark_all_dirty();
+    }
+
+    /// Bookmark `length` bytes from `start` as `label`, or the selected structure or the top left
+    /// byte when no range is given
+    ///
+    /// Bookmarks are written to the sidecar beside the file straight away.
+    pub fn add_bookmark(&mut self, range: Option<(usize, usize)>, label: String) {
+        let (offset, length) = match range {
+            Some((start, length)) => match self.range(start, length.max(1)) {
+                Some(_) => (start, length),
+                None => return,
+            },
+            None => match self.buffer().highlight.clone() {
+                Some(range) => (range.start, range.len()),
+                None => (
+                    top_left_byte_index(self.offset, self.scroll_y, self.bytes_per_row),
+                    0,
+                ),
+            },
+        };
+        let message = format!("bookmarked {} at {:#x}", label, offset);
+        let buffer = &mut self.buffers[self.current_buffer];
+        let added = buffer.add_bookmark(Bookmark {
+            offset,
+            length,
+            label,
+        });
+        self.mark_all_dirty();
+        match added {
+            Ok(()) => self.message(&message),
+            Err(error) => self.bookmark_error(error),
+        }
+    }
+
+    /// Remove the bookmarks of the current buffer labelled `label`
+    pub fn remove_bookmark(&mut self, label: &str) {
+        let removed = self.buffers[self.current_buffer].remove_bookmarks(label);
+        self.mark_all_dirty();
+        match removed {
+            Ok(0) => self.error(&format!("no bookmark labelled {}", label)),
+            Ok(1) => self.message(&format!("removed {}", label)),
+            Ok(count) => self.message(&format!("removed {} bookmarks labelled {}", count, label)),
+            Err(error) => self.bookmark_error(error),
+        }
+    }
+
+    fn bookmark_error(&mut self, error: std::io::Error) {
+        let sidecar = bookmark::sidecar(self.buffer().path());
+        self.error(&format!("{}: {}", sidecar, error));
+    }
+
+    /// List the bookmarks of the current buffer, selecting one jumps to it
+    pub fn show_bookmarks(&mut self) {
+        let bookmarks = &self.buffer().bookmarks;
+        if bookmarks.is_empty() {
+            self.reset_prompt();
+            self.message("no bookmarks, add one with :bookmark LABEL");
+            return;
+        }
+
+        let items = bookmarks
+            .iter()
+            .map(|bookmark| {
+                let length = match bookmark.length {
+                    0 => String::new(),
+                    length => format!("{:#x}", length),
+                };
+                format!("{:#010x} {:>8} {}", bookmark.offset, length, bookmark.label)
+            })
+            .collect();
+        let title = format!("Bookmarks ({})", bookmarks.len());
+        let overlay = Overlay::new(OverlayKind::Bookmarks, title, items).filterable();
+        self.show_overlay(overlay);
+    }
+
+    /// Write the bookmarks of the current buffer to `path` as JSON
+    pub fn export_bookmarks(&mut self, path: &str) {
+        let buffer = self.buffer();
+        let json = bookmark::to_json(buffer.path(), &buffer.bookmarks);
+        let count = buffer.bookmarks.len();
+        match fs::write(path, json) {
+            Ok(()) => self.message(&format!("wrote {} bookmarks to {}", count, path)),
+            Err(error) => self.error(&format!("{}: {}", path, error)),
+        }
+    }
+
+    /// Display `overlay` over the data frame, keyboard input is directed to it until closed
+    pub fn show_overlay(&mut self, overlay: Overlay) {
+        self.mark_all_dirty();
+        self.state = State::Overlay;
+        self.overlay = Some(overlay);
+    }
+
+    /// Close the overlay, returning focus to the main window
+    pub fn close_overlay(&mut self) -> Option<Overlay> {
+        self.mark_all_dirty();
+        self.state = State::Wait;
+        self.overlay.take()
+    }
+
+    /// Move the overlay selection up one item
+    pub fn overlay_up(&mut self) {
+        if let Some(overlay) = self.overlay.as_mut() {
+            self.data_frame_dirty = true;
+            overlay.up();
+        }
+    }
+
+    /// Move the overlay selection down one item
+    pub fn overlay_down(&mut self) {
+        if let Some(overlay) = self.overlay.as_mut() {
+            self.data_frame_dirty = true;
+            overlay.down();
+        }
+    }
+
+    /// Whether the open overlay takes typed text as a filter
+    pub fn overlay_filterable(&self) -> bool {
+        self.overlay
+            .as_ref()
+            .is_some_and(|overlay| overlay.filterable)
+    }
+
+    /// Add `c` to the filter of the overlay
+    pub fn push_overlay_filter(&mut self, c: char) {
+        if let Some(overlay) = self.overlay.as_mut() {
+            self.data_frame_dirty = true;
+            overlay.push_filter(c);
+        }
+    }
+
+    /// Remove the last character from the filter of the overlay
+    pub fn pop_overlay_filter(&mut self) {
+        if let Some(overlay) = self.overlay.as_mut() {
+            self.data_frame_dirty = true;
+            overlay.pop_filter();
+        }
+    }
+
+    /// Close the overlay and act on its selected item
+    pub fn select_overlay_item(&mut self) {
+        if let Some(overlay) = self.close_overlay() {
+            let value = match overlay.selected_value() {
+                Some(value) => value,
+                None => return,
+            };
+            match overlay.kind {
+                OverlayKind::Bookmarks => {
+                    if let Some(bookmark) = self.buffer().bookmarks.get(value).cloned() {
+                        match bookmark.length {
+                            0 => self.goto_offset(bookmark.offset),
+                            _ => self.select_structure(bookmark.range()),
+                        }
+                    }
+                }
+                OverlayKind::Buffers => self.switch_buffer(value),
+                OverlayKind::Strings => self.goto(value),
+                OverlayKind::Structures => {
+                    if let Some(range) = overlay.selected_range() {
+                        self.select_structure(range);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Display a message in the prompt bar until the next keypress
+    pub fn message(&mut self, text: &str) {
+        self.prompt_bar_dirty = true;
+        self.message = Some(text.to_owned());
+    }
+
+    /// Take the message waiting to be displayed, so it's shown somewhere else instead
+    pub fn take_message(&mut self) -> Option<String> {
+        self.message.take()
+    }
+
+    /// Display a message saying a command failed
+    pub fn error(&mut self, text: &str) {
+        self.message(text);
+        self.error = Some(text.to_owned());
+    }
+
+    /// Take the last failure reported since this was last called
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    /// Treat the output as something other than a terminal, for running scripts
+    pub fn headless(mut self) -> Self {
+        self.headless = true;
+        self
+    }
+
+    /// Save the top left byte index under `name` in the current buffer
+    pub fn set_mark(&mut self, name: char) {
+        let index = top_left_byte_index(self.offset, self.scroll_y, self.bytes_per_row);
+        self.buffers[self.current_buffer].marks.insert(name, index);
+        self.message(&format!("mark '{}' set at {:#x}", name, index));
+    }
+
+    /// Jump to a position previously saved with `set_mark`
+    pub fn jump_to_mark(&mut self, name: char) {
+        match self.buffers[self.current_buffer].marks.get(&name) {
+            Some(&index) => self.goto(index),
+            None => self.error(&format!("mark '{}' not set", name)),
+        }
+    }
+
+    /// Scroll so the row containing the byte at `index` is at the top of the data frame
+    ///
+    /// If the byte lies before the current offset, the offset is reduced to the smallest value
+    /// keeping rows aligned as they were, or 0 if that isn't possible.
+    pub fn goto(&mut self, index: usize) {
+        self.data_frame_dirty = true;
+        self.status_bar_dirty = true;
+
+        if index < self.offset {
+            let phase = self.offset % self.bytes_per_row;
+            self.offset = if phase <= index { phase } else { 0 };
         }
+        self.scroll_y = (index - self.offset) / self.bytes_per_row;
     }
 
     fn status_bar_height() -> u16 {
@@ -86,18 +1124,41 @@ impl<'a, T: Write> Screen<'a, T> {
         1
     }
 
+    /// Height of the data display in the focused window
     pub fn data_frame_height(&self) -> u16 {
-        self.frame.height - Self::status_bar_height() - Self::prompt_height()
+        self.focused_content_area().height
     }
 
+    /// Width of the data display in the focused window
     pub fn data_frame_width(&self) -> u16 {
-        self.frame.width
+        self.focused_content_area().width
+    }
+
+    /// Number of rows of data visible at once in the focused window
+    ///
+    /// When wrapping, each row can take up several lines of the data frame.
+    pub fn visible_rows(&self) -> usize {
+        use crate::byte_display::lines_per_row;
+
+        let height = self.data_frame_height() as usize;
+        if self.wrap {
+            let lines = lines_per_row(self.bytes_per_row, self.data_frame_width(), &self.format);
+            cmp::max(1, height / lines)
+        } else {
+            height
+        }
+    }
+
+    /// Data displayed in the focused window, starting at the offset
+    fn data_from_offset(&self) -> &[u8] {
+        let data = self.data();
+        &data[cmp::min(self.offset, data.len())..]
     }
 
     pub fn status_bar_position(&self) -> Point {
         Point {
             x: 1,
-            y: self.frame.height - Self::prompt_height(),
+            y: self.frame.height.saturating_sub(Self::prompt_height()),
         }
     }
 
@@ -124,7 +1185,7 @@ impl<'a, T: Write> Screen<'a, T> {
     ///
     /// Offset is clamped, preventing attempts to index past the end of the data.
     pub fn right(&mut self) {
-        if self.offset <= self.data.len() {
+        if self.offset <= self.data().len() {
             self.data_frame_dirty = true;
             self.status_bar_dirty = true;
 
@@ -132,84 +1193,110 @@ impl<'a, T: Write> Screen<'a, T> {
         }
     }
 
-    /// Scroll left one column
-    pub fn scroll_left(&mut self) {
+    /// Scroll left `count` groups
+    pub fn scroll_left(&mut self, count: usize) {
         if self.scroll_x > 0 {
             self.data_frame_dirty = true;
             self.status_bar_dirty = true;
 
-            self.scroll_x -= 1;
+            let group_size = self.format.group_size;
+            let groups = (self.scroll_x - 1) / group_size;
+            self.scroll_x = groups.saturating_sub(count.saturating_sub(1)) * group_size;
         }
     }
 
-    /// Scroll right one column
-    pub fn scroll_right(&mut self) {
-        if self.scroll_x < max_scroll_x(self.bytes_per_row, self.data_frame_width() as usize) {
+    /// Scroll right `count` groups
+    ///
+    /// Scrolling stops at the first group boundary reaching the last column.
+    pub fn scroll_right(&mut self, count: usize) {
+        let width = self.data_frame_width() as usize;
+        let max = max_scroll_x(self.bytes_per_row, width, &self.format);
+        if self.scroll_x < max {
             self.data_frame_dirty = true;
             self.status_bar_dirty = true;
-            self.scroll_x += 1;
+            let group_size = self.format.group_size;
+            let scroll_x = self.scroll_x / group_size * group_size;
+            self.scroll_x = cmp::min(
+                scroll_x.saturating_add(count.saturating_mul(group_size)),
+                max.div_ceil(group_size) * group_size,
+            );
         }
     }
 
-    /// Scroll down one line
+    /// Scroll down `count` lines
     ///
     /// Scrolling is clamped and will not move further than half a screen past the last line.
-    pub fn down(&mut self) {
+    pub fn down(&mut self, count: usize) {
         self.data_frame_dirty = true;
         self.status_bar_dirty = true;
 
-        let data = &self.data[self.offset..self.data.len()];
+        let data = self.data_from_offset();
 
-        if self.scroll_y
-            < max_scroll_y(self.data_frame_height() as usize, &data, self.bytes_per_row)
-        {
-            self.scroll_y += 1;
+        let max = max_scroll_y(self.visible_rows(), data, self.bytes_per_row);
+        if self.scroll_y < max {
+            self.scroll_y = cmp::min(self.scroll_y.saturating_add(count), max);
         }
     }
 
-    /// Scroll down one line
+    /// Scroll up `count` lines
     ///
     /// Scrolling is clamped and will not move past the first line.
-    pub fn up(&mut self) {
+    pub fn up(&mut self, count: usize) {
         self.data_frame_dirty = true;
         self.status_bar_dirty = true;
 
-        if self.scroll_y > 0 {
-            self.scroll_y -= 1;
-        }
+        self.scroll_y = self.scroll_y.saturating_sub(count);
     }
 
-    /// Scroll one page down
+    /// Scroll `count` pages down
     ///
     /// Will scroll down half a frame below the last line.
-    pub fn page_down(&mut self) {
+    pub fn page_down(&mut self, count: usize) {
         self.data_frame_dirty = true;
         self.status_bar_dirty = true;
 
-        let len = self.data.len();
-        let data = &self.data[self.offset..len];
+        let data = self.data_from_offset();
 
-        if (self.scroll_y + self.data_frame_height() as usize)
-            < max_scroll_y(self.data_frame_height() as usize, &data, self.bytes_per_row)
-        {
-            self.scroll_y += self.data_frame_height() as usize;
+        let distance = self.visible_rows().saturating_mul(count);
+        let max = max_scroll_y(self.visible_rows(), data, self.bytes_per_row);
+        if self.scroll_y.saturating_add(distance) < max {
+            self.scroll_y += distance;
         } else {
-            self.scroll_y =
-                max_scroll_y(self.data_frame_height() as usize, &data, self.bytes_per_row);
+            self.scroll_y = max;
         }
     }
 
-    /// Scroll one page up
+    /// Scroll `count` pages up
     ///
     /// Will not scroll up past the first line.
-    pub fn page_up(&mut self) {
+    pub fn page_up(&mut self, count: usize) {
         self.data_frame_dirty = true;
         self.status_bar_dirty = true;
 
-        if self.data_frame_height() as usize > self.scroll_y {
-            self.scroll_y = 0;
-        } else {
-            self.scroll_y -= self.data_frame_height() as usize;
+        let distance = self.visible_rows().saturating_mul(count);
+        self.scroll_y = self.scroll_y.saturating_sub(distance);
+    }
+
+    /// Digits typed so far as a count for the next key
+    pub fn count(&self) -> Option<usize> {
+        self.count
+    }
+
+    /// Add `digit` to the end of the count being typed
+    pub fn push_count(&mut self, digit: usize) {
+        self.status_bar_dirty = true;
+        let count = self.count.unwrap_or(0);
+        self.count = Some(count.saturating_mul(10).saturating_add(digit));
+    }
+
+    /// Count typed before the key being handled, 1 if there wasn't one, clearing it
+    pub fn take_count(&mut self) -> usize {
+        match self.count.take() {
+            Some(count) => {
+                self.status_bar_dirty = true;
+                count
+            }
+            None => 1,
         }
     }
 
@@ -226,10 +1313,9 @@ impl<'a, T: Write> Screen<'a, T> {
         self.data_frame_dirty = true;
         self.status_bar_dirty = true;
 
-        let len = self.data.len();
-        let data = &self.data[self.offset..len];
+        let data = self.data_from_offset();
 
-        self.scroll_y = max_scroll_y(self.data_frame_height() as usize, &data, self.bytes_per_row);
+        self.scroll_y = max_scroll_y(self.visible_rows(), data, self.bytes_per_row);
     }
 
     /// Shift focus to the prompt bar
@@ -262,14 +1348,14 @@ impl<'a, T: Write> Screen<'a, T> {
     /// Set number of bytes to display per row
     ///
     /// When setting the width, the byte in the top left corner of the screen will stay in place,
-    /// offsets and scroll_y can change to accommodate this constraint.
+    /// offsets and scroll_y can change to accommodate this constraint. A width of 0 is treated as 1.
     pub fn set_width(&mut self, width: usize) {
         self.data_frame_dirty = true;
         self.prompt_bar_dirty = true;
         self.status_bar_dirty = true;
         self.state = State::Wait;
 
-        self.bytes_per_row = width;
+        self.bytes_per_row = cmp::max(width, 1);
 
         let anchor = top_left_byte_index(self.offset, self.scroll_y, self.bytes_per_row);
 
@@ -324,22 +1410,59 @@ impl<'a, T: Write> Screen<'a, T> {
         self.data_frame_dirty = false;
         self.prompt_bar_dirty = false;
         self.status_bar_dirty = false;
+        self.clear_before_render = false;
         self.switch_focus_to_prompt = false;
     }
 
+    /// Adapt to a new terminal size
+    ///
+    /// Scroll positions of every window are clamped to what can be reached in its new area and
+    /// the whole terminal is redrawn on the next render.
+    pub fn resize(&mut self, frame: Frame) {
+        self.frame = frame;
+        self.mark_all_dirty();
+        self.clear_before_render = true;
+        if self.state == State::Prompt {
+            self.switch_focus_to_prompt = true;
+        }
+
+        self.windows[self.focused_window] = Window {
+            buffer: self.current_buffer,
+            view: self.view(),
+        };
+        for (index, area) in self.window_areas() {
+            let content = self.content_area(area);
+            let window = &mut self.windows[index];
+            clamp_view(
+                &mut window.view,
+                content.dimensions(),
+                &self.buffers[window.buffer].data,
+            );
+        }
+        self.load_window(self.focused_window);
+    }
+
     /// Render the current state of Screen
-    pub fn render(&mut self, ctx: &Context) -> Result<(), Box<dyn StdError>> {
-        use crate::byte_display;
+    pub fn render(&mut self) -> Result<(), Box<dyn StdError>> {
+        use crate::overlay::{self, fit};
         use crate::status_bar;
 
+        if self.clear_before_render {
+            write!(self.out, "{}", termion::clear::All)?;
+        }
+
         if self.data_frame_dirty {
-            let len = self.data.len();
-            let data = &self.data[self.offset..len];
-            byte_display::render(self, data)
+            self.render_windows();
+
+            if let Some(overlay) = self.overlay.as_mut() {
+                let (position, dimensions) = overlay_area(&self.frame);
+                overlay::render(&mut self.out, overlay, position, dimensions);
+            }
         }
 
         if self.status_bar_dirty {
-            status_bar::render(self, ctx.file_path);
+            let path = self.buffer().path().to_owned();
+            status_bar::render(self, &path);
         }
 
         if self.switch_focus_to_prompt {
@@ -352,12 +1475,17 @@ impl<'a, T: Write> Screen<'a, T> {
             )?;
         } else if self.prompt_bar_dirty {
             match self.state {
-                State::Wait => {
+                State::Wait | State::Overlay => {
                     write!(
                         self.out,
-                        "{}{}{}",
+                        "{}{}{}{}",
                         termion::cursor::Goto(1, self.frame.height),
                         termion::clear::CurrentLine,
+                        fit(
+                            &self.message.take().unwrap_or_default(),
+                            self.frame.width as usize
+                        )
+                        .trim_end(),
                         termion::cursor::Hide
                     )?;
                 }
@@ -379,17 +1507,231 @@ impl<'a, T: Write> Screen<'a, T> {
         Ok(())
     }
 
+    fn render_windows(&mut self) {
+        use crate::byte_display;
+        use crate::overlay::fit;
+        use termion::{color, cursor, style};
+
+        let split = self.windows.len() > 1;
+        let palette = if self.colours {
+            Some(self.palette)
+        } else {
+            None
+        };
+
+        for (index, area) in self.window_areas() {
+            let (buffer, view) = if index == self.focused_window {
+                (self.current_buffer, self.view())
+            } else {
+                let window = &self.windows[index];
+                (window.buffer, window.view.clone())
+            };
+            let content = self.content_area(area);
+            let bookmarks = self.buffers[buffer]
+                .bookmarks
+                .iter()
+                .zip(BOOKMARK_COLOURS.iter().cycle())
+                .map(|(bookmark, &colour)| Highlight {
+                    range: bookmark.range(),
+                    colour,
+                });
+            let fields = self.buffers[buffer]
+                .template
+                .iter()
+                .flat_map(|applied| applied.fields.iter())
+                .filter_map(|field| {
+                    field.colour.map(|colour| Highlight {
+                        range: field.range.clone(),
+                        colour,
+                    })
+                });
+            let edits = self.buffers[buffer].edits.iter().map(|edit| Highlight {
+                range: edit.range.clone(),
+                colour: EDIT_HIGHLIGHT,
+            });
+            let preview = self.buffers[buffer].preview.iter().map(|edit| Highlight {
+                range: edit.range.clone(),
+                colour: PREVIEW_HIGHLIGHT,
+            });
+            // the selected structure is drawn over template fields and edits
+            let structure = self.buffers[buffer]
+                .highlight
+                .iter()
+                .map(|range| Highlight {
+                    range: range.clone(),
+                    colour: STRUCTURE_HIGHLIGHT,
+                });
+            // bookmarks are a band beneath everything else
+            let highlights: Vec<Highlight> = bookmarks
+                .chain(fields)
+                .chain(edits)
+                .chain(preview)
+                .chain(structure)
+                .collect();
+            let data = &self.buffers[buffer].data;
+            let data = &data[view.offset.min(data.len())..];
+
+            if split && area.height > 0 {
+                let title = format!(" {}", self.buffers[buffer].path());
+                if index == self.focused_window {
+                    write!(
+                        self.out,
+                        "{}{}{}{}{}{}",
+                        cursor::Goto(area.x, area.y),
+                        color::Bg(color::White),
+                        color::Fg(color::Black),
+                        fit(&title, area.width as usize),
+                        color::Bg(color::Reset),
+                        color::Fg(color::Reset),
+                    )
+                    .unwrap();
+                } else {
+                    write!(
+                        self.out,
+                        "{}{}{}{}",
+                        cursor::Goto(area.x, area.y),
+                        style::Underline,
+                        fit(&title, area.width as usize),
+                        style::Reset,
+                    )
+                    .unwrap();
+                }
+            }
+
+            byte_display::render(
+                &mut self.out,
+                &view,
+                content.position(),
+                content.dimensions(),
+                data,
+                palette,
+                &highlights,
+            );
+        }
+
+        for (x, y, height) in self.layout.separators(self.data_area()) {
+            for row in y..y + height {
+                write!(self.out, "{}│", cursor::Goto(x, row)).unwrap();
+            }
+        }
+
+        if self.panel_shown() {
+            let top = top_left_byte_index(self.offset, self.scroll_y, self.bytes_per_row);
+            let area = self.panel_area();
+            if let Some(applied) = &self.buffers[self.current_buffer].template {
+                panel::render(&mut self.out, applied, area, top);
+            }
+        }
+
+        if let (true, Some(measure)) = (self.minimap_shown(), self.minimap) {
+            let top = top_left_byte_index(self.offset, self.scroll_y, self.bytes_per_row);
+            let viewport = top..top + self.visible_rows() * self.bytes_per_row;
+            let area = self.minimap_area();
+            let buffer = &self.buffers[self.current_buffer];
+            minimap::render(
+                &mut self.out,
+                &buffer.summary,
+                buffer.data.len(),
+                measure,
+                area,
+                viewport,
+                palette,
+            );
+        }
+    }
+
     /// Toggle between text display modes
     ///
     /// Either print ASCII printable range characters or display all bytes as hex values.
     pub fn toggle_text_display_mode(&mut self) {
-        match self.text_display_mode {
-            Mode::Ascii => self.text_display_mode = Mode::Hex,
-            Mode::Hex => self.text_display_mode = Mode::Ascii,
+        match self.format.mode {
+            Mode::Ascii => self.format.mode = Mode::Hex,
+            _ => self.format.mode = Mode::Ascii,
         }
         self.data_frame_dirty = true;
     }
 
+    /// Display each byte as a cell of `mode`
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.data_frame_dirty = true;
+        self.prompt_bar_dirty = true;
+        self.status_bar_dirty = true;
+        self.state = State::Wait;
+
+        self.format.mode = mode;
+    }
+
+    /// Decode the character column using `encoding`
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.data_frame_dirty = true;
+        self.prompt_bar_dirty = true;
+        self.status_bar_dirty = true;
+        self.state = State::Wait;
+
+        self.format.encoding = encoding;
+    }
+
+    /// Set the number of bytes packed together between spaces, 0 is treated as 1
+    ///
+    /// Horizontal scrolling is moved back to the start of the group it's in.
+    pub fn set_group_size(&mut self, size: usize) {
+        self.data_frame_dirty = true;
+        self.prompt_bar_dirty = true;
+        self.status_bar_dirty = true;
+        self.state = State::Wait;
+
+        self.format.group_size = cmp::max(size, 1);
+        self.scroll_x -= self.scroll_x % self.format.group_size;
+    }
+
+    /// Display groups as little-endian words with their bytes reversed, or in file order
+    pub fn set_little_endian(&mut self, little_endian: bool) {
+        self.data_frame_dirty = true;
+        self.prompt_bar_dirty = true;
+        self.status_bar_dirty = true;
+        self.state = State::Wait;
+
+        self.format.little_endian = little_endian;
+    }
+
+    /// Toggle between scrolling horizontally and wrapping rows wider than the window
+    pub fn toggle_wrap(&mut self) {
+        self.data_frame_dirty = true;
+        self.prompt_bar_dirty = true;
+        self.status_bar_dirty = true;
+        self.state = State::Wait;
+
+        self.wrap = !self.wrap;
+        self.scroll_x = 0;
+    }
+
+    /// Toggle showing stand-in glyphs for bytes without a character in ASCII mode
+    pub fn toggle_pictures(&mut self) {
+        self.data_frame_dirty = true;
+        self.prompt_bar_dirty = true;
+        self.state = State::Wait;
+
+        self.format.pictures = !self.format.pictures;
+    }
+
+    /// Toggle colouring bytes by class
+    pub fn toggle_colours(&mut self) {
+        self.data_frame_dirty = true;
+        self.prompt_bar_dirty = true;
+        self.state = State::Wait;
+
+        self.colours = !self.colours;
+    }
+
+    /// Set the colour of a byte class, `None` uses the terminal's default colour
+    pub fn set_colour(&mut self, class: ByteClass, colour: Option<u8>) {
+        self.data_frame_dirty = true;
+        self.prompt_bar_dirty = true;
+        self.state = State::Wait;
+
+        self.palette.set(class, colour);
+    }
+
     /// Clear the screen and reset the cursor position
     ///
     /// This prepares the terminal for being reverted into cooked mode and should be the last
@@ -401,8 +1743,47 @@ impl<'a, T: Write> Screen<'a, T> {
     }
 }
 
+/// Clamp scroll positions of `view` to the furthest reachable in an area of `dimensions`
+fn clamp_view(view: &mut View, dimensions: Dimension, data: &[u8]) {
+    use crate::byte_display::lines_per_row;
+
+    let data = &data[view.offset.min(data.len())..];
+    view.scroll_x = view.scroll_x.min(max_scroll_x(
+        view.bytes_per_row,
+        dimensions.width as usize,
+        &view.format,
+    ));
+    // when wrapping each row takes several lines, as in `Screen::visible_rows`
+    let rows = if view.wrap {
+        let lines = lines_per_row(view.bytes_per_row, dimensions.width, &view.format);
+        cmp::max(1, dimensions.height as usize / lines)
+    } else {
+        dimensions.height as usize
+    };
+    view.scroll_y = view
+        .scroll_y
+        .min(max_scroll_y(rows, data, view.bytes_per_row));
+}
+
+/// Area of the data frame covered by an overlay, leaving a margin on each side when there's room
+fn overlay_area(frame: &Frame) -> (Point, Dimension) {
+    let height = frame.height.saturating_sub(2);
+    let margin_x = if frame.width > 20 { 2 } else { 0 };
+    let margin_y = if height > 10 { 1 } else { 0 };
+    (
+        Point {
+            x: 1 + margin_x,
+            y: 1 + margin_y,
+        },
+        Dimension {
+            width: frame.width - 2 * margin_x,
+            height: height - 2 * margin_y,
+        },
+    )
+}
+
 fn max_scroll_y(height: usize, data: &[u8], width: usize) -> usize {
-    let lines = data.len() / width as usize;
+    let lines = data.len() / width;
     if lines > height {
         lines - height / 2
     } else {
@@ -410,8 +1791,8 @@ fn max_scroll_y(height: usize, data: &[u8], width: usize) -> usize {
     }
 }
 
-fn max_scroll_x(bytes_per_row: usize, screen_width: usize) -> usize {
-    let bytes_on_screen = (screen_width + 1) / 3;
+fn max_scroll_x(bytes_per_row: usize, screen_width: usize, format: &Format) -> usize {
+    let bytes_on_screen = format.bytes_in(screen_width);
 
     if bytes_per_row < bytes_on_screen / 2 {
         bytes_on_screen / 2
@@ -446,6 +1827,672 @@ fn offset_for_anchor(anchor: usize, offset: usize, bytes_per_row: usize) -> usiz
 mod tests {
     use super::*;
 
+    fn screen(len: usize) -> Screen<Vec<u8>> {
+        let buffer = Buffer::new("a".to_owned(), vec![0; len]);
+        Screen::new(
+            buffer,
+            Frame {
+                width: 80,
+                height: 24,
+            },
+            Vec::new(),
+        )
+    }
+
+    mod buffers {
+        use super::*;
+
+        #[test]
+        fn switching_buffers_restores_their_view() {
+            let mut screen = screen(4096);
+            screen.set_width(16);
+            screen.down(1);
+
+            screen.open_buffer(Buffer::new("b".to_owned(), vec![1; 10]));
+            assert_eq!(screen.buffer().path(), "b");
+            assert_eq!(screen.view(), View::default());
+
+            screen.previous_buffer();
+            assert_eq!(screen.buffer().path(), "a");
+            assert_eq!(screen.bytes_per_row, 16);
+            assert_eq!(screen.scroll_y, 1);
+
+            screen.next_buffer();
+            screen.next_buffer();
+            assert_eq!(screen.buffer().path(), "a");
+        }
+
+        #[test]
+        fn selecting_from_the_buffer_list_switches_buffer() {
+            let mut screen = screen(10);
+            screen.open_buffer(Buffer::new("b".to_owned(), vec![]));
+            screen.show_buffer_list();
+            assert_eq!(screen.state, State::Overlay);
+
+            screen.overlay_up();
+            screen.select_overlay_item();
+            assert_eq!(screen.state, State::Wait);
+            assert_eq!(screen.buffer().path(), "a");
+            screen.render().unwrap();
+        }
+
+        #[test]
+        fn marks_are_kept_per_buffer() {
+            let mut screen = screen(4096);
+            screen.goto(320);
+            screen.set_mark('a');
+            screen.start();
+
+            screen.open_buffer(Buffer::new("b".to_owned(), vec![0; 4096]));
+            screen.jump_to_mark('a');
+            assert_eq!(screen.scroll_y, 0);
+
+            screen.previous_buffer();
+            screen.jump_to_mark('a');
+            assert_eq!(screen.scroll_y, 10);
+        }
+    }
+
+    mod windows {
+        use super::*;
+
+        #[test]
+        fn split_windows_share_the_data_frame() {
+            let mut screen = screen(4096);
+            assert_eq!(screen.data_frame_height(), 22);
+
+            screen.split(Direction::Horizontal, None);
+            assert_eq!(screen.window_count(), 2);
+            // half of 22 rows, less a header row
+            assert_eq!(screen.data_frame_height(), 10);
+
+            screen.split(Direction::Vertical, None);
+            assert_eq!(screen.data_frame_width(), 39);
+            screen.render().unwrap();
+        }
+
+        #[test]
+        fn each_window_keeps_its_own_view() {
+            let mut screen = screen(4096);
+            screen.split(Direction::Vertical, None);
+            screen.down(1);
+            screen.down(1);
+
+            screen.next_window();
+            assert_eq!(screen.scroll_y, 0);
+            screen.set_width(8);
+
+            screen.previous_window();
+            assert_eq!((screen.scroll_y, screen.bytes_per_row), (2, 32));
+        }
+
+        #[test]
+        fn windows_can_show_different_buffers() {
+            let mut screen = screen(4096);
+            screen.split(
+                Direction::Horizontal,
+                Some(Buffer::new("b".to_owned(), vec![1; 10])),
+            );
+            assert_eq!(screen.buffer().path(), "b");
+
+            screen.next_window();
+            assert_eq!(screen.buffer().path(), "a");
+            screen.render().unwrap();
+        }
+
+        #[test]
+        fn closing_a_window_focuses_a_neighbour() {
+            let mut screen = screen(4096);
+            screen.close_window();
+            assert_eq!(screen.window_count(), 1);
+
+            screen.split(Direction::Horizontal, None);
+            screen.close_window();
+            assert_eq!(screen.window_count(), 1);
+            assert_eq!(screen.data_frame_height(), 22);
+        }
+
+        #[test]
+        fn resizing_changes_the_focused_window_size() {
+            let mut screen = screen(4096);
+            screen.split(Direction::Horizontal, None);
+            screen.resize_window(Direction::Horizontal, 6);
+            assert_eq!(screen.data_frame_height(), 5);
+
+            // sizes too big for the terminal take as much room as there is
+            screen.resize_window(Direction::Horizontal, 0x1_0003);
+            let height = screen.data_frame_height();
+            screen.resize_window(Direction::Horizontal, 100);
+            assert_eq!(screen.data_frame_height(), height);
+            assert!(height > 5);
+        }
+    }
+
+    mod resize {
+        use super::*;
+
+        #[test]
+        fn it_clamps_scroll_positions_to_the_new_frame() {
+            let mut screen = screen(4096);
+            screen.end();
+            screen.set_scroll_x(50);
+            // 128 rows of 32 bytes, with a data frame of 22 rows
+            assert_eq!(screen.scroll_y, 128 - 11);
+
+            screen.resize(Frame {
+                width: 200,
+                height: 102,
+            });
+            assert_eq!(screen.scroll_y, 128 - 50);
+            // 66 bytes fit on a line, scrolling is allowed up to half a line
+            assert_eq!(screen.scroll_x, 33);
+        }
+
+        #[test]
+        fn it_redraws_the_whole_frame_at_the_new_size() {
+            let mut screen = screen(4096);
+            screen.colours = false;
+            screen.render().unwrap();
+            screen.out.clear();
+
+            screen.resize(Frame {
+                width: 20,
+                height: 6,
+            });
+            screen.render().unwrap();
+            let output = String::from_utf8(screen.out.clone()).unwrap();
+
+            assert!(output.starts_with(&termion::clear::All.to_string()));
+            // 4 data rows of 6 bytes each, and the status bar
+            assert_eq!(output.matches("00 00 00 00 00 00 ").count(), 4);
+            assert!(output.contains(&termion::cursor::Goto(1, 5).to_string()));
+            assert!(!output.contains(&termion::cursor::Goto(1, 7).to_string()));
+        }
+
+        #[test]
+        fn it_clamps_wrapped_rows() {
+            let mut screen = screen(4096);
+            screen.set_width(64);
+            screen.toggle_wrap();
+            screen.end();
+
+            screen.resize(Frame {
+                width: 80,
+                height: 60,
+            });
+            assert_eq!(screen.visible_rows(), 19);
+            assert_eq!(screen.scroll_y, 55);
+        }
+
+        #[test]
+        fn it_clamps_every_window() {
+            let mut screen = screen(4096);
+            screen.split(Direction::Horizontal, None);
+            screen.end();
+            screen.next_window();
+            screen.end();
+
+            screen.resize(Frame {
+                width: 80,
+                height: 60,
+            });
+            assert_eq!(screen.scroll_y, 128 - 14);
+            screen.next_window();
+            assert_eq!(screen.scroll_y, 128 - 14);
+        }
+    }
+
+    mod tiny_frames {
+        use super::*;
+
+        fn exercise(screen: &mut Screen<Vec<u8>>) {
+            screen.render().unwrap();
+            screen.end();
+            screen.page_down(1);
+            screen.scroll_right(1);
+            screen.render().unwrap();
+            screen.page_up(1);
+            screen.down(1);
+            screen.render().unwrap();
+        }
+
+        #[test]
+        fn rendering_never_panics_down_to_one_by_three() {
+            for &wrap in &[false, true] {
+                for width in 1..40 {
+                    for height in 3..8 {
+                        let mut screen = screen(1000);
+                        screen.resize(Frame { width, height });
+                        if wrap {
+                            screen.toggle_wrap();
+                        }
+                        exercise(&mut screen);
+
+                        screen.split(Direction::Vertical, None);
+                        screen.split(Direction::Horizontal, None);
+                        exercise(&mut screen);
+
+                        screen.toggle_minimap();
+                        while screen.summarise() {}
+                        exercise(&mut screen);
+
+                        screen.show_buffer_list();
+                        screen.message("a message wider than the frame");
+                        screen.render().unwrap();
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn wrapping_pages_by_whole_rows() {
+            let mut screen = screen(4096);
+            screen.toggle_wrap();
+            // 26 bytes fit beside the gutter, so each row of 32 takes 2 lines of 22
+            assert_eq!(screen.visible_rows(), 11);
+            screen.page_down(1);
+            assert_eq!(screen.scroll_y, 11);
+        }
+
+        #[test]
+        fn zero_width_is_treated_as_one() {
+            let mut screen = screen(10);
+            screen.set_width(0);
+            assert_eq!(screen.bytes_per_row, 1);
+            exercise(&mut screen);
+        }
+    }
+
+    mod strings {
+        use super::*;
+
+        #[test]
+        fn selecting_a_string_jumps_to_it() {
+            let mut data = vec![0; 4096];
+            data[1000..1005].copy_from_slice(b"hello");
+            data[2000..2005].copy_from_slice(b"world");
+            let mut screen = Screen::new(
+                Buffer::new("a".to_owned(), data),
+                Frame {
+                    width: 80,
+                    height: 24,
+                },
+                Vec::new(),
+            );
+
+            screen.show_strings(4, None);
+            assert_eq!(screen.state, State::Overlay);
+            assert!(screen.overlay_filterable());
+            screen.push_overlay_filter('w');
+            screen.select_overlay_item();
+
+            assert_eq!(screen.state, State::Wait);
+            assert_eq!(screen.scroll_y, 2000 / 32);
+        }
+
+        #[test]
+        fn nothing_is_shown_without_strings() {
+            let mut screen = screen(4096);
+            screen.show_strings(4, None);
+            assert_eq!(screen.state, State::Wait);
+        }
+    }
+
+    mod minimap {
+        use super::*;
+
+        #[test]
+        fn it_takes_columns_from_the_data_frame() {
+            let mut screen = screen(4096);
+            screen.set_minimap(Some(Measure::Entropy));
+            assert_eq!(screen.data_frame_width(), 78);
+            screen.set_minimap(None);
+            assert_eq!(screen.data_frame_width(), 80);
+        }
+
+        #[test]
+        fn it_is_summarised_in_steps() {
+            let mut screen =
+                screen(crate::minimap::BLOCK_SIZE * crate::minimap::BLOCKS_PER_STEP * 2);
+            assert!(!screen.summarise());
+
+            screen.toggle_minimap();
+            assert!(screen.summarise());
+            assert!(screen.summarise());
+            assert!(!screen.summarise());
+        }
+
+        #[test]
+        fn it_jumps_between_regions() {
+            // 22 rows of the data frame split 2200 bytes into regions of 100
+            let mut screen = screen(2200);
+            screen.toggle_minimap();
+            screen.next_region();
+            assert_eq!(screen.scroll_y, 100 / 32);
+            screen.next_region();
+            assert_eq!(screen.scroll_y, 200 / 32);
+            screen.previous_region();
+            assert_eq!(screen.scroll_y, 100 / 32);
+            screen.render().unwrap();
+        }
+    }
+
+    mod templates {
+        use super::*;
+
+        fn template_file(name: &str, source: &str) -> String {
+            let path = std::env::temp_dir().join(format!("hi-{}-{}", std::process::id(), name));
+            fs::write(&path, source).unwrap();
+            path.to_str().unwrap().to_owned()
+        }
+
+        #[test]
+        fn applying_a_template_opens_the_panel() {
+            let path = template_file("pair", "struct Pair { a: u16; b: u16; }");
+            let mut screen = screen(64);
+            screen.apply_template(&path, Some(4));
+
+            let applied = screen.buffer().template.as_ref().unwrap();
+            assert_eq!(applied.fields.len(), 3);
+            assert_eq!(applied.fields[2].range, 6..8);
+            assert_eq!(screen.data_frame_width(), 80 - panel::WIDTH);
+            screen.render().unwrap();
+
+            screen.clear_template();
+            assert_eq!(screen.data_frame_width(), 80);
+            fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn template_errors_are_reported_with_their_line() {
+            let path = template_file("broken", "struct Broken {\n  a u8;\n}");
+            let mut screen = screen(64);
+            screen.apply_template(&path, None);
+
+            assert!(screen.buffer().template.is_none());
+            assert_eq!(
+                screen.message,
+                Some(format!("{}: line 2: expected ':', found 'u8'", path))
+            );
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    mod hash {
+        use super::*;
+
+        #[test]
+        fn it_shows_the_checksum_of_a_range() {
+            let mut screen = screen(64);
+            let crc32 = Algorithm::from_name("crc32").unwrap();
+            screen.hash(crc32, Some((8, 4)), false);
+            assert_eq!(screen.message, Some("crc32 0x8..0xc: 2144df1c".to_owned()));
+
+            screen.hash(crc32, Some((60, 8)), false);
+            assert_eq!(
+                screen.message,
+                Some("0x8 bytes from 0x3c runs past the end of the file".to_owned())
+            );
+        }
+
+        #[test]
+        fn copying_sends_the_result_to_the_terminal() {
+            let mut screen = screen(0);
+            screen.hash(Algorithm::from_name("adler32").unwrap(), None, true);
+            assert_eq!(
+                screen.message,
+                Some("adler32 0x0..0x0: 00000001 (copied)".to_owned())
+            );
+            assert!(screen.out.ends_with(b"\x1b]52;c;MDAwMDAwMDE=\x07"));
+        }
+    }
+
+    mod edits {
+        use super::*;
+
+        #[test]
+        fn filling_repeats_the_pattern_as_one_edit() {
+            let mut screen = screen(16);
+            screen.fill(2, 5, b"ab");
+
+            assert_eq!(&screen.data()[..8], b"\0\0ababa\0");
+            assert_eq!(screen.buffer().edits.len(), 1);
+            assert_eq!(screen.buffer().edits[0].range, 2..7);
+            screen.render().unwrap();
+
+            screen.fill(12, 8, b"x");
+            assert_eq!(screen.buffer().edits.len(), 1);
+            assert_eq!(
+                screen.message,
+                Some("0x8 bytes from 0xc runs past the end of the file".to_owned())
+            );
+
+            screen.fill(0, 0, b"x");
+            assert_eq!(screen.buffer().edits.len(), 1);
+            assert_eq!(screen.message, Some("nothing to fill".to_owned()));
+        }
+
+        #[test]
+        fn quitting_with_unsaved_edits_needs_confirming() {
+            let mut screen = screen(16);
+            assert!(screen.confirm_quit());
+
+            screen.fill(0, 1, b"x");
+            assert!(!screen.confirm_quit());
+            assert!(screen.confirm_quit());
+        }
+
+        #[test]
+        fn transforms_are_previewed_until_committed() {
+            let mut screen = screen(8);
+            screen.transform(Transform::Xor(vec![0x0F, 0xF0]), Some((2, 3)));
+            assert_eq!(screen.data(), b"\0\0\x0f\xf0\x0f\0\0\0");
+            assert!(screen.buffer().edits.is_empty());
+
+            // a second transform replaces the preview instead of stacking on it
+            screen.transform(Transform::Not, Some((0, 2)));
+            assert_eq!(screen.data(), b"\xff\xff\0\0\0\0\0\0");
+            screen.transform(Transform::Xor(vec![0x0F]), Some((2, 3)));
+            screen.transform(Transform::Not, Some((1, 2)));
+            assert_eq!(screen.data(), b"\0\xff\xff\0\0\0\0\0");
+            screen.transform(Transform::Not, Some((0, 2)));
+            screen.fill(0, 1, b"x");
+            assert_eq!(screen.data()[0], 0xFF);
+            screen.render().unwrap();
+
+            screen.commit();
+            assert_eq!(screen.message, Some("committed not".to_owned()));
+            assert_eq!(screen.buffer().edits.len(), 1);
+            screen.commit();
+            assert_eq!(screen.message, Some("nothing to commit".to_owned()));
+        }
+
+        #[test]
+        fn transforms_cover_the_highlight_and_revert() {
+            let mut screen = screen(8);
+            screen.buffers[screen.current_buffer].highlight = Some(4..12);
+            screen.transform(Transform::Add(1), None);
+            assert_eq!(screen.data(), b"\0\0\0\0\x01\x01\x01\x01");
+
+            screen.revert();
+            assert_eq!(
+                screen.message,
+                Some("reverted add over 0x4..0x8".to_owned())
+            );
+            assert_eq!(screen.data(), &[0; 8][..]);
+            screen.revert();
+            assert_eq!(screen.message, Some("nothing to revert".to_owned()));
+
+            screen.buffers[screen.current_buffer].highlight = None;
+            screen.transform(Transform::ByteSwap(2), None);
+            assert_eq!(screen.buffer().preview.as_ref().unwrap().range, 0..8);
+        }
+    }
+
+    mod bookmarks {
+        use super::*;
+
+        fn bookmarked_screen(name: &str) -> Screen<Vec<u8>> {
+            let path = std::env::temp_dir().join(format!("hi-{}-{}", std::process::id(), name));
+            let mut screen = screen(64);
+            screen.buffers[0].context.file_path = path.to_str().unwrap().to_owned();
+            screen
+        }
+
+        #[test]
+        fn bookmarks_are_kept_beside_the_file() {
+            let mut screen = bookmarked_screen("bookmarks");
+            let path = screen.buffer().path().to_owned();
+            screen.add_bookmark(Some((0x20, 4)), "crc".to_owned());
+            screen.buffers[0].highlight = Some(8..12);
+            screen.add_bookmark(None, "header".to_owned());
+            assert_eq!(screen.message, Some("bookmarked header at 0x8".to_owned()));
+            screen.render().unwrap();
+
+            fs::write(&path, screen.data()).unwrap();
+            let reopened = Buffer::open(&path).unwrap();
+            assert_eq!(reopened.bookmarks, screen.buffer().bookmarks);
+            assert_eq!(reopened.bookmarks[0].label, "header");
+
+            screen.remove_bookmark("crc");
+            screen.remove_bookmark("header");
+            assert!(fs::metadata(bookmark::sidecar(&path)).is_err());
+            screen.remove_bookmark("header");
+            assert_eq!(
+                screen.message,
+                Some("no bookmark labelled header".to_owned())
+            );
+            fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn selecting_a_bookmark_jumps_to_it() {
+            let mut screen = bookmarked_screen("selecting");
+            let path = screen.buffer().path().to_owned();
+            screen.add_bookmark(Some((0x100, 1)), "past the end".to_owned());
+            assert!(screen.buffer().bookmarks.is_empty());
+
+            screen.set_width(16);
+            screen.add_bookmark(Some((0x30, 0)), "here".to_owned());
+            screen.show_bookmarks();
+            screen.select_overlay_item();
+            assert_eq!(screen.scroll_y, 3);
+            assert_eq!(screen.buffer().highlight, None);
+
+            let export = format!("{}.json", path);
+            screen.export_bookmarks(&export);
+            assert!(fs::read_to_string(&export)
+                .unwrap()
+                .contains("{\"offset\": 48, \"length\": 0, \"label\": \"here\"}"));
+            screen.remove_bookmark("here");
+            fs::remove_file(export).unwrap();
+        }
+    }
+
+    mod macros {
+        use super::*;
+        use termion::event::{Event, Key};
+
+        #[test]
+        fn recording_is_shown_in_the_status_bar() {
+            let mut screen = screen(16);
+            screen.start_recording('a');
+            screen.macros.record(&Event::Key(Key::Char('j')));
+            screen.macros.record(&Event::Key(Key::Char('q')));
+            screen.render().unwrap();
+            assert!(String::from_utf8_lossy(&screen.out).contains("|recording @a"));
+
+            screen.stop_recording();
+            assert_eq!(screen.message, Some("recorded @a: j".to_owned()));
+            screen.define_macro('b', Some(vec![Event::Key(Key::Ctrl('d'))]));
+            screen.define_macro('b', None);
+            assert_eq!(screen.message, Some("@b: <C-d>".to_owned()));
+            screen.define_macro('c', None);
+            assert_eq!(screen.message, Some("register c is empty".to_owned()));
+        }
+    }
+
+    mod counts {
+        use super::*;
+
+        type Motion = fn(&mut Screen<Vec<u8>>, usize);
+
+        #[test]
+        fn counts_move_as_far_as_repeating_the_key() {
+            let mut counted = screen(4096);
+            let mut repeated = screen(4096);
+            for screen in [&mut counted, &mut repeated] {
+                screen.set_width(200);
+                screen.set_group_size(4);
+            }
+            let moves: [(Motion, usize); 7] = [
+                (Screen::down, 20),
+                (Screen::page_down, 2),
+                (Screen::up, 3),
+                (Screen::page_up, 1),
+                (Screen::scroll_right, 5),
+                (Screen::scroll_left, 2),
+                (Screen::scroll_right, 1000),
+            ];
+            for (motion, count) in moves {
+                motion(&mut counted, count);
+                for _ in 0..count {
+                    motion(&mut repeated, 1);
+                }
+                assert_eq!(
+                    (counted.scroll_x, counted.scroll_y),
+                    (repeated.scroll_x, repeated.scroll_y),
+                    "{:?}",
+                    count
+                );
+            }
+
+            counted.down(usize::MAX);
+            repeated.end();
+            assert_eq!(
+                (counted.scroll_x, counted.scroll_y),
+                (repeated.scroll_x, repeated.scroll_y)
+            );
+            counted.page_up(usize::MAX);
+            assert_eq!(counted.scroll_y, 0);
+        }
+
+        #[test]
+        fn the_count_is_shown_until_taken() {
+            let mut screen = screen(16);
+            assert_eq!(screen.take_count(), 1);
+            screen.push_count(2);
+            screen.push_count(0);
+            screen.render().unwrap();
+            assert!(String::from_utf8_lossy(&screen.out).contains("20|State::Wait"));
+            assert_eq!(screen.take_count(), 20);
+            assert_eq!(screen.count(), None);
+        }
+    }
+
+    mod goto {
+        use super::*;
+
+        #[test]
+        fn it_scrolls_to_the_row_containing_the_index() {
+            let mut screen = screen(4096);
+            screen.goto(70);
+            assert_eq!((screen.offset, screen.scroll_y), (0, 2));
+        }
+
+        #[test]
+        fn it_keeps_row_alignment_when_jumping_before_the_offset() {
+            let mut screen = screen(4096);
+            screen.set_offset(100);
+            screen.goto(40);
+            assert_eq!((screen.offset, screen.scroll_y), (4, 1));
+
+            screen.set_offset(100);
+            screen.goto(2);
+            assert_eq!((screen.offset, screen.scroll_y), (0, 0));
+        }
+    }
+
     mod max_scroll {
         use super::max_scroll_y;
 
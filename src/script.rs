@@ -0,0 +1,78 @@
+//! Sequences of prompt commands, read from a file or given on the command line
+//!
+//! A script holds one command per line, written as it would be typed at the prompt with or
+//! without the leading `:`. Blank lines and lines starting with `#` are skipped.
+use std::env;
+use std::error::Error as StdError;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::command_prompt::parser::parse_command;
+use crate::command_prompt::Command;
+
+/// A line of a script which isn't a command
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    pub line: usize,
+    pub text: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: unknown command: {}", self.line, self.text)
+    }
+}
+
+impl StdError for Error {}
+
+/// `$XDG_CONFIG_HOME/hi/config`, or `~/.config/hi/config` when that isn't set
+///
+/// The config file is a script run whenever a file is opened in the terminal interface.
+pub fn config_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("hi").join("config"))
+}
+
+/// The commands in `source` with their line numbers, or the first line which isn't a command
+///
+/// Nothing is returned unless every line is understood, so a script never runs halfway.
+pub fn parse(source: &str) -> Result<Vec<(usize, Command)>, Error> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, text)| {
+            let command = text.strip_prefix(':').unwrap_or(text);
+            parse_command(command)
+                .map(|command| (line, command))
+                .map_err(|_| Error {
+                    line,
+                    text: text.to_owned(),
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_parsed_as_prompt_commands() {
+        let source = "# set up\n:w 16\n\n  goto 0x400\n";
+        assert_eq!(
+            parse(source),
+            Ok(vec![(2, Command::SetWidth(16)), (4, Command::Goto(0x400))])
+        );
+    }
+
+    #[test]
+    fn errors_give_the_line() {
+        let error = parse(":w 16\n:frobnicate\n:goto x\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: unknown command: :frobnicate");
+    }
+}
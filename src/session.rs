@@ -0,0 +1,211 @@
+//! Where each file was left, so reopening it carries on from there
+//!
+//! The view and marks of every open buffer are written to a state directory on quitting, in a
+//! file per path named by the path's SHA-1. They're restored when the file is next opened, as
+//! long as its size and modification time haven't changed in between.
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::buffer::{Buffer, View};
+use crate::encoding::Encoding;
+use crate::hash::Algorithm;
+use crate::line::Mode;
+
+/// `$XDG_STATE_HOME/hi`, or `~/.local/state/hi` when that isn't set
+pub fn state_dir() -> Option<PathBuf> {
+    env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))
+        .map(|dir| dir.join("hi"))
+}
+
+/// Path in `dir` of the session of the file at `path`
+fn session_file(dir: &Path, path: &str) -> PathBuf {
+    let path = fs::canonicalize(path)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.to_owned());
+    dir.join(Algorithm::Sha1.digest(path.as_bytes()))
+}
+
+/// Size and modification time of the file at `path`, to tell whether it's changed
+fn stamp(path: &str) -> io::Result<(u64, String)> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|time| format!("{}.{:09}", time.as_secs(), time.subsec_nanos()))
+        .unwrap_or_default();
+    Ok((metadata.len(), modified))
+}
+
+#[derive(Debug, PartialEq)]
+struct Session {
+    stamp: (u64, String),
+    view: View,
+    marks: HashMap<char, usize>,
+}
+
+/// Restore the view and marks `buffer` was left with in `dir`, if its file hasn't changed since
+///
+/// Returns whether there was anything to restore.
+pub fn restore(dir: &Path, buffer: &mut Buffer) -> bool {
+    let session = fs::read_to_string(session_file(dir, buffer.path()))
+        .ok()
+        .and_then(|text| parse(&text));
+    match (session, stamp(buffer.path())) {
+        (Some(session), Ok(stamp)) if session.stamp == stamp => {
+            buffer.view = session.view;
+            buffer.marks = session.marks;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Write the view and marks of `buffer` to `dir`, creating it if needed
+pub fn save(dir: &Path, buffer: &Buffer) -> io::Result<()> {
+    let (size, modified) = stamp(buffer.path())?;
+    let view = &buffer.view;
+    let on_off = |on| if on { "on" } else { "off" };
+
+    let mut text = format!("# {}\n", buffer.path());
+    writeln!(text, "size {}", size).unwrap();
+    writeln!(text, "modified {}", modified).unwrap();
+    writeln!(text, "offset {}", view.offset).unwrap();
+    writeln!(text, "scroll_y {}", view.scroll_y).unwrap();
+    writeln!(text, "scroll_x {}", view.scroll_x).unwrap();
+    writeln!(text, "width {}", view.bytes_per_row).unwrap();
+    writeln!(text, "mode {}", view.format.mode.name()).unwrap();
+    writeln!(text, "group {}", view.format.group_size).unwrap();
+    writeln!(text, "little_endian {}", on_off(view.format.little_endian)).unwrap();
+    writeln!(text, "encoding {}", view.format.encoding.name()).unwrap();
+    writeln!(text, "pictures {}", on_off(view.format.pictures)).unwrap();
+    writeln!(text, "wrap {}", on_off(view.wrap)).unwrap();
+    let mut marks: Vec<_> = buffer.marks.iter().collect();
+    marks.sort();
+    for (name, index) in marks {
+        writeln!(text, "mark {} {}", name, index).unwrap();
+    }
+
+    fs::create_dir_all(dir)?;
+    fs::write(session_file(dir, buffer.path()), text)
+}
+
+/// Read a session, `None` if anything in it can't be understood
+fn parse(text: &str) -> Option<Session> {
+    let mut size = None;
+    let mut modified = None;
+    let mut view = View::default();
+    let mut marks = HashMap::new();
+
+    for line in text.lines().filter(|line| !line.starts_with('#')) {
+        let (key, value) = line.split_once(' ')?;
+        match key {
+            "size" => size = Some(value.parse().ok()?),
+            "modified" => modified = Some(value.to_owned()),
+            "offset" => view.offset = value.parse().ok()?,
+            "scroll_y" => view.scroll_y = value.parse().ok()?,
+            "scroll_x" => view.scroll_x = value.parse().ok()?,
+            "width" => view.bytes_per_row = value.parse().ok().filter(|&width| width > 0)?,
+            "mode" => view.format.mode = Mode::from_name(value)?,
+            "group" => {
+                view.format.group_size = value
+                    .parse()
+                    .ok()
+                    .filter(|group: &usize| group.is_power_of_two() && *group <= 8)?
+            }
+            "little_endian" => view.format.little_endian = on_off(value)?,
+            "encoding" => view.format.encoding = Encoding::from_name(value)?,
+            "pictures" => view.format.pictures = on_off(value)?,
+            "wrap" => view.wrap = on_off(value)?,
+            "mark" => {
+                let (name, index) = value.split_once(' ')?;
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(name), None) => marks.insert(name, index.parse().ok()?),
+                    _ => return None,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    Some(Session {
+        stamp: (size?, modified?),
+        view,
+        marks,
+    })
+}
+
+fn on_off(value: &str) -> Option<bool> {
+    match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line::Format;
+
+    #[test]
+    fn views_are_restored_until_the_file_changes() {
+        let dir = env::temp_dir().join(format!("hi-session-{}", std::process::id()));
+        let path = dir.join("data.bin");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, [0; 64]).unwrap();
+        let path = path.to_str().unwrap();
+
+        let mut buffer = Buffer::open(path).unwrap();
+        assert!(!restore(&dir, &mut buffer));
+        let view = View {
+            offset: 3,
+            scroll_y: 2,
+            bytes_per_row: 16,
+            format: Format {
+                mode: Mode::Ascii,
+                group_size: 4,
+                encoding: Encoding::Cp437,
+                ..Format::default()
+            },
+            wrap: true,
+            ..View::default()
+        };
+        buffer.view = view.clone();
+        buffer.marks.insert('a', 40);
+        save(&dir, &buffer).unwrap();
+
+        let mut reopened = Buffer::open(path).unwrap();
+        assert!(restore(&dir, &mut reopened));
+        assert_eq!(reopened.view, view);
+        assert_eq!(reopened.marks, buffer.marks);
+
+        fs::write(path, [0; 65]).unwrap();
+        let mut changed = Buffer::open(path).unwrap();
+        assert!(!restore(&dir, &mut changed));
+        assert_eq!(changed.view, View::default());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn sessions_which_cant_be_understood_are_ignored() {
+        let session =
+            parse("# a\nsize 4\nmodified 1.5\nwidth 8\nmark q 2\nfuture thing\n").unwrap();
+        assert_eq!(session.stamp, (4, "1.5".to_owned()));
+        assert_eq!(session.view.bytes_per_row, 8);
+        assert_eq!(session.marks[&'q'], 2);
+
+        assert_eq!(parse("size 4\n"), None);
+        assert_eq!(parse("size 4\nmodified 1\nwidth 0\n"), None);
+        assert_eq!(parse("size 4\nmodified 1\ngroup 3\n"), None);
+        assert_eq!(parse("size 4\nmodified 1\nmode hexadecimal\n"), None);
+    }
+}
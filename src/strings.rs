@@ -0,0 +1,139 @@
+//! Extraction of text runs from binary data, like the `strings` utility
+use crate::encoding::Encoding;
+
+/// Shortest run of characters reported when no length is given
+pub const DEFAULT_MIN_LENGTH: usize = 4;
+
+/// A run of printable characters
+#[derive(Clone, Debug, PartialEq)]
+pub struct Found {
+    /// Index of the first byte of the run
+    pub offset: usize,
+    pub encoding: Encoding,
+    pub text: String,
+}
+
+/// Characters which can make up a string, the same as `strings` accepts
+fn is_string_byte(byte: u8) -> bool {
+    byte == b'\t' || (0x20..=0x7E).contains(&byte)
+}
+
+/// Find runs of at least `min_length` characters in each of `encodings`, ordered by offset
+///
+/// ASCII and UTF-16LE are supported, UTF-16LE runs are made up of ASCII characters each followed
+/// by a zero byte. Other encodings are ignored.
+pub fn find(data: &[u8], min_length: usize, encodings: &[Encoding]) -> Vec<Found> {
+    let min_length = min_length.max(1);
+    let mut found = Vec::new();
+    for &encoding in encodings {
+        match encoding {
+            Encoding::Ascii => find_ascii(data, min_length, &mut found),
+            Encoding::Utf16Le => find_utf16le(data, min_length, &mut found),
+            _ => {}
+        }
+    }
+    found.sort_by_key(|string| string.offset);
+    found
+}
+
+fn find_ascii(data: &[u8], min_length: usize, found: &mut Vec<Found>) {
+    let mut push = |start: usize, end: usize| {
+        if end - start >= min_length {
+            found.push(Found {
+                offset: start,
+                encoding: Encoding::Ascii,
+                text: String::from_utf8_lossy(&data[start..end]).into_owned(),
+            });
+        }
+    };
+
+    let mut run_start = None;
+    for (i, &byte) in data.iter().enumerate() {
+        match (is_string_byte(byte), run_start) {
+            (true, None) => run_start = Some(i),
+            (false, Some(start)) => {
+                push(start, i);
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = run_start {
+        push(start, data.len());
+    }
+}
+
+fn find_utf16le(data: &[u8], min_length: usize, found: &mut Vec<Found>) {
+    let mut i = 0;
+    while i + 1 < data.len() {
+        let start = i;
+        let mut text = String::new();
+        while i + 1 < data.len() && is_string_byte(data[i]) && data[i + 1] == 0 {
+            text.push(data[i].into());
+            i += 2;
+        }
+
+        if text.len() >= min_length {
+            found.push(Found {
+                offset: start,
+                encoding: Encoding::Utf16Le,
+                text,
+            });
+        } else {
+            // runs can start at odd offsets, so only step over a single byte
+            i = start + 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_finds_ascii_runs() {
+        let data = b"\x00abc\x01hello\x02world";
+        let found = find(data, 4, &[Encoding::Ascii]);
+
+        assert_eq!(
+            found,
+            vec![
+                Found {
+                    offset: 5,
+                    encoding: Encoding::Ascii,
+                    text: "hello".to_owned()
+                },
+                Found {
+                    offset: 11,
+                    encoding: Encoding::Ascii,
+                    text: "world".to_owned()
+                },
+            ]
+        );
+        assert_eq!(find(data, 3, &[Encoding::Ascii]).len(), 3);
+    }
+
+    #[test]
+    fn it_finds_utf16le_runs_at_any_alignment() {
+        let data = b"\xFFw\x00i\x00d\x00e\x00\x00\x00n\x00o\x00";
+        let found = find(data, 3, &[Encoding::Utf16Le]);
+
+        assert_eq!(
+            found,
+            vec![Found {
+                offset: 1,
+                encoding: Encoding::Utf16Le,
+                text: "wide".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn results_are_ordered_by_offset() {
+        let data = b"w\x00i\x00d\x00e\x00\x01narrow";
+        let found = find(data, 4, &[Encoding::Ascii, Encoding::Utf16Le]);
+
+        let offsets: Vec<usize> = found.iter().map(|string| string.offset).collect();
+        assert_eq!(offsets, vec![0, 9]);
+    }
+}
@@ -0,0 +1,1398 @@
+//! Templates from C struct definitions, laid out the way x86-64 compilers lay them out
+//!
+//! A practical subset of C is understood: struct, union and enum definitions, typedefs, the
+//! types of `<stdint.h>`, arrays, pointers and object-like `#define` constants. Fields get their
+//! natural alignment unless the struct is `__attribute__((packed))` or a `#pragma pack` is in
+//! effect. Other preprocessor directives are ignored, so both branches of an `#ifdef` are read,
+//! and declarations that aren't types, like function prototypes, are skipped.
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use super::{Count, Endian, EnumDef, Error, FieldDef, Integer, Member, StructDef, Template, Type};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The structs and unions of a header file, any of which can be applied as a template
+#[derive(Debug)]
+pub struct Header {
+    template: Template,
+    /// Template struct names by tag or typedef name
+    records: HashMap<String, String>,
+}
+
+impl Header {
+    /// A template laying the struct or union called `name` over the data
+    ///
+    /// `name` is a tag, as in `struct name`, or a typedef.
+    pub fn template(&self, name: &str) -> Option<Template> {
+        let mut template = self.template.clone();
+        if template.select(self.records.get(name)?) {
+            Some(template)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse the C definitions in `source`
+pub fn parse(source: &str) -> Result<Header> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        template: Template {
+            endian: Endian::Little,
+            structs: Vec::new(),
+            enums: Vec::new(),
+            root: None,
+        },
+        tags: HashMap::new(),
+        typedefs: builtin_typedefs(),
+        constants: HashMap::new(),
+        records: HashMap::new(),
+        pack: None,
+        pushed: Vec::new(),
+        anonymous: 0,
+    };
+    parser.declarations()?;
+    Ok(Header {
+        template: parser.template,
+        records: parser.records,
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Name(String),
+    Number(u64),
+    Symbol(&'static str),
+    /// Strings and anything else, only allowed in declarations that are skipped
+    Other(String),
+    /// A `#pragma pack`, which takes effect where it appears
+    Pack(Pack),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Pack {
+    Set(Option<usize>),
+    Push(Option<usize>),
+    Pop,
+}
+
+/// Symbols, longest first so `<<` isn't read as two `<`
+const SYMBOLS: [&str; 21] = [
+    "<<", ">>", "{", "}", "(", ")", "[", "]", ";", ",", "*", "=", ":", "+", "-", "/", "%", "&",
+    "|", "^", "~",
+];
+
+/// Binary operators of constant expressions, with how tightly they bind
+const OPERATORS: [(&str, u8); 10] = [
+    ("|", 1),
+    ("^", 2),
+    ("&", 3),
+    ("<<", 4),
+    (">>", 4),
+    ("+", 5),
+    ("-", 5),
+    ("*", 6),
+    ("/", 6),
+    ("%", 6),
+];
+
+/// Largest alignment of any type, the alignment given by a bare `aligned` attribute
+const MAX_ALIGNMENT: usize = 16;
+
+/// Replace comments with spaces, keeping their newlines so line numbers stay the same
+fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+                out.push(' ');
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut last = ' ';
+                for c in &mut chars {
+                    if c == '\n' {
+                        out.push('\n');
+                    }
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+                out.push(' ');
+            }
+            ('"', _) | ('\'', _) => {
+                out.push(c);
+                while let Some(next) = chars.next() {
+                    out.push(next);
+                    if next == '\\' {
+                        out.extend(chars.next());
+                    } else if next == c || next == '\n' {
+                        break;
+                    }
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Split `source` into tokens paired with their line numbers, handling preprocessor directives
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>> {
+    let source = strip_comments(source);
+    let mut macros: HashMap<String, Vec<Token>> = HashMap::new();
+    let mut tokens = Vec::new();
+    let mut lines = source.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let number = index + 1;
+        let directive = match line.trim_start().strip_prefix('#') {
+            Some(directive) => directive,
+            None => {
+                for token in line_tokens(line) {
+                    match token {
+                        Token::Name(ref name) if macros.contains_key(name) => {
+                            tokens.extend(macros[name].iter().map(|token| (token.clone(), number)))
+                        }
+                        token => tokens.push((token, number)),
+                    }
+                }
+                continue;
+            }
+        };
+
+        let mut directive = directive.to_owned();
+        while directive.ends_with('\\') {
+            directive.pop();
+            match lines.next() {
+                Some((_, next)) => directive.push_str(next),
+                None => break,
+            }
+        }
+        let directive = directive.trim_start();
+        if let Some(rest) = directive.strip_prefix("define") {
+            let rest = rest.trim_start();
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let (name, body) = rest.split_at(end);
+            // function-like macros aren't expanded
+            if !name.is_empty() && !body.starts_with('(') {
+                let body = line_tokens(body)
+                    .into_iter()
+                    .flat_map(|token| match token {
+                        Token::Name(ref name) if macros.contains_key(name) => macros[name].clone(),
+                        token => vec![token],
+                    })
+                    .collect();
+                macros.insert(name.to_owned(), body);
+            }
+        } else if let Some(name) = directive.strip_prefix("undef") {
+            macros.remove(name.trim());
+        } else if let Some(pragma) = directive.strip_prefix("pragma") {
+            if let Some(arguments) = pragma.trim_start().strip_prefix("pack") {
+                tokens.push((Token::Pack(pack(arguments, number)?), number));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// The arguments of `#pragma pack`, like `(push, 1)`
+fn pack(arguments: &str, line: usize) -> Result<Pack> {
+    let error = || Error {
+        line,
+        message: format!("unsupported #pragma pack{}", arguments.trim_end()),
+    };
+    let arguments = arguments
+        .trim()
+        .strip_prefix('(')
+        .and_then(|arguments| arguments.strip_suffix(')'))
+        .ok_or_else(error)?;
+    let mut arguments = arguments.split(',').map(str::trim);
+    let value = |argument: Option<&str>| match argument {
+        None | Some("") => Ok(None),
+        Some(value) => match value.parse() {
+            Ok(value) if [1, 2, 4, 8, 16].contains(&value) => Ok(Some(value)),
+            _ => Err(error()),
+        },
+    };
+    match arguments.next() {
+        Some("push") => Ok(Pack::Push(value(arguments.next_back())?)),
+        Some("pop") => Ok(Pack::Pop),
+        argument => Ok(Pack::Set(value(argument)?)),
+    }
+}
+
+fn line_tokens(line: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let end = if c.is_whitespace() {
+            c.len_utf8()
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..end].to_owned()));
+            end
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '.')
+                .unwrap_or(rest.len());
+            let text = &rest[..end];
+            tokens.push(match integer_literal(text) {
+                Some(n) => Token::Number(n),
+                None => Token::Other(text.to_owned()),
+            });
+            end
+        } else if c == '"' || c == '\'' {
+            let end = literal_length(rest);
+            let text = &rest[..end];
+            let mut chars = text.chars();
+            tokens.push(
+                match (chars.next(), chars.next(), chars.next(), chars.next()) {
+                    (Some('\''), Some(c), Some('\''), None) if c != '\\' => Token::Number(c as u64),
+                    _ => Token::Other(text.to_owned()),
+                },
+            );
+            end
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        } else {
+            tokens.push(Token::Other(c.to_string()));
+            c.len_utf8()
+        };
+        rest = &rest[end..];
+    }
+    tokens
+}
+
+/// Length of the string or character literal at the start of `text`, including its quotes
+fn literal_length(text: &str) -> usize {
+    let quote = text.as_bytes()[0];
+    let mut escaped = false;
+    for (i, &b) in text.as_bytes().iter().enumerate().skip(1) {
+        match b {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            _ if b == quote => return i + 1,
+            _ => (),
+        }
+    }
+    text.len()
+}
+
+/// A decimal, hex or octal integer, with any `u` and `l` suffixes
+fn integer_literal(text: &str) -> Option<u64> {
+    let text = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if text.len() > 1 && text.starts_with('0') {
+        u64::from_str_radix(&text[1..], 8).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// How a type is laid out, and how it's shown when applied
+#[derive(Clone, Debug, PartialEq)]
+struct Layout {
+    kind: Type,
+    /// Number of elements of array types
+    count: Option<u64>,
+    size: usize,
+    align: usize,
+}
+
+impl Layout {
+    fn integer(size: usize, signed: bool, endian: Option<Endian>) -> Layout {
+        Layout {
+            kind: Type::Integer(Integer {
+                size,
+                signed,
+                endian,
+            }),
+            count: None,
+            size,
+            align: size,
+        }
+    }
+
+    fn pointer() -> Layout {
+        Layout::integer(8, false, None)
+    }
+}
+
+/// A type named in a declaration, which can only be used through a pointer when incomplete
+#[derive(Clone, Debug)]
+enum Base {
+    Complete(Layout),
+    /// A struct that's declared but not yet defined, or void
+    Incomplete(String),
+}
+
+/// A type given a name with `typedef`
+#[derive(Clone, Debug)]
+enum Typedef {
+    Layout(Layout),
+    /// A struct, union or enum that wasn't defined at the typedef, looked up when used
+    Tag(String),
+}
+
+#[derive(Default)]
+struct Attributes {
+    packed: bool,
+    aligned: Option<usize>,
+}
+
+/// A name being declared, and how its type differs from the type at the start of the declaration
+struct Declarator {
+    name: String,
+    pointer: bool,
+    /// Lengths of each dimension of arrays
+    dimensions: Vec<u64>,
+    /// Functions, which take up no space in a struct
+    function: bool,
+}
+
+/// A member of a struct or union being defined
+struct Declared {
+    name: String,
+    layout: Layout,
+    attributes: Attributes,
+}
+
+fn builtin_typedefs() -> HashMap<String, Typedef> {
+    let mut typedefs = HashMap::new();
+    let mut add = |name: &str, size, signed, endian| {
+        typedefs.insert(
+            name.to_owned(),
+            Typedef::Layout(Layout::integer(size, signed, endian)),
+        );
+    };
+    for &size in &[1, 2, 4, 8] {
+        let bits = size * 8;
+        add(&format!("int{}_t", bits), size, true, None);
+        add(&format!("uint{}_t", bits), size, false, None);
+        add(&format!("__s{}", bits), size, true, None);
+        add(&format!("__u{}", bits), size, false, None);
+        if size > 1 {
+            add(&format!("__le{}", bits), size, false, Some(Endian::Little));
+            add(&format!("__be{}", bits), size, false, Some(Endian::Big));
+        }
+    }
+    for name in &["size_t", "uintptr_t", "uintmax_t"] {
+        add(name, 8, false, None);
+    }
+    for name in &["ssize_t", "intptr_t", "ptrdiff_t", "intmax_t"] {
+        add(name, 8, true, None);
+    }
+    add("bool", 1, false, None);
+    add("wchar_t", 4, true, None);
+    typedefs
+}
+
+fn align_up(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    template: Template,
+    /// Defined structs, unions and enums by `struct NAME`, `union NAME` or `enum NAME`
+    tags: HashMap<String, Layout>,
+    typedefs: HashMap<String, Typedef>,
+    /// Enumerators, which can be used in constant expressions
+    constants: HashMap<String, i128>,
+    /// Template struct names of structs and unions, by tag and typedef name
+    records: HashMap<String, String>,
+    /// Maximum alignment of fields set by `#pragma pack`
+    pack: Option<usize>,
+    pushed: Vec<Option<usize>>,
+    /// Number of structs, unions and enums without a tag so far
+    anonymous: usize,
+}
+
+impl Parser {
+    fn peek(&mut self) -> Option<&Token> {
+        while let Some(&(Token::Pack(pack), _)) = self.tokens.get(self.position) {
+            self.position += 1;
+            match pack {
+                Pack::Set(value) => self.pack = value,
+                Pack::Push(value) => {
+                    self.pushed.push(self.pack);
+                    if value.is_some() {
+                        self.pack = value;
+                    }
+                }
+                Pack::Pop => self.pack = self.pushed.pop().unwrap_or(None),
+            }
+        }
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |&(_, line)| line)
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error {
+            line: self.line(),
+            message: message.to_owned(),
+        }
+    }
+
+    fn unexpected(&mut self, expected: &str) -> Error {
+        let found = match self.peek() {
+            Some(Token::Name(name)) => format!("'{}'", name),
+            Some(Token::Number(n)) => n.to_string(),
+            Some(Token::Symbol(symbol)) => format!("'{}'", symbol),
+            Some(Token::Other(text)) => format!("'{}'", text),
+            Some(Token::Pack(_)) | None => "the end of the file".to_owned(),
+        };
+        self.error(&format!("expected {}, found {}", expected, found))
+    }
+
+    fn accept(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(&Token::Symbol(s)) if s == symbol => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        if self.accept(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", symbol)))
+        }
+    }
+
+    fn accept_word(&mut self, words: &[&str]) -> Option<String> {
+        match self.peek() {
+            Some(Token::Name(name)) if words.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.position += 1;
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    fn name(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Name(name)) => {
+                let name = name.clone();
+                self.position += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    /// Declarations up to the end of the file
+    fn declarations(&mut self) -> Result<()> {
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Symbol(";") => self.position += 1,
+                Token::Name(word) if word == "typedef" => {
+                    self.position += 1;
+                    let base = self.specifier()?;
+                    self.typedefs(base)?;
+                }
+                Token::Name(word) if word == "struct" || word == "union" || word == "enum" => {
+                    self.specifier()?;
+                    if !self.accept(";") {
+                        self.skip_declaration();
+                    }
+                }
+                // variables and functions don't define any types
+                _ => self.skip_declaration(),
+            }
+        }
+        Ok(())
+    }
+
+    /// Skip to the end of a declaration, or the body of a function definition
+    fn skip_declaration(&mut self) {
+        let mut depth = 0;
+        let mut last = None;
+        while let Some(token) = self.next() {
+            match token {
+                Token::Symbol("(") | Token::Symbol("[") => depth += 1,
+                Token::Symbol(")") | Token::Symbol("]") => depth -= 1,
+                Token::Symbol("{") if depth == 0 && last == Some(Token::Symbol(")")) => {
+                    self.skip_block();
+                    return;
+                }
+                Token::Symbol("{") => self.skip_block(),
+                Token::Symbol(";") if depth <= 0 => return,
+                _ => (),
+            }
+            last = Some(token);
+        }
+    }
+
+    /// Skip to the brace closing one that's just been read
+    fn skip_block(&mut self) {
+        let mut depth = 1;
+        while let Some(token) = self.next() {
+            match token {
+                Token::Symbol("{") => depth += 1,
+                Token::Symbol("}") if depth == 1 => return,
+                Token::Symbol("}") => depth -= 1,
+                _ => (),
+            }
+        }
+    }
+
+    /// The type at the start of a declaration, defining any struct, union or enum in it
+    fn specifier(&mut self) -> Result<Base> {
+        let mut words = Vec::new();
+        while let Some(Token::Name(word)) = self.peek().cloned() {
+            match word.as_str() {
+                "const" | "volatile" | "restrict" | "static" | "extern" | "inline" | "register"
+                | "__extension__" => self.position += 1,
+                "signed" | "unsigned" | "short" | "long" | "int" | "char" | "float" | "double"
+                | "_Bool" | "void" => {
+                    self.position += 1;
+                    words.push(word);
+                }
+                "struct" | "union" if words.is_empty() => return self.record(),
+                "enum" if words.is_empty() => return self.enumeration(),
+                _ if words.is_empty() => {
+                    let base = match self.typedefs.get(&word) {
+                        Some(Typedef::Layout(layout)) => Base::Complete(layout.clone()),
+                        Some(Typedef::Tag(tag)) => match self.tags.get(tag) {
+                            Some(layout) => Base::Complete(layout.clone()),
+                            None => Base::Incomplete(tag.clone()),
+                        },
+                        None => return Err(self.error(&format!("unknown type {}", word))),
+                    };
+                    self.position += 1;
+                    return Ok(base);
+                }
+                _ => break,
+            }
+        }
+        if words.is_empty() {
+            return Err(self.unexpected("a type"));
+        }
+
+        let has = |word| words.iter().any(|w| w == word);
+        let signed = !has("unsigned");
+        let layout = if has("void") {
+            return Ok(Base::Incomplete("void".to_owned()));
+        } else if has("_Bool") {
+            Layout::integer(1, false, None)
+        } else if has("float") {
+            Layout {
+                kind: Type::Float(Integer {
+                    size: 4,
+                    signed: true,
+                    endian: None,
+                }),
+                ..Layout::integer(4, true, None)
+            }
+        } else if has("double") {
+            if has("long") {
+                return Err(self.error("long double isn't supported"));
+            }
+            Layout {
+                kind: Type::Float(Integer {
+                    size: 8,
+                    signed: true,
+                    endian: None,
+                }),
+                ..Layout::integer(8, true, None)
+            }
+        } else if has("char") {
+            if has("signed") || has("unsigned") {
+                Layout::integer(1, signed, None)
+            } else {
+                Layout {
+                    kind: Type::Char,
+                    ..Layout::integer(1, true, None)
+                }
+            }
+        } else if has("short") {
+            Layout::integer(2, signed, None)
+        } else if has("long") {
+            Layout::integer(8, signed, None)
+        } else {
+            Layout::integer(4, signed, None)
+        };
+        Ok(Base::Complete(layout))
+    }
+
+    /// A name for a struct, union or enum without a tag
+    fn anonymous(&mut self) -> String {
+        self.anonymous += 1;
+        format!("(anonymous {})", self.anonymous)
+    }
+
+    /// `struct TAG`, or a struct or union definition
+    fn record(&mut self) -> Result<Base> {
+        let keyword = self.name()?;
+        let union = keyword == "union";
+        let mut attributes = self.attributes()?;
+        let tag = match self.peek() {
+            Some(Token::Name(_)) => Some(self.name()?),
+            _ => None,
+        };
+        let key = tag.as_ref().map(|tag| format!("{} {}", keyword, tag));
+        if !self.accept("{") {
+            let key = key.ok_or_else(|| self.unexpected("a name or '{'"))?;
+            return Ok(match self.tags.get(&key) {
+                Some(layout) => Base::Complete(layout.clone()),
+                None => Base::Incomplete(key),
+            });
+        }
+        if let Some(key) = &key {
+            if self.tags.contains_key(key) {
+                return Err(self.error(&format!("{} is already defined", key)));
+            }
+        }
+
+        let mut members = Vec::new();
+        while !self.accept("}") {
+            if self.peek().is_none() {
+                return Err(self.unexpected("'}'"));
+            }
+            members.extend(self.member()?);
+        }
+        let trailing = self.attributes()?;
+        attributes.packed |= trailing.packed;
+        attributes.aligned = attributes.aligned.max(trailing.aligned);
+
+        let name = match tag {
+            Some(tag) => tag,
+            None => self.anonymous(),
+        };
+        let layout = self.lay_out(&name, union, &attributes, members);
+        if let Some(key) = key {
+            self.tags.insert(key, layout.clone());
+            self.records.insert(name.clone(), name);
+        }
+        Ok(Base::Complete(layout))
+    }
+
+    /// Place `members` the way the SysV x86-64 ABI does, adding the struct to the template
+    fn lay_out(
+        &mut self,
+        name: &str,
+        union: bool,
+        attributes: &Attributes,
+        members: Vec<Declared>,
+    ) -> Layout {
+        let mut defs = Vec::new();
+        let mut end = 0;
+        let mut align = 1;
+        for member in members {
+            let mut member_align = member.layout.align;
+            if attributes.packed || member.attributes.packed {
+                member_align = 1;
+            }
+            member_align = member_align.max(member.attributes.aligned.unwrap_or(1));
+            // as with GCC, #pragma pack caps even alignment asked for with aligned(N)
+            if let Some(pack) = self.pack {
+                member_align = member_align.min(pack);
+            }
+
+            if !union {
+                let offset = align_up(end, member_align);
+                if offset > end {
+                    defs.push(Member::Padding(offset - end));
+                }
+                end = offset + member.layout.size;
+            } else {
+                end = end.max(member.layout.size);
+            }
+            align = align.max(member_align);
+            defs.push(Member::Field(FieldDef {
+                name: member.name,
+                kind: member.layout.kind,
+                count: member.layout.count.map(Count::Literal),
+            }));
+        }
+        align = align.max(attributes.aligned.unwrap_or(1));
+        let size = align_up(end, align);
+        if !union && size > end {
+            defs.push(Member::Padding(size - end));
+        }
+
+        self.template.structs.push(StructDef {
+            name: name.to_owned(),
+            members: defs,
+            union,
+            size: Some(size),
+        });
+        Layout {
+            kind: Type::Named(name.to_owned()),
+            count: None,
+            size,
+            align,
+        }
+    }
+
+    /// A declaration of one or more members of a struct or union
+    fn member(&mut self) -> Result<Vec<Declared>> {
+        let base = self.specifier()?;
+        if self.accept(";") {
+            // a struct or union without a name, whose members belong to the enclosing one
+            return Ok(match base {
+                Base::Complete(layout) => vec![Declared {
+                    name: "(anonymous)".to_owned(),
+                    layout,
+                    attributes: Attributes::default(),
+                }],
+                Base::Incomplete(_) => Vec::new(),
+            });
+        }
+
+        let mut members = Vec::new();
+        loop {
+            let declarator = self.declarator()?;
+            if declarator.function {
+                return Err(self.error(&format!("function {} can't be a member", declarator.name)));
+            }
+            if self.accept(":") {
+                return Err(self.error(&format!("bit-field {} isn't supported", declarator.name)));
+            }
+            let layout = self.layout(&base, &declarator)?;
+            let attributes = self.attributes()?;
+            members.push(Declared {
+                name: declarator.name,
+                layout,
+                attributes,
+            });
+            if !self.accept(",") {
+                self.expect(";")?;
+                return Ok(members);
+            }
+        }
+    }
+
+    /// The names declared by a `typedef`, after the type
+    fn typedefs(&mut self, mut base: Base) -> Result<()> {
+        loop {
+            let declarator = self.declarator()?;
+            let plain = !declarator.pointer && declarator.dimensions.is_empty();
+            let typedef = match &base {
+                _ if declarator.function => None,
+                // a struct that's defined after the typedef
+                Base::Incomplete(tag) if plain && tag != "void" => Some(Typedef::Tag(tag.clone())),
+                Base::Complete(Layout {
+                    kind: Type::Named(target),
+                    ..
+                }) if plain => {
+                    let mut target = target.clone();
+                    if target.starts_with("(anonymous") && self.rename(&target, &declarator.name) {
+                        target = declarator.name.clone();
+                        if let Base::Complete(layout) = &mut base {
+                            layout.kind = Type::Named(target.clone());
+                        }
+                    }
+                    if self.template.find_struct(&target).is_some() {
+                        self.records.insert(declarator.name.clone(), target);
+                    }
+                    Some(Typedef::Layout(self.layout(&base, &declarator)?))
+                }
+                _ => Some(Typedef::Layout(self.layout(&base, &declarator)?)),
+            };
+            // like GCC, aligned(N) after the name sets the alignment, even lowering it, while
+            // packed is ignored since the type is already laid out
+            let attributes = self.attributes()?;
+            let typedef = match (typedef, attributes.aligned) {
+                (Some(Typedef::Layout(layout)), Some(align)) => {
+                    Some(Typedef::Layout(Layout { align, ..layout }))
+                }
+                (typedef, _) => typedef,
+            };
+            if let Some(Typedef::Tag(tag)) = &typedef {
+                if let Some(target) = tag
+                    .strip_prefix("struct ")
+                    .or_else(|| tag.strip_prefix("union "))
+                {
+                    self.records
+                        .insert(declarator.name.clone(), target.to_owned());
+                }
+            }
+            if let Some(typedef) = typedef {
+                self.typedefs.insert(declarator.name, typedef);
+            }
+            if !self.accept(",") {
+                return self.expect(";");
+            }
+        }
+    }
+
+    /// Give the struct, union or enum `from`, which has no tag, the first name it's given by
+    /// `typedef`, unless that's already taken
+    fn rename(&mut self, from: &str, to: &str) -> bool {
+        if self.template.find_struct(to).is_some() || self.template.find_enum(to).is_some() {
+            return false;
+        }
+        for def in &mut self.template.structs {
+            if def.name == from {
+                def.name = to.to_owned();
+            }
+        }
+        for def in &mut self.template.enums {
+            if def.name == from {
+                def.name = to.to_owned();
+            }
+        }
+        true
+    }
+
+    /// A declarator like `*name[4]` or `(*name)(int)`
+    fn declarator(&mut self) -> Result<Declarator> {
+        let mut pointer = false;
+        loop {
+            if self.accept("*") {
+                pointer = true;
+            } else if self
+                .accept_word(&["const", "volatile", "restrict"])
+                .is_none()
+            {
+                break;
+            }
+        }
+
+        let mut dimensions = Vec::new();
+        let mut function = false;
+        let name = if self.accept("(") {
+            // pointers to functions, like `(*name)(int)` or `(*name[2])(void)`
+            self.expect("*")?;
+            let name = self.name()?;
+            self.dimensions(&mut dimensions)?;
+            self.expect(")")?;
+            self.expect("(")?;
+            self.skip_parameters();
+            pointer = true;
+            name
+        } else {
+            let name = self.name()?;
+            if self.accept("(") {
+                self.skip_parameters();
+                function = true;
+            }
+            name
+        };
+        self.dimensions(&mut dimensions)?;
+        Ok(Declarator {
+            name,
+            pointer,
+            dimensions,
+            function,
+        })
+    }
+
+    /// The layout of what `declarator` declares with the type `base`
+    fn layout(&self, base: &Base, declarator: &Declarator) -> Result<Layout> {
+        let element = match base {
+            _ if declarator.pointer => Layout::pointer(),
+            Base::Complete(layout) => layout.clone(),
+            Base::Incomplete(what) => {
+                return Err(self.error(&format!("{} has incomplete type {}", declarator.name, what)))
+            }
+        };
+        if declarator.dimensions.is_empty() {
+            return Ok(element);
+        }
+        let length = declarator
+            .dimensions
+            .iter()
+            .try_fold(1u64, |length, &n| length.checked_mul(n));
+        let count = length.and_then(|length| length.checked_mul(element.count.unwrap_or(1)));
+        let size = length
+            .and_then(|length| usize::try_from(length).ok())
+            .and_then(|length| length.checked_mul(element.size));
+        match (count, size) {
+            (Some(count), Some(size)) => Ok(Layout {
+                count: Some(count),
+                size,
+                ..element
+            }),
+            _ => Err(self.error(&format!("{} is too large", declarator.name))),
+        }
+    }
+
+    /// Array lengths like `[4][N + 1]`, with `[]` as a flexible array member of no elements
+    fn dimensions(&mut self, dimensions: &mut Vec<u64>) -> Result<()> {
+        while self.accept("[") {
+            if self.accept("]") {
+                dimensions.push(0);
+                continue;
+            }
+            let length = self.expression()?;
+            let length =
+                u64::try_from(length).map_err(|_| self.error("array lengths can't be negative"))?;
+            dimensions.push(length);
+            self.expect("]")?;
+        }
+        Ok(())
+    }
+
+    /// Skip to the parenthesis closing one that's just been read
+    fn skip_parameters(&mut self) {
+        let mut depth = 1;
+        while let Some(token) = self.next() {
+            match token {
+                Token::Symbol("(") => depth += 1,
+                Token::Symbol(")") if depth == 1 => return,
+                Token::Symbol(")") => depth -= 1,
+                _ => (),
+            }
+        }
+    }
+
+    /// `__attribute__((...))` lists, of which `packed` and `aligned` are understood
+    fn attributes(&mut self) -> Result<Attributes> {
+        let mut attributes = Attributes::default();
+        while self
+            .accept_word(&["__attribute__", "__attribute"])
+            .is_some()
+        {
+            self.expect("(")?;
+            self.expect("(")?;
+            while !self.accept(")") {
+                let name = self.name()?;
+                match name.trim_matches('_') {
+                    "packed" => attributes.packed = true,
+                    "aligned" => {
+                        let align = if self.accept("(") {
+                            let align = self.expression()?;
+                            self.expect(")")?;
+                            usize::try_from(align)
+                                .ok()
+                                .filter(|align| align.is_power_of_two())
+                                .ok_or_else(|| self.error("alignments must be powers of two"))?
+                        } else {
+                            MAX_ALIGNMENT
+                        };
+                        attributes.aligned = attributes.aligned.max(Some(align));
+                    }
+                    _ => return Err(self.error(&format!("unsupported attribute {}", name))),
+                }
+                if !self.accept(",") {
+                    self.expect(")")?;
+                    break;
+                }
+            }
+            self.expect(")")?;
+        }
+        Ok(attributes)
+    }
+
+    /// `enum TAG`, or an enum definition
+    fn enumeration(&mut self) -> Result<Base> {
+        self.position += 1;
+        let tag = match self.peek() {
+            Some(Token::Name(_)) => Some(self.name()?),
+            _ => None,
+        };
+        let key = tag.as_ref().map(|tag| format!("enum {}", tag));
+        if !self.accept("{") {
+            let key = key.ok_or_else(|| self.unexpected("a name or '{'"))?;
+            return Ok(match self.tags.get(&key) {
+                Some(layout) => Base::Complete(layout.clone()),
+                None => Base::Incomplete(key),
+            });
+        }
+
+        let mut variants = Vec::new();
+        let mut value = 0;
+        while !self.accept("}") {
+            let variant = self.name()?;
+            if self.accept("=") {
+                value = self.expression()?;
+            }
+            if i64::try_from(value).is_err() && u64::try_from(value).is_err() {
+                return Err(self.error(&format!(
+                    "{} = {} doesn't fit in a 64-bit enum",
+                    variant, value
+                )));
+            }
+            self.constants.insert(variant.clone(), value);
+            variants.push((variant, value));
+            value += 1;
+            if !self.accept(",") {
+                self.expect("}")?;
+                break;
+            }
+        }
+
+        // like GCC, unsigned int unless a value is negative or needs more than 32 bits
+        let min = variants.iter().map(|&(_, value)| value).min().unwrap_or(0);
+        let max = variants.iter().map(|&(_, value)| value).max().unwrap_or(0);
+        let (size, signed) = if min >= 0 && u32::try_from(max).is_ok() {
+            (4, false)
+        } else if i32::try_from(min).is_ok() && i32::try_from(max).is_ok() {
+            (4, true)
+        } else if min >= 0 {
+            (8, false)
+        } else if i64::try_from(max).is_ok() {
+            (8, true)
+        } else {
+            return Err(self.error(&format!(
+                "enum values from {} to {} don't fit in a 64-bit enum",
+                min, max
+            )));
+        };
+
+        let name = match tag {
+            Some(tag) => tag,
+            None => self.anonymous(),
+        };
+        self.template.enums.push(EnumDef {
+            name: name.clone(),
+            base: Integer {
+                size,
+                signed,
+                endian: None,
+            },
+            variants,
+        });
+        let layout = Layout {
+            kind: Type::Named(name),
+            ..Layout::integer(size, signed, None)
+        };
+        if let Some(key) = key {
+            self.tags.insert(key, layout.clone());
+        }
+        Ok(Base::Complete(layout))
+    }
+
+    /// A constant expression of numbers, enumerators and `sizeof`
+    fn expression(&mut self) -> Result<i128> {
+        self.binary(0)
+    }
+
+    /// Operators binding at least as tightly as `precedence`
+    fn binary(&mut self, precedence: u8) -> Result<i128> {
+        let mut left = self.unary()?;
+        while let Some(&Token::Symbol(symbol)) = self.peek() {
+            let (operator, binding) =
+                match OPERATORS.iter().find(|&&(operator, _)| operator == symbol) {
+                    Some(&(operator, binding)) if binding >= precedence => (operator, binding),
+                    _ => break,
+                };
+            self.position += 1;
+            let right = self.binary(binding + 1)?;
+            let shift = u32::try_from(right).ok();
+            left = match operator {
+                "|" => Some(left | right),
+                "^" => Some(left ^ right),
+                "&" => Some(left & right),
+                "<<" => shift.and_then(|shift| left.checked_shl(shift)),
+                ">>" => shift.and_then(|shift| left.checked_shr(shift)),
+                "+" => left.checked_add(right),
+                "-" => left.checked_sub(right),
+                "*" => left.checked_mul(right),
+                "/" => left.checked_div(right),
+                _ => left.checked_rem(right),
+            }
+            .ok_or_else(|| self.error("constant expression overflows or divides by zero"))?;
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i128> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(i128::from(n)),
+            Some(Token::Symbol("-")) => Ok(-self.unary()?),
+            Some(Token::Symbol("+")) => self.unary(),
+            Some(Token::Symbol("~")) => Ok(!self.unary()?),
+            Some(Token::Symbol("(")) => {
+                let value = self.expression()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            Some(Token::Name(ref name)) if name == "sizeof" => {
+                self.expect("(")?;
+                let base = self.specifier()?;
+                let mut layout = match base {
+                    Base::Complete(layout) => Some(layout),
+                    Base::Incomplete(_) => None,
+                };
+                while self.accept("*") {
+                    layout = Some(Layout::pointer());
+                }
+                self.expect(")")?;
+                match layout {
+                    Some(layout) => Ok(layout.size as i128),
+                    None => Err(self.error("sizeof an incomplete type")),
+                }
+            }
+            Some(Token::Name(name)) => match self.constants.get(&name) {
+                Some(&value) => Ok(value),
+                None => {
+                    self.position -= 1;
+                    Err(self.error(&format!("unknown constant {}", name)))
+                }
+            },
+            _ => {
+                self.position -= 1;
+                Err(self.unexpected("a constant expression"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    /// Names and ranges of the fields laid over `size` zeroes by the struct `name` in `source`
+    fn layout(source: &str, name: &str, size: usize) -> Vec<(String, Range<usize>)> {
+        let applied = parse(source)
+            .unwrap()
+            .template(name)
+            .unwrap()
+            .apply(&vec![0; size], 0);
+        assert_eq!(applied.error, None);
+        applied
+            .fields
+            .into_iter()
+            .map(|field| (field.name, field.range))
+            .collect()
+    }
+
+    fn fields(fields: &[(&str, Range<usize>)]) -> Vec<(String, Range<usize>)> {
+        fields
+            .iter()
+            .map(|(name, range)| (name.to_string(), range.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn fields_are_aligned_naturally_unless_packed() {
+        let source = "
+            struct natural { uint8_t a; uint32_t b; uint16_t c; };
+            struct __attribute__((packed)) tight { uint8_t a; uint32_t b; uint16_t c; };
+            #pragma pack(push, 2)
+            struct two { uint8_t a; uint32_t b; uint16_t c; };
+            #pragma pack(pop)
+        ";
+
+        assert_eq!(
+            layout(source, "natural", 12),
+            fields(&[
+                ("natural", 0..12),
+                ("a", 0..1),
+                ("(padding)", 1..4),
+                ("b", 4..8),
+                ("c", 8..10),
+                ("(padding)", 10..12),
+            ])
+        );
+        assert_eq!(
+            layout(source, "tight", 7),
+            fields(&[("tight", 0..7), ("a", 0..1), ("b", 1..5), ("c", 5..7)])
+        );
+        assert_eq!(
+            layout(source, "two", 8),
+            fields(&[
+                ("two", 0..8),
+                ("a", 0..1),
+                ("(padding)", 1..2),
+                ("b", 2..6),
+                ("c", 6..8),
+            ])
+        );
+    }
+
+    #[test]
+    fn it_reads_typedefs_unions_enums_and_constants() {
+        let source = "
+            #include <stdint.h>
+            #define NAME_LEN (4 * 2) /* bytes */
+            enum kind { KIND_A = 1, KIND_B };
+            typedef union { uint32_t word; unsigned char bytes[4]; } value_t;
+            typedef struct entry entry_t;
+            typedef struct {
+                enum kind kind;
+                char name[NAME_LEN];
+                value_t value;
+                entry_t *next;
+                double scale;
+            } record;
+            int record_count(const record *r);
+        ";
+        let header = parse(source).unwrap();
+        assert!(header.template("entry_t").is_none());
+
+        let mut data = vec![0; 32];
+        data[..4].copy_from_slice(&2u32.to_le_bytes());
+        data[4..8].copy_from_slice(b"disk");
+        data[24..].copy_from_slice(&1.5f64.to_le_bytes());
+        let applied = header.template("record").unwrap().apply(&data, 0);
+
+        assert_eq!(applied.error, None);
+        let shown: Vec<(&str, Range<usize>, &str)> = applied
+            .fields
+            .iter()
+            .map(|field| {
+                (
+                    field.name.as_str(),
+                    field.range.clone(),
+                    field.value.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            shown,
+            vec![
+                ("record", 0..32, ""),
+                ("kind", 0..4, "KIND_B (2)"),
+                ("name", 4..12, "\"disk....\""),
+                ("value", 12..16, ""),
+                ("word", 12..16, "0"),
+                ("bytes", 12..16, "00 00 00 00"),
+                ("next", 16..24, "0"),
+                ("scale", 24..32, "1.5"),
+            ]
+        );
+    }
+
+    #[test]
+    fn enums_are_sized_by_their_values() {
+        let source = "
+            enum status { E_FAIL = -1, OK = 0 };
+            enum big { SMALL, BIG = 0x100000000 };
+            struct result { enum status status; uint8_t flag; enum big big; };
+        ";
+        let mut data = vec![0; 16];
+        data[..4].copy_from_slice(&(-1i32).to_le_bytes());
+        data[8..].copy_from_slice(&0x1_0000_0000u64.to_le_bytes());
+        let applied = parse(source)
+            .unwrap()
+            .template("result")
+            .unwrap()
+            .apply(&data, 0);
+
+        assert_eq!(applied.error, None);
+        let shown: Vec<(&str, Range<usize>, &str)> = applied
+            .fields
+            .iter()
+            .filter(|field| field.name != "(padding)")
+            .map(|field| {
+                (
+                    field.name.as_str(),
+                    field.range.clone(),
+                    field.value.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            shown,
+            vec![
+                ("result", 0..16, ""),
+                ("status", 0..4, "E_FAIL (-1)"),
+                ("flag", 4..5, "0"),
+                ("big", 8..16, "BIG (4294967296)"),
+            ]
+        );
+        assert!(parse("enum e { A = -1, B = 0xffffffffffffffff };").is_err());
+    }
+
+    #[test]
+    fn pack_caps_aligned_members() {
+        let source = "
+            #pragma pack(2)
+            struct capped { char a; long b __attribute__((aligned(8))); };
+            #pragma pack()
+        ";
+        assert_eq!(
+            layout(source, "capped", 10),
+            fields(&[
+                ("capped", 0..10),
+                ("a", 0..1),
+                ("(padding)", 1..2),
+                ("b", 2..10),
+            ])
+        );
+    }
+
+    #[test]
+    fn typedefs_take_attributes() {
+        let source = "
+            typedef struct { char a; int b; } __attribute__((packed)) tight;
+            typedef int wide __attribute__((aligned(8)));
+            typedef long narrow __attribute__((aligned(2)));
+            typedef struct { char a; int b; } loose __attribute__((packed));
+            struct uses { char a; wide w; char b; narrow n; tight t; loose l; };
+        ";
+        assert_eq!(
+            layout(source, "uses", 40),
+            fields(&[
+                ("uses", 0..40),
+                ("a", 0..1),
+                ("(padding)", 1..8),
+                ("w", 8..12),
+                ("b", 12..13),
+                ("(padding)", 13..14),
+                ("n", 14..22),
+                ("t", 22..27),
+                ("a", 22..23),
+                ("b", 23..27),
+                ("(padding)", 27..28),
+                ("l", 28..36),
+                ("a", 28..29),
+                ("(padding)", 29..32),
+                ("b", 32..36),
+                ("(padding)", 36..40),
+            ])
+        );
+    }
+
+    #[test]
+    fn errors_give_the_line() {
+        assert_eq!(
+            parse("struct a {\n    int flag : 1;\n};").unwrap_err(),
+            Error {
+                line: 2,
+                message: "bit-field flag isn't supported".to_owned(),
+            }
+        );
+        assert_eq!(
+            parse("struct a {\n    int x;\n    widget_t w;\n};").unwrap_err(),
+            Error {
+                line: 3,
+                message: "unknown type widget_t".to_owned(),
+            }
+        );
+        assert_eq!(
+            parse("struct a {\n    struct b inner;\n};").unwrap_err(),
+            Error {
+                line: 2,
+                message: "inner has incomplete type struct b".to_owned(),
+            }
+        );
+    }
+}
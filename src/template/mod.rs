@@ -0,0 +1,700 @@
+//! Structure templates describing binary formats, applied to a buffer to label its bytes
+//!
+//! A template file holds enum and struct definitions, the last struct is applied to the data.
+//! For example:
+//!
+//! ```text
+//! endian little;
+//!
+//! enum Kind : u8 { Data = 1, Index = 2 }
+//!
+//! struct Entry {
+//!     id: u32be;
+//!     name: char[8];
+//! }
+//!
+//! struct Header {
+//!     magic: u8[4];
+//!     kind: Kind;
+//!     count: u16;
+//!     entries: Entry[count];
+//!     if kind == Index {
+//!         root: u32;
+//!     }
+//! }
+//! ```
+//!
+//! Fields are integers (`u8` to `u64` and `i8` to `i64`, with an optional `le` or `be` suffix
+//! overriding the template's byte order), `f32` and `f64`, `char`, enums, structs or unions.
+//! Arrays are sized by a number or an earlier field, conditions compare an earlier field with a
+//! number or enum variant. Templates can also be read from C headers, see [`c`].
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::ops::Range;
+
+pub mod c;
+pub mod panel;
+mod parser;
+
+pub use self::parser::parse;
+
+/// Background colours given to fields in turn
+const FIELD_COLOURS: [u8; 6] = [17, 22, 52, 53, 58, 23];
+
+/// Elements of integer arrays shown in a field's value
+const MAX_ELEMENTS_SHOWN: usize = 16;
+
+/// Limit on nesting, so templates with recursive structs fail rather than overflow the stack
+const MAX_DEPTH: usize = 32;
+
+/// A problem in a template file
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl StdError for Error {}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Endian {
+    Little,
+    Big,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Integer {
+    size: usize,
+    signed: bool,
+    /// Overrides the byte order of the template when given
+    endian: Option<Endian>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Type {
+    Integer(Integer),
+    /// IEEE 754 single or double precision, `signed` is ignored
+    Float(Integer),
+    Char,
+    /// A struct or enum, looked up by name
+    Named(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Count {
+    Literal(u64),
+    /// The value of an earlier field
+    Field(String),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    Literal(u64),
+    /// An earlier field or an enum variant
+    Name(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Condition {
+    field: String,
+    comparison: Comparison,
+    operand: Operand,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct FieldDef {
+    name: String,
+    kind: Type,
+    count: Option<Count>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Member {
+    Field(FieldDef),
+    If(Condition, Vec<Member>, Vec<Member>),
+    /// Bytes skipped to align the following field
+    Padding(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct StructDef {
+    name: String,
+    members: Vec<Member>,
+    /// Members of unions all start at the start of the union
+    union: bool,
+    /// Size of structs with a fixed layout, including any trailing padding
+    size: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct EnumDef {
+    name: String,
+    base: Integer,
+    variants: Vec<(String, i128)>,
+}
+
+/// A parsed template file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    endian: Endian,
+    structs: Vec<StructDef>,
+    enums: Vec<EnumDef>,
+    /// Index of the struct laid over the data, the last one unless another is selected
+    root: Option<usize>,
+}
+
+/// A field of a template found in the data
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    /// Name of the field, with its index for array elements
+    pub name: String,
+    /// Number of structs and arrays the field is nested in
+    pub depth: usize,
+    pub range: Range<usize>,
+    /// Decoded value, empty for structs and arrays of structs
+    pub value: String,
+    /// Background colour of fields holding values, `None` for those grouping other fields
+    pub colour: Option<u8>,
+}
+
+/// Result of applying a template to some data
+#[derive(Clone, Debug, PartialEq)]
+pub struct Applied {
+    pub fields: Vec<Field>,
+    /// Why the template couldn't be applied in full, the fields before the problem are kept
+    pub error: Option<String>,
+}
+
+impl Template {
+    /// Lay `name` over the data when the template is applied, rather than the last struct
+    ///
+    /// Returns false, leaving the selection as it was, if there's no struct called `name`.
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.structs.iter().position(|def| def.name == name) {
+            Some(index) => {
+                self.root = Some(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Lay the selected struct of the template over `data` starting at `offset`
+    pub fn apply(&self, data: &[u8], offset: usize) -> Applied {
+        let mut evaluator = Evaluator {
+            template: self,
+            data,
+            fields: Vec::new(),
+            scopes: Vec::new(),
+            leaves: 0,
+        };
+        let root = match self.root {
+            Some(index) => &self.structs[index],
+            None => self
+                .structs
+                .last()
+                .expect("templates have at least one struct"),
+        };
+        let error = evaluator.structure(root, &root.name, offset, 0).err();
+        Applied {
+            fields: evaluator.fields,
+            error,
+        }
+    }
+
+    fn find_struct(&self, name: &str) -> Option<&StructDef> {
+        self.structs.iter().find(|def| def.name == name)
+    }
+
+    fn find_enum(&self, name: &str) -> Option<&EnumDef> {
+        self.enums.iter().find(|def| def.name == name)
+    }
+}
+
+/// State of a template being applied
+struct Evaluator<'a> {
+    template: &'a Template,
+    data: &'a [u8],
+    fields: Vec<Field>,
+    /// Values of the integer fields of each struct being decoded, innermost last
+    scopes: Vec<HashMap<String, i128>>,
+    /// Number of fields holding values so far, used to pick colours
+    leaves: usize,
+}
+
+type Result<T> = std::result::Result<T, String>;
+
+impl<'a> Evaluator<'a> {
+    /// Decode the struct `def` at `position`, returning the position following it
+    fn structure(
+        &mut self,
+        def: &StructDef,
+        name: &str,
+        position: usize,
+        depth: usize,
+    ) -> Result<usize> {
+        if depth > MAX_DEPTH {
+            return Err(format!("structs nested more than {} deep", MAX_DEPTH));
+        }
+        let index = self.group(name, position, depth);
+        self.scopes.push(HashMap::new());
+        let end = self.members(&def.members, position, depth + 1, def.union);
+        self.scopes.pop();
+        let end = match (end?, def.size) {
+            (_, Some(size)) => {
+                self.bytes(position, size, name)?;
+                position + size
+            }
+            (end, None) => end,
+        };
+        self.fields[index].range.end = end;
+        Ok(end)
+    }
+
+    /// Decode `members` from `position`, returning where the last one ends
+    ///
+    /// Members of a `union` all start at `position`, it ends where the longest one does.
+    fn members(
+        &mut self,
+        members: &[Member],
+        position: usize,
+        depth: usize,
+        union: bool,
+    ) -> Result<usize> {
+        let mut next = position;
+        let mut end = position;
+        for member in members {
+            let start = if union { position } else { next };
+            next = match member {
+                Member::Field(field) => self.field(field, start, depth)?,
+                Member::If(condition, then, otherwise) => {
+                    if self.test(condition)? {
+                        self.members(then, start, depth, union)?
+                    } else {
+                        self.members(otherwise, start, depth, union)?
+                    }
+                }
+                Member::Padding(size) => {
+                    self.bytes(start, *size, "padding")?;
+                    self.fields.push(Field {
+                        name: "(padding)".to_owned(),
+                        depth,
+                        range: start..start + size,
+                        value: format!("{} bytes", size),
+                        colour: None,
+                    });
+                    start + size
+                }
+            };
+            end = end.max(next);
+        }
+        Ok(end)
+    }
+
+    fn field(&mut self, field: &FieldDef, position: usize, depth: usize) -> Result<usize> {
+        let count = match &field.count {
+            None => None,
+            Some(Count::Literal(n)) => Some(*n),
+            Some(Count::Field(name)) => {
+                let n = self.lookup(name)?;
+                if n < 0 {
+                    return Err(format!(
+                        "{} is negative, it can't size {}",
+                        name, field.name
+                    ));
+                }
+                Some(n as u64)
+            }
+        };
+        // every element takes at least a byte, so this also stops huge counts being looped over
+        if let Some(count) = count {
+            if count > self.data.len().saturating_sub(position) as u64 {
+                return Err(format!(
+                    "{} at {:#x} runs past the end of the data, it has {} elements",
+                    field.name, position, count
+                ));
+            }
+        }
+
+        match (&field.kind, count) {
+            (Type::Integer(integer), None) => {
+                let value = self.integer(*integer, position, &field.name)?;
+                self.define(&field.name, value);
+                self.leaf(
+                    &field.name,
+                    position,
+                    integer.size,
+                    value.to_string(),
+                    depth,
+                );
+                Ok(position + integer.size)
+            }
+            (Type::Integer(integer), Some(count)) => {
+                let size = integer.size * count as usize;
+                self.bytes(position, size, &field.name)?;
+                let values = (0..count as usize)
+                    .map(|i| self.integer(*integer, position + i * integer.size, &field.name))
+                    .collect::<Result<Vec<i128>>>()?;
+                let value = if integer.size == 1 && !integer.signed {
+                    list(&values, |v| format!("{:02X}", v), " ")
+                } else {
+                    list(&values, |v| v.to_string(), ", ")
+                };
+                self.leaf(&field.name, position, size, value, depth);
+                Ok(position + size)
+            }
+            (Type::Float(float), count) => {
+                let size = float.size * count.unwrap_or(1) as usize;
+                self.bytes(position, size, &field.name)?;
+                let raw = Integer {
+                    signed: false,
+                    ..*float
+                };
+                let values = (0..count.unwrap_or(1) as usize)
+                    .map(|i| self.integer(raw, position + i * float.size, &field.name))
+                    .collect::<Result<Vec<i128>>>()?;
+                let value = list(&values, |bits| float_text(bits, float.size), ", ");
+                self.leaf(&field.name, position, size, value, depth);
+                Ok(position + size)
+            }
+            (Type::Char, count) => {
+                let size = count.unwrap_or(1) as usize;
+                let bytes = self.bytes(position, size, &field.name)?;
+                let text: String = bytes.iter().map(|&b| char_of(b)).collect();
+                let value = if count.is_some() {
+                    format!("\"{}\"", text)
+                } else {
+                    format!("'{}'", text)
+                };
+                self.leaf(&field.name, position, size, value, depth);
+                Ok(position + size)
+            }
+            (Type::Named(type_name), count) => {
+                if let Some(def) = self.template.find_enum(type_name) {
+                    let base = def.base;
+                    let count = count.unwrap_or(1) as usize;
+                    let values = (0..count)
+                        .map(|i| self.integer(base, position + i * base.size, &field.name))
+                        .collect::<Result<Vec<i128>>>()?;
+                    if field.count.is_none() {
+                        self.define(&field.name, values[0]);
+                    }
+                    let value = list(&values, |v| variant_name(def, v), ", ");
+                    self.leaf(&field.name, position, base.size * count, value, depth);
+                    return Ok(position + base.size * count);
+                }
+
+                let def = self
+                    .template
+                    .find_struct(type_name)
+                    .expect("types are checked when parsing");
+                match count {
+                    None => self.structure(def, &field.name, position, depth),
+                    Some(count) => {
+                        let label = format!("{}[{}]", field.name, count);
+                        let index = self.group(&label, position, depth);
+                        let mut end = position;
+                        for i in 0..count {
+                            let name = format!("{}[{}]", field.name, i);
+                            end = self.structure(def, &name, end, depth + 1)?;
+                        }
+                        self.fields[index].range.end = end;
+                        Ok(end)
+                    }
+                }
+            }
+        }
+    }
+
+    fn test(&self, condition: &Condition) -> Result<bool> {
+        let left = self.lookup(&condition.field)?;
+        let right = match &condition.operand {
+            Operand::Literal(n) => i128::from(*n),
+            Operand::Name(name) => match self.lookup(name) {
+                Ok(value) => value,
+                Err(error) => self.variant(name).ok_or(error)?,
+            },
+        };
+        Ok(match condition.comparison {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        })
+    }
+
+    /// Value of the integer field `name` in the innermost struct defining it
+    fn lookup(&self, name: &str) -> Result<i128> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied()
+            .ok_or_else(|| format!("no field named {} before its use", name))
+    }
+
+    /// Value of the enum variant `name`, from whichever enum defines it
+    fn variant(&self, name: &str) -> Option<i128> {
+        self.template.enums.iter().find_map(|def| {
+            def.variants
+                .iter()
+                .find(|(variant, _)| variant == name)
+                .map(|&(_, value)| value)
+        })
+    }
+
+    fn define(&mut self, name: &str, value: i128) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_owned(), value);
+        }
+    }
+
+    fn bytes(&self, position: usize, size: usize, name: &str) -> Result<&'a [u8]> {
+        let data = self.data;
+        position
+            .checked_add(size)
+            .and_then(|end| data.get(position..end))
+            .ok_or_else(|| format!("{} at {:#x} runs past the end of the data", name, position))
+    }
+
+    fn integer(&self, integer: Integer, position: usize, name: &str) -> Result<i128> {
+        let bytes = self.bytes(position, integer.size, name)?;
+        let endian = integer.endian.unwrap_or(self.template.endian);
+        let mut value: u64 = 0;
+        for i in 0..bytes.len() {
+            let byte = match endian {
+                Endian::Little => bytes[bytes.len() - 1 - i],
+                Endian::Big => bytes[i],
+            };
+            value = value << 8 | u64::from(byte);
+        }
+
+        let bits = integer.size * 8;
+        if integer.signed && value >> (bits - 1) & 1 == 1 {
+            Ok(i128::from(value) - (1i128 << bits))
+        } else {
+            Ok(i128::from(value))
+        }
+    }
+
+    /// Add a field grouping others, returning its index so its end can be filled in
+    fn group(&mut self, name: &str, position: usize, depth: usize) -> usize {
+        self.fields.push(Field {
+            name: name.to_owned(),
+            depth,
+            range: position..position,
+            value: String::new(),
+            colour: None,
+        });
+        self.fields.len() - 1
+    }
+
+    fn leaf(&mut self, name: &str, position: usize, size: usize, value: String, depth: usize) {
+        self.fields.push(Field {
+            name: name.to_owned(),
+            depth,
+            range: position..position + size,
+            value,
+            colour: Some(FIELD_COLOURS[self.leaves % FIELD_COLOURS.len()]),
+        });
+        self.leaves += 1;
+    }
+}
+
+/// Join `values` formatted with `show`, leaving out those past the first few
+fn list<F: Fn(i128) -> String>(values: &[i128], show: F, separator: &str) -> String {
+    let mut shown: Vec<String> = values
+        .iter()
+        .take(MAX_ELEMENTS_SHOWN)
+        .map(|&value| show(value))
+        .collect();
+    if values.len() > MAX_ELEMENTS_SHOWN {
+        shown.push("…".to_owned());
+    }
+    shown.join(separator)
+}
+
+/// The float with the bit pattern `bits`, `size` bytes long
+fn float_text(bits: i128, size: usize) -> String {
+    match size {
+        4 => f32::from_bits(bits as u32).to_string(),
+        _ => f64::from_bits(bits as u64).to_string(),
+    }
+}
+
+fn variant_name(def: &EnumDef, value: i128) -> String {
+    def.variants
+        .iter()
+        .find(|&&(_, variant)| variant == value)
+        .map(|(name, _)| format!("{} ({})", name, value))
+        .unwrap_or_else(|| format!("{:#x}", value))
+}
+
+/// Printable ASCII characters are shown as they are, other bytes as a dot
+fn char_of(byte: u8) -> char {
+    if (0x20..0x7F).contains(&byte) {
+        byte as char
+    } else {
+        '.'
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "
+        endian little;
+        enum Kind : u8 { Data = 1, Index = 2 }
+        struct Entry {
+            id: u16be;
+            name: char[3];
+        }
+        struct Header {
+            magic: u8[2];
+            kind: Kind;
+            count: u8;
+            entries: Entry[count];
+            if kind == Index {
+                root: i16;
+            } else {
+                size: u32;
+            }
+        }
+    ";
+
+    fn apply(data: &[u8]) -> Applied {
+        parse(HEADER).unwrap().apply(data, 0)
+    }
+
+    fn summary(applied: &Applied) -> Vec<(String, Range<usize>, String)> {
+        applied
+            .fields
+            .iter()
+            .map(|field| (field.name.clone(), field.range.clone(), field.value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn fields_are_decoded_in_order() {
+        let data = b"HI\x02\x02\x00\x01abc\x00\x02xyz\xFE\xFF";
+        let applied = apply(data);
+
+        assert_eq!(applied.error, None);
+        let expected = vec![
+            ("Header", 0..16, ""),
+            ("magic", 0..2, "48 49"),
+            ("kind", 2..3, "Index (2)"),
+            ("count", 3..4, "2"),
+            ("entries[2]", 4..14, ""),
+            ("entries[0]", 4..9, ""),
+            ("id", 4..6, "1"),
+            ("name", 6..9, "\"abc\""),
+            ("entries[1]", 9..14, ""),
+            ("id", 9..11, "2"),
+            ("name", 11..14, "\"xyz\""),
+            ("root", 14..16, "-2"),
+        ];
+        let expected: Vec<(String, Range<usize>, String)> = expected
+            .into_iter()
+            .map(|(name, range, value)| (name.to_owned(), range, value.to_owned()))
+            .collect();
+        assert_eq!(summary(&applied), expected);
+        assert_eq!(applied.fields[4].depth, 1);
+        assert_eq!(applied.fields[6].depth, 3);
+    }
+
+    #[test]
+    fn conditions_choose_fields() {
+        let applied = apply(b"HI\x01\x00\x10\x00\x00\x00");
+
+        assert_eq!(applied.error, None);
+        let last = applied.fields.last().unwrap();
+        assert_eq!((last.name.as_str(), last.value.as_str()), ("size", "16"));
+    }
+
+    #[test]
+    fn leaves_are_coloured_in_turn() {
+        let applied = apply(b"HI\x01\x00\x10\x00\x00\x00");
+        let colours: Vec<Option<u8>> = applied.fields.iter().map(|f| f.colour).collect();
+
+        assert_eq!(
+            colours,
+            vec![None, Some(17), Some(22), Some(52), None, Some(53)]
+        );
+    }
+
+    #[test]
+    fn truncated_data_keeps_the_fields_before_it() {
+        let applied = apply(b"HI\x02\x00\x01");
+
+        assert_eq!(
+            applied.error,
+            Some("root at 0x4 runs past the end of the data".to_owned())
+        );
+        assert_eq!(applied.fields.last().unwrap().name, "entries[0]");
+    }
+
+    #[test]
+    fn oversized_arrays_are_refused() {
+        let applied = apply(b"HI\x02\xFF");
+
+        assert_eq!(
+            applied.error,
+            Some("entries at 0x4 runs past the end of the data, it has 255 elements".to_owned())
+        );
+    }
+
+    #[test]
+    fn union_members_overlap() {
+        let mut template = parse(
+            "union Value { whole: u32be; ratio: f32be; }
+             struct Pair { first: u8; }",
+        )
+        .unwrap();
+        assert!(template.select("Value"));
+        assert!(!template.select("Missing"));
+        let applied = template.apply(&[0x3F, 0xC0, 0, 0], 0);
+
+        assert_eq!(applied.error, None);
+        let expected: Vec<(String, Range<usize>, String)> = vec![
+            ("Value", 0..4, ""),
+            ("whole", 0..4, "1069547520"),
+            ("ratio", 0..4, "1.5"),
+        ]
+        .into_iter()
+        .map(|(name, range, value)| (name.to_owned(), range, value.to_owned()))
+        .collect();
+        assert_eq!(summary(&applied), expected);
+    }
+
+    #[test]
+    fn recursive_structs_fail_cleanly() {
+        let template = parse("struct Node { next: Node; }").unwrap();
+        let applied = template.apply(&[0; 8], 0);
+
+        assert_eq!(
+            applied.error,
+            Some(format!("structs nested more than {} deep", MAX_DEPTH))
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Side panel listing the fields of an applied template beside the data frame
+use std::io::Write;
+
+use termion::{color, cursor};
+
+use super::Applied;
+use crate::layout::Rect;
+use crate::overlay::fit;
+
+/// Columns taken up by the panel, including the separator on its left
+pub const WIDTH: u16 = 40;
+
+/// Draw the fields of `applied` into `area`, starting with the first field ending after `top`
+///
+/// Each row holds a swatch of the field's colour, then its name and value indented by how deeply
+/// it's nested. A problem applying the template is shown after the last field.
+pub fn render<T: Write>(out: &mut T, applied: &Applied, area: Rect, top: usize) {
+    if area.width < 3 {
+        return;
+    }
+    let width = area.width as usize - 3;
+
+    let first = applied
+        .fields
+        .iter()
+        .position(|field| field.colour.is_some() && field.range.end > top)
+        .unwrap_or(applied.fields.len());
+    let mut rows = applied.fields[first..].iter().map(|field| {
+        let text = if field.value.is_empty() {
+            field.name.clone()
+        } else {
+            format!("{}: {}", field.name, field.value)
+        };
+        (
+            field.colour,
+            format!("{}{}", "  ".repeat(field.depth), text),
+        )
+    });
+    let mut error = applied
+        .error
+        .as_ref()
+        .map(|error| (None, format!("! {}", error)));
+
+    for y in area.y..area.y + area.height {
+        let (colour, text) = rows
+            .next()
+            .or_else(|| error.take())
+            .unwrap_or((None, String::new()));
+        write!(out, "{}│", cursor::Goto(area.x, y)).unwrap();
+        match colour {
+            Some(n) => write!(
+                out,
+                "{}█{} ",
+                color::Fg(color::AnsiValue(n)),
+                color::Fg(color::Reset)
+            ),
+            None => write!(out, "  "),
+        }
+        .unwrap();
+        write!(out, "{}", fit(&text, width)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::Field;
+
+    #[test]
+    fn rows_start_at_the_first_visible_field() {
+        let field = |name: &str, depth, start, end, colour: Option<u8>| Field {
+            name: name.to_owned(),
+            depth,
+            range: start..end,
+            value: if colour.is_some() {
+                "1".to_owned()
+            } else {
+                String::new()
+            },
+            colour,
+        };
+        let applied = Applied {
+            fields: vec![
+                field("Header", 0, 0, 4, None),
+                field("a", 1, 0, 2, Some(17)),
+                field("b", 1, 2, 4, Some(22)),
+            ],
+            error: Some("oops".to_owned()),
+        };
+        let area = Rect {
+            x: 1,
+            y: 1,
+            width: 11,
+            height: 3,
+        };
+        let mut out = Vec::new();
+        render(&mut out, &applied, area, 2);
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[1;1H│\x1b[38;5;22m█\x1b[39m   b: 1  \
+             \x1b[2;1H│  ! oops  \
+             \x1b[3;1H│          "
+        );
+    }
+}
@@ -0,0 +1,477 @@
+//! Tokenizer and recursive descent parser for template files
+use super::{
+    Comparison, Condition, Count, Endian, EnumDef, Error, FieldDef, Integer, Member, Operand,
+    StructDef, Template, Type,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Name(String),
+    Number(u64),
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Name(name) => format!("'{}'", name),
+            Token::Number(n) => format!("'{}'", n),
+            Token::Symbol(symbol) => format!("'{}'", symbol),
+        }
+    }
+}
+
+/// Symbols, longest first so `==` isn't read as two `=`
+const SYMBOLS: [&str; 14] = [
+    "==", "!=", "<=", ">=", "<", ">", "=", "{", "}", "[", "]", ":", ";", ",",
+];
+
+/// Split `source` into tokens paired with their line numbers
+///
+/// Comments run from `#` or `//` to the end of the line.
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, Error> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let mut rest = line;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() || rest.starts_with('#') || rest.starts_with("//") {
+                break;
+            }
+
+            let c = rest.chars().next().unwrap();
+            if c.is_ascii_alphabetic() || c == '_' {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                tokens.push((Token::Name(rest[..end].to_owned()), number));
+                rest = &rest[end..];
+            } else if c.is_ascii_digit() {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len());
+                let text = &rest[..end];
+                let value = match text.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => text.parse(),
+                };
+                let value = value.map_err(|_| Error {
+                    line: number,
+                    message: format!("invalid number '{}'", text),
+                })?;
+                tokens.push((Token::Number(value), number));
+                rest = &rest[end..];
+            } else {
+                let symbol = SYMBOLS
+                    .iter()
+                    .find(|symbol| rest.starts_with(*symbol))
+                    .ok_or_else(|| Error {
+                        line: number,
+                        message: format!("unexpected character '{}'", c),
+                    })?;
+                tokens.push((Token::Symbol(symbol), number));
+                rest = &rest[symbol.len()..];
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parse the template in `source`
+pub fn parse(source: &str) -> Result<Template, Error> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    };
+    let mut template = Template {
+        endian: Endian::Little,
+        structs: Vec::new(),
+        enums: Vec::new(),
+        root: None,
+    };
+    // named types are checked once everything is defined, so structs can be used before them
+    let mut named = Vec::new();
+
+    while let Some(token) = parser.next() {
+        match token {
+            Token::Name(ref keyword) if keyword == "endian" => {
+                template.endian = match parser.name()?.as_str() {
+                    "little" => Endian::Little,
+                    "big" => Endian::Big,
+                    _ => return Err(parser.error("expected 'little' or 'big'")),
+                };
+                parser.expect(";")?;
+            }
+            Token::Name(ref keyword) if keyword == "enum" => {
+                let def = parser.enumeration()?;
+                if template.find_enum(&def.name).is_some() {
+                    return Err(parser.error(&format!("{} is already defined", def.name)));
+                }
+                template.enums.push(def);
+            }
+            Token::Name(ref keyword) if keyword == "struct" || keyword == "union" => {
+                let union = keyword == "union";
+                let def = parser.structure(union, &mut named)?;
+                if template.find_struct(&def.name).is_some() {
+                    return Err(parser.error(&format!("{} is already defined", def.name)));
+                }
+                template.structs.push(def);
+            }
+            token => {
+                return Err(parser.error(&format!(
+                    "expected 'endian', 'enum', 'struct' or 'union', found {}",
+                    token.describe()
+                )))
+            }
+        }
+    }
+
+    for (name, line) in named {
+        if template.find_struct(&name).is_none() && template.find_enum(&name).is_none() {
+            return Err(Error {
+                line,
+                message: format!("unknown type {}", name),
+            });
+        }
+    }
+    if template.structs.is_empty() {
+        return Err(Error {
+            line: parser.line(),
+            message: "no structs defined".to_owned(),
+        });
+    }
+    Ok(template)
+}
+
+/// Read integer types such as `u8`, `i32` and `u16be`
+fn integer_type(name: &str) -> Option<Integer> {
+    let (name, endian) = split_endian(name);
+    let (signed, bits) = match name.split_at(1.min(name.len())) {
+        ("u", bits) => (false, bits),
+        ("i", bits) => (true, bits),
+        _ => return None,
+    };
+    let size = match bits {
+        "8" => 1,
+        "16" => 2,
+        "32" => 4,
+        "64" => 8,
+        _ => return None,
+    };
+    Some(Integer {
+        size,
+        signed,
+        endian,
+    })
+}
+
+/// The float type called `name`, like `f32` or `f64be`
+fn float_type(name: &str) -> Option<Integer> {
+    let (name, endian) = split_endian(name);
+    let size = match name {
+        "f32" => 4,
+        "f64" => 8,
+        _ => return None,
+    };
+    Some(Integer {
+        size,
+        signed: true,
+        endian,
+    })
+}
+
+/// Split an `le` or `be` suffix off a type name
+fn split_endian(name: &str) -> (&str, Option<Endian>) {
+    if let Some(name) = name.strip_suffix("le") {
+        (name, Some(Endian::Little))
+    } else if let Some(name) = name.strip_suffix("be") {
+        (name, Some(Endian::Big))
+    } else {
+        (name, None)
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .map(|(token, _)| token.clone());
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    /// Line of the last token read
+    fn line(&self) -> usize {
+        let index = self.position.saturating_sub(1);
+        self.tokens.get(index).map_or(1, |&(_, line)| line)
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error {
+            line: self.line(),
+            message: message.to_owned(),
+        }
+    }
+
+    fn unexpected(&mut self, expected: &str) -> Error {
+        match self.next() {
+            Some(token) => self.error(&format!(
+                "expected {}, found {}",
+                expected,
+                token.describe()
+            )),
+            None => self.error(&format!("expected {}, found the end of the file", expected)),
+        }
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), Error> {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", symbol)))
+        }
+    }
+
+    /// Consume `symbol` if it's next
+    fn accept(&mut self, symbol: &'static str) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(symbol));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn name(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::Name(name)) => {
+                let name = name.clone();
+                self.position += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    fn number(&mut self) -> Result<u64, Error> {
+        match self.peek() {
+            Some(&Token::Number(n)) => {
+                self.position += 1;
+                Ok(n)
+            }
+            _ => Err(self.unexpected("a number")),
+        }
+    }
+
+    /// `enum NAME : INTEGER { VARIANT = NUMBER, ... }`, after the keyword
+    fn enumeration(&mut self) -> Result<EnumDef, Error> {
+        let name = self.name()?;
+        self.expect(":")?;
+        let base = self.name()?;
+        let base = integer_type(&base)
+            .ok_or_else(|| self.error(&format!("{} isn't an integer type", base)))?;
+        self.expect("{")?;
+
+        let mut variants = Vec::new();
+        while !self.accept("}") {
+            let variant = self.name()?;
+            self.expect("=")?;
+            let value = self.number()?;
+            variants.push((variant, i128::from(value)));
+            if !self.accept(",") {
+                self.expect("}")?;
+                break;
+            }
+        }
+        Ok(EnumDef {
+            name,
+            base,
+            variants,
+        })
+    }
+
+    /// `struct NAME { MEMBER... }` or `union NAME { MEMBER... }`, after the keyword
+    fn structure(
+        &mut self,
+        union: bool,
+        named: &mut Vec<(String, usize)>,
+    ) -> Result<StructDef, Error> {
+        let name = self.name()?;
+        self.expect("{")?;
+        let members = self.members(named)?;
+        Ok(StructDef {
+            name,
+            members,
+            union,
+            size: None,
+        })
+    }
+
+    /// Members up to and including the closing brace of a block
+    fn members(&mut self, named: &mut Vec<(String, usize)>) -> Result<Vec<Member>, Error> {
+        let mut members = Vec::new();
+        while !self.accept("}") {
+            if self.peek() == Some(&Token::Name("if".to_owned())) {
+                self.position += 1;
+                members.push(self.conditional(named)?);
+            } else {
+                members.push(Member::Field(self.field(named)?));
+            }
+        }
+        Ok(members)
+    }
+
+    /// `if FIELD OP OPERAND { MEMBER... } [else { MEMBER... }]`, after the keyword
+    fn conditional(&mut self, named: &mut Vec<(String, usize)>) -> Result<Member, Error> {
+        let field = self.name()?;
+        let comparison = match self.next() {
+            Some(Token::Symbol("==")) => Comparison::Equal,
+            Some(Token::Symbol("!=")) => Comparison::NotEqual,
+            Some(Token::Symbol("<")) => Comparison::Less,
+            Some(Token::Symbol("<=")) => Comparison::LessOrEqual,
+            Some(Token::Symbol(">")) => Comparison::Greater,
+            Some(Token::Symbol(">=")) => Comparison::GreaterOrEqual,
+            _ => return Err(self.error("expected a comparison")),
+        };
+        let operand = match self.next() {
+            Some(Token::Number(n)) => Operand::Literal(n),
+            Some(Token::Name(name)) => Operand::Name(name),
+            _ => return Err(self.error("expected a number or name to compare with")),
+        };
+
+        self.expect("{")?;
+        let then = self.members(named)?;
+        let otherwise = if self.peek() == Some(&Token::Name("else".to_owned())) {
+            self.position += 1;
+            self.expect("{")?;
+            self.members(named)?
+        } else {
+            Vec::new()
+        };
+        Ok(Member::If(
+            Condition {
+                field,
+                comparison,
+                operand,
+            },
+            then,
+            otherwise,
+        ))
+    }
+
+    /// `NAME : TYPE [ '[' COUNT ']' ] ;`
+    fn field(&mut self, named: &mut Vec<(String, usize)>) -> Result<FieldDef, Error> {
+        let name = self.name()?;
+        self.expect(":")?;
+        let type_name = self.name()?;
+        let kind = match (integer_type(&type_name), float_type(&type_name)) {
+            (Some(integer), _) => Type::Integer(integer),
+            (None, Some(float)) => Type::Float(float),
+            (None, None) if type_name == "char" => Type::Char,
+            (None, None) => {
+                named.push((type_name.clone(), self.line()));
+                Type::Named(type_name)
+            }
+        };
+
+        let count = if self.accept("[") {
+            let count = match self.next() {
+                Some(Token::Number(n)) => Count::Literal(n),
+                Some(Token::Name(field)) => Count::Field(field),
+                _ => return Err(self.error("expected a number or field as the array length")),
+            };
+            self.expect("]")?;
+            Some(count)
+        } else {
+            None
+        };
+        self.expect(";")?;
+
+        Ok(FieldDef { name, kind, count })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_tokenizes_names_numbers_and_symbols() {
+        let tokens: Vec<Token> = tokenize("a: u8[0x10]; # note\nif a >= 2 // note")
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Name("a".to_owned()),
+                Token::Symbol(":"),
+                Token::Name("u8".to_owned()),
+                Token::Symbol("["),
+                Token::Number(16),
+                Token::Symbol("]"),
+                Token::Symbol(";"),
+                Token::Name("if".to_owned()),
+                Token::Name("a".to_owned()),
+                Token::Symbol(">="),
+                Token::Number(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reads_integer_types() {
+        assert_eq!(
+            integer_type("u16be"),
+            Some(Integer {
+                size: 2,
+                signed: false,
+                endian: Some(Endian::Big)
+            })
+        );
+        assert_eq!(
+            integer_type("i64").map(|i| (i.size, i.signed)),
+            Some((8, true))
+        );
+        assert_eq!(integer_type("u24"), None);
+        assert_eq!(integer_type("be"), None);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = |source| parse(source).unwrap_err().to_string();
+
+        assert_eq!(
+            error("struct A {\n  x u8;\n}"),
+            "line 2: expected ':', found 'u8'"
+        );
+        assert_eq!(
+            error("struct A {\n  x: Missing;\n}"),
+            "line 2: unknown type Missing"
+        );
+        assert_eq!(error("enum E : u8 { A = 1 }"), "line 1: no structs defined");
+        assert_eq!(
+            error("struct A {\n  x: u8;\n"),
+            "line 2: expected a name, found the end of the file"
+        );
+        assert_eq!(
+            error("struct A { x: u8; }\n$"),
+            "line 2: unexpected character '$'"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Bitwise transforms applied to ranges of bytes, for undoing simple obfuscation
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transform {
+    /// Exclusive or with a key, repeated through the range
+    Xor(Vec<u8>),
+    /// Wrapping addition to each byte
+    Add(u8),
+    /// Rotate the bits of each byte left
+    Rotate(u32),
+    Not,
+    /// Reverse the order of the bytes in each group of this many
+    ByteSwap(usize),
+}
+
+impl Transform {
+    /// Transform `bytes` in place
+    ///
+    /// A byte swap leaves any bytes after the last whole group as they are.
+    pub fn apply(&self, bytes: &mut [u8]) {
+        match self {
+            Transform::Xor(key) => {
+                for (byte, k) in bytes.iter_mut().zip(key.iter().cycle()) {
+                    *byte ^= k;
+                }
+            }
+            Transform::Add(n) => bytes
+                .iter_mut()
+                .for_each(|byte| *byte = byte.wrapping_add(*n)),
+            Transform::Rotate(n) => bytes
+                .iter_mut()
+                .for_each(|byte| *byte = byte.rotate_left(*n)),
+            Transform::Not => bytes.iter_mut().for_each(|byte| *byte = !*byte),
+            Transform::ByteSwap(width) => bytes
+                .chunks_exact_mut(*width)
+                .for_each(|group| group.reverse()),
+        }
+    }
+
+    /// The name of the command applying the transform
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transform::Xor(_) => "xor",
+            Transform::Add(_) => "add",
+            Transform::Rotate(_) => "rol",
+            Transform::Not => "not",
+            Transform::ByteSwap(2) => "bswap16",
+            Transform::ByteSwap(4) => "bswap32",
+            Transform::ByteSwap(_) => "bswap64",
+        }
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Transform::Xor(key) => {
+                write!(f, "xor ")?;
+                key.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+            }
+            Transform::Add(n) => write!(f, "add {}", n),
+            Transform::Rotate(n) => write!(f, "rol {}", n),
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(transform: Transform, bytes: &[u8]) -> Vec<u8> {
+        let mut bytes = bytes.to_vec();
+        transform.apply(&mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn xor_keys_cycle_through_the_range() {
+        assert_eq!(
+            applied(
+                Transform::Xor(vec![0xFF, 0x0F]),
+                &[0x00, 0x00, 0xF0, 0xF0, 0x12]
+            ),
+            [0xFF, 0x0F, 0x0F, 0xFF, 0xED]
+        );
+    }
+
+    #[test]
+    fn bytes_are_added_rotated_and_inverted() {
+        assert_eq!(applied(Transform::Add(3), &[0x01, 0xFE]), [0x04, 0x01]);
+        assert_eq!(applied(Transform::Rotate(1), &[0x81, 0x40]), [0x03, 0x80]);
+        assert_eq!(applied(Transform::Not, &[0x00, 0x5A]), [0xFF, 0xA5]);
+    }
+
+    #[test]
+    fn byte_swaps_leave_a_partial_group() {
+        assert_eq!(
+            applied(Transform::ByteSwap(2), &[1, 2, 3, 4, 5]),
+            [2, 1, 4, 3, 5]
+        );
+        assert_eq!(
+            applied(Transform::ByteSwap(8), &[1, 2, 3, 4, 5, 6, 7, 8]),
+            [8, 7, 6, 5, 4, 3, 2, 1]
+        );
+        assert_eq!(Transform::ByteSwap(4).to_string(), "bswap32");
+        assert_eq!(Transform::Xor(vec![0xDE, 0xAD]).to_string(), "xor dead");
+    }
+}
@@ -0,0 +1,61 @@
+use std::env;
+use std::fs;
+use std::process::{Command, Output};
+
+/// Run `script` with `--script` against 64 zero bytes, with `files` beside it for it to source
+fn run_script(name: &str, script: &str, files: &[(&str, &str)]) -> Output {
+    let dir = env::temp_dir().join(format!("hi-headless-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    let data = dir.join("data.bin");
+    let script_path = dir.join("check.hi");
+    fs::write(&data, [0; 64]).unwrap();
+    fs::write(&script_path, script).unwrap();
+    for (file, contents) in files {
+        fs::write(dir.join(file), contents).unwrap();
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hi"))
+        .current_dir(&dir)
+        .arg("--script")
+        .arg("check.hi")
+        .arg(&data)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(dir).unwrap();
+    output
+}
+
+#[test]
+fn scripts_which_succeed_exit_zero() {
+    let output = run_script("ok", "hash crc32 0 0x10 copy\n", &[]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("(not copied: headless)"));
+}
+
+#[test]
+fn failing_commands_are_reported_and_exit_non_zero() {
+    let output = run_script(
+        "failing",
+        "goto 0x10\nhash crc32 0x1000 0x10\nsection .foo\n",
+        &[],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("line 2: 0x10 bytes from 0x1000 runs past the end of the file"));
+    assert!(stderr.contains("line 3: not an ELF file"));
+    assert!(stderr.contains("2 commands failed"));
+}
+
+#[test]
+fn failures_in_sourced_scripts_give_their_line() {
+    let output = run_script(
+        "sourced",
+        "# checks\nsource inner.hi\n",
+        &[("inner.hi", "goto 0\n\ngoto 0x100\n")],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr
+        .contains("check.hi: line 2: inner.hi: line 3: offset 0x100 is past the end of the file"));
+}